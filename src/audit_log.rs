@@ -0,0 +1,71 @@
+use std::fs::{File, OpenOptions};
+use std::io::{BufWriter, Write};
+use std::path::Path;
+
+use crate::data_types::RejectReason;
+use crate::date_time_tool::{current_timestamp, saturating_duration};
+use crate::log_error;
+
+/// Buffered lines are pushed to disk at least this often.
+const AUDIT_FLUSH_INTERVAL_NS: u64 = 1_000_000_000;
+
+/// Append-only record of rejected orders, kept apart from normal logging.
+///
+/// One line per rejection: `timestamp_ns,product_id,order_id,reason_code,reason`.
+pub struct AuditLog {
+    writer: BufWriter<File>,
+    last_flush_ns: u64,
+}
+
+impl AuditLog {
+    /// Opens `path` for appending, creating it if needed. Existing entries are kept.
+    pub fn open(path: &Path) -> std::io::Result<Self> {
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        Ok(Self {
+            writer: BufWriter::new(file),
+            last_flush_ns: current_timestamp(),
+        })
+    }
+
+    /// Appends one rejection. Write errors are logged, never raised into matching.
+    pub fn record_rejection(
+        &mut self,
+        timestamp_ns: u64,
+        product_id: u16,
+        order_id: u64,
+        reason: RejectReason,
+    ) {
+        if let Err(e) = writeln!(
+            self.writer,
+            "{},{},{},{},{}",
+            timestamp_ns,
+            product_id,
+            order_id,
+            reason as u8,
+            reason.as_str()
+        ) {
+            log_error!("Failed to write audit entry for order {}: {}", order_id, e);
+            return;
+        }
+        self.flush_if_due();
+    }
+
+    /// Flushes buffered entries once `AUDIT_FLUSH_INTERVAL_NS` has passed since the last
+    /// flush. Also called from the receive loop so a quiet engine still flushes.
+    pub fn flush_if_due(&mut self) {
+        let now = current_timestamp();
+        if saturating_duration(self.last_flush_ns, now) < AUDIT_FLUSH_INTERVAL_NS {
+            return;
+        }
+        self.last_flush_ns = now;
+        if let Err(e) = self.writer.flush() {
+            log_error!("Failed to flush audit log: {}", e);
+        }
+    }
+}
+
+impl Drop for AuditLog {
+    fn drop(&mut self) {
+        let _ = self.writer.flush();
+    }
+}
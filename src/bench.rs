@@ -0,0 +1,169 @@
+use std::sync::Mutex;
+
+use crate::data_types::{BucketLayout, ContinuousOrderBook, MatchResult, OrderExecution, OrderGenParams, ResultSender};
+use crate::date_time_tool::saturating_duration;
+use crate::high_resolution_timer::HighResolutionTimer;
+use crate::perf_stats::{self, Stats};
+use crate::test_order_book_builder::generate_orders;
+
+/// Seed used for `--bench` so runs on different machines see the same stream.
+pub const BENCH_SEED: u64 = 42;
+
+/// Keeps every execution it is handed; used where there is no network to send to.
+#[derive(Default)]
+pub struct CollectingSender {
+    pub executions: Mutex<Vec<OrderExecution>>,
+}
+
+impl ResultSender for CollectingSender {
+    fn send_result(&self, result: &MatchResult) {
+        self.executions
+            .lock()
+            .unwrap()
+            .extend_from_slice(&result.order_execution_list);
+    }
+}
+
+pub struct BenchReport {
+    pub layout: BucketLayout,
+    pub orders: usize,
+    pub executions: usize,
+    pub elapsed_ns: u64,
+    /// Latency samples dropped from the front of the run before computing `stats`.
+    pub warmup_samples: usize,
+    pub retained_samples: usize,
+    pub stats: Option<Stats>,
+}
+
+impl BenchReport {
+    pub fn orders_per_second(&self) -> u64 {
+        if self.elapsed_ns == 0 {
+            return 0;
+        }
+        (self.orders as u128 * 1_000_000_000 / self.elapsed_ns as u128) as u64
+    }
+}
+
+/// Feeds `order_count` generated orders straight into a fresh book of `layout` and
+/// measures the per-order match latency. No sockets are opened.
+///
+/// The first `warmup` latencies are left out of the percentiles so they reflect the
+/// steady state rather than allocation and cache warm-up.
+pub fn run_bench(order_count: usize, product_id: u16, warmup: usize, layout: BucketLayout) -> BenchReport {
+    run_collecting(order_count, product_id, warmup, layout).0
+}
+
+/// Runs the same generated stream through a book of each layout, AoS first.
+pub fn compare_layouts(order_count: usize, product_id: u16, warmup: usize) -> [BenchReport; 2] {
+    [BucketLayout::Aos, BucketLayout::Soa].map(|layout| run_bench(order_count, product_id, warmup, layout))
+}
+
+/// `run_bench`, also handing back every execution in the order it was produced.
+fn run_collecting(
+    order_count: usize,
+    product_id: u16,
+    warmup: usize,
+    layout: BucketLayout,
+) -> (BenchReport, Vec<OrderExecution>) {
+    let params = OrderGenParams {
+        product_id,
+        price_spread: 5_000,
+        ..OrderGenParams::default()
+    };
+    let orders = generate_orders(order_count, BENCH_SEED, &params);
+
+    let base_price = params.mid_price - params.price_spread as i64;
+    let levels = (params.price_spread * 2 + 1) as usize;
+    let mut book = ContinuousOrderBook::new(1, base_price, levels, 1024);
+    book.set_bucket_layout(layout);
+    let sender = CollectingSender::default();
+    let mut latencies = Vec::with_capacity(order_count);
+
+    let timer = HighResolutionTimer::start();
+    let start = timer.ns() as u64;
+    for order in orders {
+        book.match_order(order);
+        latencies.push(book.match_result.total_time() as u32);
+        if !book.match_result.order_execution_list.is_empty() {
+            sender.send_result(&book.match_result);
+        }
+    }
+    let elapsed_ns = saturating_duration(start, timer.ns() as u64);
+
+    let executions = sender.executions.into_inner().unwrap();
+    let warmup_samples = warmup.min(latencies.len());
+    let report = BenchReport {
+        layout,
+        orders: order_count,
+        executions: executions.len(),
+        elapsed_ns,
+        warmup_samples,
+        retained_samples: latencies.len() - warmup_samples,
+        stats: perf_stats::calculate_perf_after_warmup(latencies, warmup_samples),
+    };
+    (report, executions)
+}
+
+pub fn print_bench_report(report: &BenchReport) {
+    println!(
+        "Bench ({:?} layout): {} orders in {}ns, {} orders per second, {} executions.\n",
+        report.layout,
+        report.orders,
+        report.elapsed_ns,
+        report.orders_per_second(),
+        report.executions
+    );
+    println!(
+        "Latency over {} samples after discarding {} warm-up samples.\n",
+        report.retained_samples, report.warmup_samples
+    );
+    match &report.stats {
+        Some(stats) => perf_stats::print_stats_table(stats),
+        None => println!("No samples recorded."),
+    }
+}
+
+/// Prints the runs of `compare_layouts` in one column each: throughput, then the
+/// latency percentiles in nanoseconds after the warm-up samples.
+pub fn print_layout_comparison(reports: &[BenchReport; 2]) {
+    let [aos, soa] = reports;
+    println!(
+        "Bench: {} orders per layout, latency over {} samples after discarding {} warm-up samples.\n",
+        aos.orders, aos.retained_samples, aos.warmup_samples
+    );
+    println!("{:<12}{:>14}{:>14}", "", format!("{:?}", aos.layout), format!("{:?}", soa.layout));
+    println!("{}", "-".repeat(40));
+    let row = |label: &str, value: &dyn Fn(&BenchReport) -> Option<u64>| {
+        let cell = |report| value(report).map_or("-".to_string(), |v| v.to_string());
+        println!("{:<12}{:>14}{:>14}", label, cell(aos), cell(soa));
+    };
+    row("orders/s", &|r| Some(r.orders_per_second()));
+    row("executions", &|r| Some(r.executions as u64));
+    row("elapsed ns", &|r| Some(r.elapsed_ns));
+    row("P50", &|r| r.stats.as_ref().map(|s| s.p50 as u64));
+    row("P90", &|r| r.stats.as_ref().map(|s| s.p90 as u64));
+    row("P99", &|r| r.stats.as_ref().map(|s| s.p99 as u64));
+    row("P999", &|r| r.stats.as_ref().map(|s| s.p999 as u64));
+    row("P100", &|r| r.stats.as_ref().map(|s| s.p100 as u64));
+    println!("{}", "-".repeat(40));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn both_layouts_produce_the_same_executions() {
+        let fills = [BucketLayout::Aos, BucketLayout::Soa].map(|layout| {
+            let (report, executions) = run_collecting(20_000, 7, 100, layout);
+            assert_eq!(report.layout, layout);
+            assert_eq!(report.executions, executions.len());
+            executions
+                .iter()
+                .map(|e| (e.buy_order_id, e.sell_order_id, e.price, e.quantity))
+                .collect::<Vec<_>>()
+        });
+        assert!(!fills[0].is_empty());
+        assert_eq!(fills[0], fills[1]);
+    }
+}
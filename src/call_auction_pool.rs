@@ -1,5 +1,7 @@
 use crate::data_types::*; 
-use std::cmp::{max, min};
+use crate::date_time_tool::{current_timestamp, saturating_duration_u32};
+use crate::number_tool::round_to_tick;
+use std::cmp::{max, min, Ordering};
 
 impl CallAuctionPool {
     /// Creates a new, empty Call Auction Pool.
@@ -21,13 +23,23 @@ impl CallAuctionPool {
 
 /// Optimized Equilibrium Price Calculation using Two-Pointer Sweep-Line.
     /// Complexity: O(N log N) due to sorting, O(N) for scanning.
-    pub fn calculate_match_price_final(&self, price_tick: u64) -> Option<(u64, u32)> {
+    ///
+    /// Candidate prices lie on the grid `price_origin + k * price_tick`; those that
+    /// execute anything are narrowed by `rules.tie_breaks` in order. When several ticks
+    /// survive every rule, the price is the middle of their range, put back on the
+    /// grid with `rules.rounding`.
+    pub fn calculate_match_price_final(
+        &self,
+        price_tick: u64,
+        price_origin: i64,
+        rules: &AuctionRules,
+    ) -> Option<(i64, u32)> {
         if self.bids.is_empty() || self.asks.is_empty() || price_tick == 0 {
             return None;
         }
 
         // 1. 收集所有原始委托价格并排序（不考虑 tick）
-        let mut raw_prices: Vec<u64> = self.bids.iter().map(|o| o.price)
+        let mut raw_prices: Vec<i64> = self.bids.iter().map(|o| o.price)
             .chain(self.asks.iter().map(|o| o.price))
             .collect();
         raw_prices.sort_unstable();
@@ -35,14 +47,14 @@ impl CallAuctionPool {
 
         // 2. 将这些价格映射到最近的合规 tick
         // 我们需要检查：每个委托价对应的当前 tick，以及它的前一个和后一个 tick
+        // div_euclid rounds towards negative infinity so negative prices align too
+        let tick = price_tick as i64;
         let mut critical_ticks = Vec::new();
         for p in raw_prices {
-            let base = (p / price_tick) * price_tick;
+            let base = price_origin + (p - price_origin).div_euclid(tick) * tick;
             critical_ticks.push(base);
-            critical_ticks.push(base + price_tick);
-            if base >= price_tick {
-                critical_ticks.push(base - price_tick);
-            }
+            critical_ticks.push(base + tick);
+            critical_ticks.push(base - tick);
         }
         critical_ticks.sort_unstable();
         critical_ticks.dedup();
@@ -55,9 +67,8 @@ impl CallAuctionPool {
         sorted_asks.sort_by(|a, b| a.price.cmp(&b.price)); // 低到高
 
         // 4. 双指针扫描逻辑
-        let mut best_price = 0u64;
-        let mut max_volume = 0u32;
-        let mut min_imbalance = u32::MAX;
+        // (price, executable volume, bid volume minus ask volume) of every tick that crosses
+        let mut candidates: Vec<(i64, u32, i64)> = Vec::new();
 
         // 初始化累计成交量
         let mut total_bid_vol: u32 = sorted_bids.iter().map(|o| o.quantity).sum();
@@ -86,56 +97,124 @@ impl CallAuctionPool {
             }
 
             let current_vol = min(total_bid_vol, total_ask_vol);
-            let imbalance = total_bid_vol.abs_diff(total_ask_vol);
-
-            if current_vol > max_volume {
-                max_volume = current_vol;
-                best_price = test_price;
-                min_imbalance = imbalance;
-            } else if current_vol == max_volume && max_volume > 0 {
-                if imbalance < min_imbalance {
-                    best_price = test_price;
-                    min_imbalance = imbalance;
+            if current_vol > 0 {
+                candidates.push((test_price, current_vol, total_bid_vol as i64 - total_ask_vol as i64));
+            }
+        }
+
+        // 5. 按规则顺序筛选候选价格
+        for rule in &rules.tie_breaks {
+            match rule {
+                AuctionTieBreak::MaxVolume => {
+                    let best = candidates.iter().map(|c| c.1).max()?;
+                    candidates.retain(|c| c.1 == best);
+                }
+                AuctionTieBreak::MinImbalance => {
+                    let best = candidates.iter().map(|c| c.2.unsigned_abs()).min()?;
+                    candidates.retain(|c| c.2.unsigned_abs() == best);
+                }
+                AuctionTieBreak::MarketPressure => {
+                    if candidates.iter().all(|c| c.2 > 0) {
+                        let highest = candidates.iter().map(|c| c.0).max()?;
+                        candidates.retain(|c| c.0 == highest);
+                    } else if candidates.iter().all(|c| c.2 < 0) {
+                        let lowest = candidates.iter().map(|c| c.0).min()?;
+                        candidates.retain(|c| c.0 == lowest);
+                    }
+                }
+                AuctionTieBreak::Reference => {
+                    if let Some(reference) = rules.reference_price {
+                        let best = candidates.iter().map(|c| c.0.abs_diff(reference)).min()?;
+                        candidates.retain(|c| c.0.abs_diff(reference) == best);
+                    }
                 }
             }
         }
+        let best_price = candidates.iter().map(|c| c.0).min()?;
+        let best_price_high = candidates.iter().map(|c| c.0).max()?;
+
+        // rounded on the doubled grid so the middle of an odd tick range stays exact
+        let price = round_to_tick(best_price + best_price_high, 2 * price_origin, 2 * price_tick, rules.rounding) / 2;
+        // the survivors may differ in volume, so measure it at the chosen price itself
+        let bid_vol: u32 = self.bids.iter().filter(|o| o.price >= price).map(|o| o.quantity).sum();
+        let ask_vol: u32 = self.asks.iter().filter(|o| o.price <= price).map(|o| o.quantity).sum();
+        let volume = min(bid_vol, ask_vol);
+        if volume > 0 { Some((price, volume)) } else { None }
+    }
 
-        if max_volume > 0 { Some((best_price, max_volume)) } else { None }
+    /// Checks the uncross at `price` against `rules.max_imbalance_pct`.
+    ///
+    /// The imbalance is the eligible quantity of the heavier side that `volume` leaves
+    /// unmatched. Returns the extension to report when it is above the allowed share of
+    /// `volume`, or `None` when the auction may cross (always without a threshold).
+    pub fn check_imbalance(&self, price: i64, volume: u32, rules: &AuctionRules) -> Option<AuctionExtended> {
+        let max_pct = rules.max_imbalance_pct?;
+        let buy_volume: u32 = self.bids.iter().filter(|o| o.price >= price).map(|o| o.quantity).sum();
+        let sell_volume: u32 = self.asks.iter().filter(|o| o.price <= price).map(|o| o.quantity).sum();
+        let imbalance = buy_volume.max(sell_volume) - volume;
+        if imbalance as u64 * 100 <= max_pct as u64 * volume as u64 {
+            return None;
+        }
+        Some(AuctionExtended {
+            price,
+            matched_volume: volume,
+            surplus_side: if buy_volume > sell_volume { ORDER_TYPE_BUY } else { ORDER_TYPE_SELL },
+            imbalance,
+        })
     }
 
-    /// Handles the actual execution of the auction, generating MatchResults.
+    /// Handles the actual execution of the auction, generating MatchResults and a
+    /// summary of the uncross.
+    ///
+    /// `start_ts` is when the auction began on the engine clock (`current_timestamp`);
+    /// fills and completion are stamped on the same monotonic clock. When the projected
+    /// imbalance exceeds `rules.max_imbalance_pct` nothing executes, the pool is left
+    /// as it was and the extension is returned instead.
     pub fn execute_auction(
         &mut self,
         price_tick: u64,
+        price_origin: i64,
         instance_tag: [u8; 16],
         product_id: u16,
-        current_ts: u64,
-    ) -> MatchResult {
+        start_ts: u64,
+        rules: &AuctionRules,
+    ) -> Result<(MatchResult, AuctionSummary), AuctionExtended> {
         let mut match_result = MatchResult {
             order_execution_list: Vec::new(),
-            start_time: current_ts,
-            end_time: current_ts,
+            start_time: start_ts,
+            end_time: start_ts,
+            receive_to_match_time: 0,
+            residual_rejected: false,
+            evicted_order: None,
         };
 
         // 1. Calculate the price and the total volume to match
-        let (match_price, mut total_volume_to_match) = match self.calculate_match_price_final(price_tick) {
+        let (match_price, mut total_volume_to_match) = match self.calculate_match_price_final(price_tick, price_origin, rules) {
             Some(res) => res,
-            None => return match_result, // Nothing to match
+            None => return Ok((match_result, AuctionSummary::default())), // Nothing to match
         };
+        if let Some(extended) = self.check_imbalance(match_price, total_volume_to_match, rules) {
+            return Err(extended);
+        }
 
         // 2. Prepare candidate orders
+        // Orders priced away from match_price stay in the pool untouched.
         // Buy Side: Orders with price >= match_price, sorted by Price desc, Time asc.
-        let mut eligible_bids: Vec<Order> = self.bids.drain(..)
-            .filter(|o| o.price >= match_price)
-            .collect();
+        let (mut eligible_bids, unmatched_bids): (Vec<Order>, Vec<Order>) = self.bids.drain(..)
+            .partition(|o| o.price >= match_price);
+        self.bids = unmatched_bids;
         eligible_bids.sort_by(|a, b| b.price.cmp(&a.price).then(a.submit_time.cmp(&b.submit_time)));
 
         // Sell Side: Orders with price <= match_price, sorted by Price asc, Time asc.
-        let mut eligible_asks: Vec<Order> = self.asks.drain(..)
-            .filter(|o| o.price <= match_price)
-            .collect();
+        let (mut eligible_asks, unmatched_asks): (Vec<Order>, Vec<Order>) = self.asks.drain(..)
+            .partition(|o| o.price <= match_price);
+        self.asks = unmatched_asks;
         eligible_asks.sort_by(|a, b| a.price.cmp(&b.price).then(a.submit_time.cmp(&b.submit_time)));
 
+        let eligible_buy_volume: u32 = eligible_bids.iter().map(|o| o.quantity).sum();
+        let eligible_sell_volume: u32 = eligible_asks.iter().map(|o| o.quantity).sum();
+        let planned_volume = total_volume_to_match;
+
         // 3. Bilateral Matching
         let mut b_idx = 0;
         let mut s_idx = 0;
@@ -155,8 +234,13 @@ impl CallAuctionPool {
                     price: match_price,
                     quantity: match_qty,
                     trade_time_network: 0, // Set by network layer
-                    internal_match_time: 0, // Latency metric
+                    // time from the auction start to the moment the fill was produced
+                    internal_match_time: saturating_duration_u32(start_ts, current_timestamp()),
                     is_mocked_result: bid.is_mocked_order() || ask.is_mocked_order(),
+                    aggressor_side: 0, // Auction uncross has no taker
+                    clock_skew_detected: false,
+                    buy_client_ref: bid.client_ref,
+                    sell_client_ref: ask.client_ref,
                 };
 
                 match_result.order_execution_list.push(execution);
@@ -176,8 +260,25 @@ impl CallAuctionPool {
         self.bids.extend(eligible_bids.into_iter().filter(|o| o.quantity > 0));
         self.asks.extend(eligible_asks.into_iter().filter(|o| o.quantity > 0));
 
-        match_result.end_time = 0; // Update with actual end timestamp if needed
-        match_result
+        match_result.end_time = current_timestamp().max(start_ts);
+
+        // the heavier side is left with what the lighter one could not absorb
+        let matched_volume = planned_volume - total_volume_to_match;
+        let surplus_side = match eligible_buy_volume.cmp(&eligible_sell_volume) {
+            Ordering::Greater => ORDER_TYPE_BUY,
+            Ordering::Less => ORDER_TYPE_SELL,
+            Ordering::Equal => 0,
+        };
+        let summary = AuctionSummary {
+            price: match_price,
+            matched_volume,
+            eligible_buy_volume,
+            eligible_sell_volume,
+            surplus_side,
+            imbalance: eligible_buy_volume.max(eligible_sell_volume) - matched_volume,
+        };
+
+        Ok((match_result, summary))
     }
 
     /// Resets the pool after the auction period ends.
@@ -1,49 +1,453 @@
 
+use std::net::{Ipv4Addr, SocketAddr};
+use std::path::PathBuf;
+
+use crate::data_types::{
+    AuctionRules, AuctionTieBreak, BookFullPolicy, BucketLayout, MockMatchPolicy, ChecksumPolicy, PriceRounding, PriorityMode, ProductSet, TapeAggregation, TestBookScenario,
+    TimestampSource, TradePriceMode, UnknownProductPolicy, WashTradePolicy, DEFAULT_COMPACT_FLOOR,
+};
+use crate::high_resolution_timer::TimerSource;
+use crate::log_tool::{LogLevel, parse_log_level};
+use crate::log_warn;
 use crate::number_tool::parse_human_readable_u32;
 
-pub fn get_config() -> Result<(String, u16, u32), String> {
+/// Runtime configuration collected from the command line and environment.
+#[derive(Debug, Clone)]
+pub struct EngineConfig {
+    pub instance_tag: String,
+    /// Human-readable label from `--name`; `--tag` is the identifier stamped on frames.
+    pub instance_name: Option<String>,
+    pub product_id: u16,
+    /// Products accepted in addition to `product_id`, e.g. `100-120` or `100,105,110`.
+    pub products: ProductSet,
+    /// Whether orders for any other product open a book or are rejected until registered.
+    pub unknown_product_policy: UnknownProductPolicy,
+    pub test_order_book_size: u32,
+    pub test_book_scenario: TestBookScenario,
+    /// Multicast group for orders and broadcasts. `None` runs the local benchmark.
+    pub multicast_addr: Option<SocketAddr>,
+    /// Group trades, acks, status and market data are sent to. Defaults to the order
+    /// group; a separate group keeps the engine from hearing its own broadcasts.
+    pub broadcast_addr: Option<SocketAddr>,
+    /// Diagnostics group mock executions are printed to. They never reach the trade
+    /// feed; `None` drops them.
+    pub mock_trade_addr: Option<SocketAddr>,
+    /// Local interfaces the group is joined on; empty lets the OS pick one. The first
+    /// one also carries outbound broadcasts.
+    pub multicast_interfaces: Vec<Ipv4Addr>,
+    /// Hops outbound broadcasts may travel. Defaults to 1, the OS default, which keeps
+    /// them on the local subnet; raise it when consumers sit behind a router.
+    pub multicast_ttl: u32,
+    /// Whether broadcasts are looped back to listeners on this host. On by default, as
+    /// the OS does; turn off when no consumer runs on the engine's host.
+    pub multicast_loopback: bool,
+    /// Number of generated orders for `--bench`; `None` when bench mode is off.
+    pub bench_orders: Option<u32>,
+    /// Leading `--bench` samples excluded from the latency percentiles.
+    pub bench_warmup: u32,
+    /// Runs `--bench` once per bucket layout and reports them side by side, in place
+    /// of a single run under `bucket_layout`.
+    pub bench_compare_layouts: bool,
+    /// CPU core the matching thread is pinned to.
+    pub cpu_core: usize,
+    /// CPU core the network receive thread is pinned to when serving; unpinned if `None`.
+    /// Never the matching thread's core, which the two would then share.
+    pub receive_core: Option<usize>,
+    /// Product trades at negative prices (spreads, power); the wire price is read as signed.
+    pub allow_negative_prices: bool,
+    /// Base units per wire quantity unit, for products that trade fractional sizes.
+    pub lot_size: u32,
+    /// Order in which crossing resting orders are filled; price-time unless overridden.
+    pub priority_mode: PriorityMode,
+    /// Whether each price level keeps scan fields in arrays of their own; AoS unless overridden.
+    pub bucket_layout: BucketLayout,
+    /// Market orders stop this many ticks past their first fill; `None` lets them sweep.
+    pub max_slippage_ticks: Option<u64>,
+    /// Continuous trading halts a product rather than trade more than this many ticks
+    /// from the price its last auction opened at; `None` applies no band.
+    pub price_band_ticks: Option<u64>,
+    /// Resting price (default) or midpoint price improvement for crossing limit orders.
+    pub trade_price_mode: TradePriceMode,
+    /// Price `--trade-price reference` executes at, within both orders' limits.
+    pub reference_price: Option<i64>,
+    /// One trade print per execution (default) or one per price level of each match.
+    pub tape_aggregation: TapeAggregation,
+    /// How midpoint trade prices and auction prices between ticks are rounded.
+    pub price_rounding: PriceRounding,
+    /// Ordered tie-break rules and reference price for choosing an auction price, and
+    /// the imbalance above which an auction is extended instead of crossed.
+    pub auction_rules: AuctionRules,
+    /// Cap on resting orders per side and what happens to passive orders beyond it.
+    pub max_orders_per_side: Option<u32>,
+    pub book_full_policy: BookFullPolicy,
+    /// Whether mock orders are priced against real liquidity or only trade with each other.
+    pub mock_match_policy: MockMatchPolicy,
+    /// Whether an order crossing a resting quote of its own trader is let through,
+    /// logged or rejected.
+    pub wash_trade_policy: WashTradePolicy,
+    /// Whether order time priority follows the client's submit_time (default) or the
+    /// engine's receive time.
+    pub timestamp_source: TimestampSource,
+    /// Orders whose submit_time is more than this far ahead of the engine clock are
+    /// rejected, nearer ones clamped to the receive time; `None` lets both through.
+    pub max_clock_skew_ms: Option<u64>,
+    /// This instance only handles orders with `order_id % shard_count == shard_index`.
+    pub shard_index: u32,
+    pub shard_count: u32,
+    /// Verbosity of runtime logging; debug output only exists in debug builds.
+    pub log_level: LogLevel,
+    /// Hardware tick counter (default) or `Instant` for every latency timer.
+    pub timer_source: TimerSource,
+    /// Append-only file recording every rejected order; `None` disables it.
+    pub audit_log_path: Option<PathBuf>,
+    /// Checkpoint file: loaded at startup when present, rewritten every
+    /// `snapshot_interval_secs` while serving; `None` disables it.
+    pub snapshot_path: Option<PathBuf>,
+    pub snapshot_interval_secs: u64,
+    /// Journal of inbound frames, replayed after the snapshot at startup and emptied
+    /// by each snapshot written; `None` disables it.
+    pub wal_path: Option<PathBuf>,
+    /// Overgrown books are compacted every `compact_interval_secs` when set, never
+    /// below `compact_floor` orders of id map capacity.
+    pub compact_interval_secs: Option<u64>,
+    pub compact_floor: usize,
+    /// Fault in the books' id maps at startup, before any order is rested.
+    pub prefault: bool,
+    /// File of order frames rested into the book at startup, before matching begins.
+    /// Not loaded when a snapshot was recovered, which already holds what is left of it.
+    pub seed_book_path: Option<PathBuf>,
+    /// How frames with a bad checksum are reported, and how many per second count as
+    /// a spike worth a warning (0 disables the warning).
+    pub checksum_policy: ChecksumPolicy,
+    pub checksum_alert_threshold: u32,
+}
+
+impl EngineConfig {
+    /// The instance tag zero-padded to the 16-byte wire representation.
+    pub fn instance_tag_bytes(&self) -> [u8; 16] {
+        let mut tag_array = [0u8; 16];
+        let bytes = self.instance_tag.as_bytes();
+        let len = std::cmp::min(bytes.len(), 16);
+        tag_array[..len].copy_from_slice(&bytes[..len]);
+        tag_array
+    }
+}
+
+/// Name of this machine, `None` when it cannot be read or is empty.
+fn hostname() -> Option<String> {
+    #[cfg(unix)]
+    {
+        let mut buf = [0u8; 256];
+        // SAFETY: the length passed is the buffer's, so the call stays within it
+        if unsafe { libc::gethostname(buf.as_mut_ptr().cast(), buf.len()) } != 0 {
+            return None;
+        }
+        let end = buf.iter().position(|&b| b == 0).unwrap_or(buf.len());
+        let name = String::from_utf8_lossy(&buf[..end]).into_owned();
+        (!name.is_empty()).then_some(name)
+    }
+
+    #[cfg(windows)]
+    {
+        std::env::var("COMPUTERNAME").ok().filter(|name| !name.is_empty())
+    }
+
+    #[cfg(not(any(unix, windows)))]
+    {
+        None
+    }
+}
+
+/// First 16 bytes of `name`, cut on a character boundary, for use as an instance tag.
+fn truncate_tag(name: &str) -> String {
+    let mut end = name.len().min(16);
+    while !name.is_char_boundary(end) {
+        end -= 1;
+    }
+    name[..end].to_string()
+}
+
+pub fn get_config() -> Result<EngineConfig, String> {
     let args: Vec<String> = std::env::args().collect();
+    parse_config(&args)
+}
+
+/// Builds the configuration from `args`, the program name first as in `std::env::args`.
+pub fn parse_config(args: &[String]) -> Result<EngineConfig, String> {
+    let mut instance_tag = None;
     let mut instance_name = None;
     let mut product_id = None;
+    let mut products_str = None;
+    let mut unknown_product_str = None;
     let mut test_order_book_size_str = None;
+    let mut multicast_addr_str = None;
+    let mut broadcast_addr_str = None;
+    let mut multicast_interfaces_str = None;
+    let mut mock_trade_addr_str = None;
+    let mut multicast_ttl_str = None;
+    let mut multicast_loopback_str = None;
+    let mut test_book_scenario_str = None;
+    let mut bench_orders_str = None;
+    let mut bench_warmup_str = None;
+    let mut cpu_core_str = None;
+    let mut receive_core_str = None;
+    let mut allow_negative_prices = false;
+    let mut prefault = false;
+    let mut bench_compare_layouts = false;
+    let mut lot_size_str = None;
+    let mut priority_mode_str = None;
+    let mut bucket_layout_str = None;
+    let mut max_slippage_ticks_str = None;
+    let mut price_band_ticks_str = None;
+    let mut trade_price_mode_str = None;
+    let mut reference_price_str = None;
+    let mut tape_aggregation_str = None;
+    let mut price_rounding_str = None;
+    let mut auction_tie_break_str = None;
+    let mut auction_reference_price_str = None;
+    let mut auction_max_imbalance_str = None;
+    let mut max_orders_per_side_str = None;
+    let mut book_full_policy_str = None;
+    let mut mock_match_policy_str = None;
+    let mut wash_trade_policy_str = None;
+    let mut timestamp_source_str = None;
+    let mut max_clock_skew_str = None;
+    let mut checksum_policy_str = None;
+    let mut checksum_alert_threshold_str = None;
+    let mut shard_index_str = None;
+    let mut shard_count_str = None;
+    let mut log_level_str = None;
+    let mut timer_source_str = None;
+    let mut audit_log_path = None;
+    let mut snapshot_path = None;
+    let mut snapshot_interval_str = None;
+    let mut wal_path = None;
+    let mut seed_book_path = None;
+    let mut compact_interval_str = None;
+    let mut compact_floor_str = None;
 
     // Command Line Arguments Parsing
     let mut i = 1;
     while i < args.len() {
         match args[i].as_str() {
-            "--name" => {
-                if i + 1 < args.len() {
-                    instance_name = Some(args[i + 1].clone());
-                    i += 1;
-                }
-            }
-            "--tag" => {
-                if i + 1 < args.len() {
-                    instance_name = Some(args[i + 1].clone());
-                    i += 1;
-                }
-            }
-            "--prodid" => {
-                if i + 1 < args.len() {
-                    product_id = Some(args[i + 1].clone());
-                    i += 1;
-                }
+            "--name" if i + 1 < args.len() => {
+                instance_name = Some(args[i + 1].clone());
+                i += 1;
+            }
+            "--tag" if i + 1 < args.len() => {
+                instance_tag = Some(args[i + 1].clone());
+                i += 1;
+            }
+            "--prodid" if i + 1 < args.len() => {
+                product_id = Some(args[i + 1].clone());
+                i += 1;
             }
             
-            "--test-order-book-size" => {
-                if i + 1 < args.len() {
-                    test_order_book_size_str = Some(args[i + 1].clone());
-                    i += 1;
-                }
+            "--test-order-book-size" if i + 1 < args.len() => {
+                test_order_book_size_str = Some(args[i + 1].clone());
+                i += 1;
+            }
+            "--test-book-scenario" if i + 1 < args.len() => {
+                test_book_scenario_str = Some(args[i + 1].clone());
+                i += 1;
+            }
+            "--products" if i + 1 < args.len() => {
+                products_str = Some(args[i + 1].clone());
+                i += 1;
+            }
+            "--unknown-product" if i + 1 < args.len() => {
+                unknown_product_str = Some(args[i + 1].clone());
+                i += 1;
+            }
+            "--multicast-addr" if i + 1 < args.len() => {
+                multicast_addr_str = Some(args[i + 1].clone());
+                i += 1;
+            }
+            "--broadcast-addr" if i + 1 < args.len() => {
+                broadcast_addr_str = Some(args[i + 1].clone());
+                i += 1;
+            }
+            "--mock-trade-addr" if i + 1 < args.len() => {
+                mock_trade_addr_str = Some(args[i + 1].clone());
+                i += 1;
+            }
+            "--interfaces" if i + 1 < args.len() => {
+                multicast_interfaces_str = Some(args[i + 1].clone());
+                i += 1;
+            }
+            "--multicast-ttl" if i + 1 < args.len() => {
+                multicast_ttl_str = Some(args[i + 1].clone());
+                i += 1;
+            }
+            "--multicast-loopback" if i + 1 < args.len() => {
+                multicast_loopback_str = Some(args[i + 1].clone());
+                i += 1;
+            }
+            "--bench" if i + 1 < args.len() => {
+                bench_orders_str = Some(args[i + 1].clone());
+                i += 1;
+            }
+            "--bench-warmup" if i + 1 < args.len() => {
+                bench_warmup_str = Some(args[i + 1].clone());
+                i += 1;
+            }
+            "--core" if i + 1 < args.len() => {
+                cpu_core_str = Some(args[i + 1].clone());
+                i += 1;
+            }
+            "--receive-core" if i + 1 < args.len() => {
+                receive_core_str = Some(args[i + 1].clone());
+                i += 1;
+            }
+            "--priority-mode" if i + 1 < args.len() => {
+                priority_mode_str = Some(args[i + 1].clone());
+                i += 1;
+            }
+            "--bucket-layout" if i + 1 < args.len() => {
+                bucket_layout_str = Some(args[i + 1].clone());
+                i += 1;
+            }
+            "--max-slippage-ticks" if i + 1 < args.len() => {
+                max_slippage_ticks_str = Some(args[i + 1].clone());
+                i += 1;
+            }
+            "--price-band-ticks" if i + 1 < args.len() => {
+                price_band_ticks_str = Some(args[i + 1].clone());
+                i += 1;
+            }
+            "--trade-price" if i + 1 < args.len() => {
+                trade_price_mode_str = Some(args[i + 1].clone());
+                i += 1;
+            }
+            "--wash-trade-policy" if i + 1 < args.len() => {
+                wash_trade_policy_str = Some(args[i + 1].clone());
+                i += 1;
+            }
+            "--timestamp-source" if i + 1 < args.len() => {
+                timestamp_source_str = Some(args[i + 1].clone());
+                i += 1;
+            }
+            "--max-clock-skew-ms" if i + 1 < args.len() => {
+                max_clock_skew_str = Some(args[i + 1].clone());
+                i += 1;
+            }
+            "--checksum-policy" if i + 1 < args.len() => {
+                checksum_policy_str = Some(args[i + 1].clone());
+                i += 1;
+            }
+            "--checksum-alert-threshold" if i + 1 < args.len() => {
+                checksum_alert_threshold_str = Some(args[i + 1].clone());
+                i += 1;
+            }
+            "--tape-aggregation" if i + 1 < args.len() => {
+                tape_aggregation_str = Some(args[i + 1].clone());
+                i += 1;
+            }
+            "--price-rounding" if i + 1 < args.len() => {
+                price_rounding_str = Some(args[i + 1].clone());
+                i += 1;
+            }
+            "--auction-tie-break" if i + 1 < args.len() => {
+                auction_tie_break_str = Some(args[i + 1].clone());
+                i += 1;
+            }
+            "--reference-price" if i + 1 < args.len() => {
+                reference_price_str = Some(args[i + 1].clone());
+                i += 1;
+            }
+            "--auction-reference-price" if i + 1 < args.len() => {
+                auction_reference_price_str = Some(args[i + 1].clone());
+                i += 1;
+            }
+            "--auction-max-imbalance" if i + 1 < args.len() => {
+                auction_max_imbalance_str = Some(args[i + 1].clone());
+                i += 1;
+            }
+            "--max-orders-per-side" if i + 1 < args.len() => {
+                max_orders_per_side_str = Some(args[i + 1].clone());
+                i += 1;
+            }
+            "--book-full-policy" if i + 1 < args.len() => {
+                book_full_policy_str = Some(args[i + 1].clone());
+                i += 1;
+            }
+            "--mock-policy" if i + 1 < args.len() => {
+                mock_match_policy_str = Some(args[i + 1].clone());
+                i += 1;
+            }
+            "--shard-index" if i + 1 < args.len() => {
+                shard_index_str = Some(args[i + 1].clone());
+                i += 1;
+            }
+            "--shard-count" if i + 1 < args.len() => {
+                shard_count_str = Some(args[i + 1].clone());
+                i += 1;
+            }
+            "--log-level" if i + 1 < args.len() => {
+                log_level_str = Some(args[i + 1].clone());
+                i += 1;
+            }
+            "--timer-source" if i + 1 < args.len() => {
+                timer_source_str = Some(args[i + 1].clone());
+                i += 1;
+            }
+            "--audit-log" if i + 1 < args.len() => {
+                audit_log_path = Some(PathBuf::from(&args[i + 1]));
+                i += 1;
+            }
+            "--snapshot" if i + 1 < args.len() => {
+                snapshot_path = Some(PathBuf::from(&args[i + 1]));
+                i += 1;
+            }
+            "--snapshot-interval" if i + 1 < args.len() => {
+                snapshot_interval_str = Some(args[i + 1].clone());
+                i += 1;
+            }
+            "--wal" if i + 1 < args.len() => {
+                wal_path = Some(PathBuf::from(&args[i + 1]));
+                i += 1;
+            }
+            "--compact-interval" if i + 1 < args.len() => {
+                compact_interval_str = Some(args[i + 1].clone());
+                i += 1;
+            }
+            "--compact-floor" if i + 1 < args.len() => {
+                compact_floor_str = Some(args[i + 1].clone());
+                i += 1;
+            }
+            "--seed-book" if i + 1 < args.len() => {
+                seed_book_path = Some(PathBuf::from(&args[i + 1]));
+                i += 1;
+            }
+            "--lot-size" if i + 1 < args.len() => {
+                lot_size_str = Some(args[i + 1].clone());
+                i += 1;
+            }
+            "--allow-negative-prices" => {
+                allow_negative_prices = true;
+            }
+            "--prefault" => {
+                prefault = true;
+            }
+            "--bench-compare-layouts" => {
+                bench_compare_layouts = true;
             }
             _ => {}
         }
         i += 1;
     }
 
-    // 1. Instance Name (Tag)
-    let tag_string = instance_name
+    // 1. Instance Tag, falling back to the name for older command lines, then to the
+    //    hostname so a fleet tags itself
+    if let (Some(tag), Some(name)) = (&instance_tag, &instance_name)
+        && tag != name
+    {
+        log_warn!("Both --tag '{}' and --name '{}' given; tagging frames with '{}'", tag, name, tag);
+    }
+    let tag_string = instance_tag
+        .or_else(|| instance_name.clone())
         .or_else(|| std::env::var("INST_NAME").ok())
+        .or_else(|| hostname().map(|host| truncate_tag(&host)))
         .unwrap_or_else(|| "DEFAULT".to_string());
 
     if tag_string.len() > 16 {
@@ -64,8 +468,76 @@ pub fn get_config() -> Result<(String, u16, u32), String> {
         )
     })?;
 
+    let products = match products_str {
+        Some(s) => parse_product_set(&s)?,
+        None => ProductSet::None,
+    };
+
+    let unknown_product_policy = match unknown_product_str.as_deref() {
+        None | Some("reject") => UnknownProductPolicy::Reject,
+        Some("auto-create") => UnknownProductPolicy::AutoCreate,
+        Some(s) => {
+            return Err(format!(
+                "Invalid unknown product policy: '{}'. Expected reject or auto-create.",
+                s
+            ))
+        }
+    };
+
     // 3. Multicast Addresses
-    
+    let multicast_addr = match multicast_addr_str.or_else(|| std::env::var("MULTICAST_ADDR").ok()) {
+        Some(addr_str) => Some(addr_str.parse::<SocketAddr>().map_err(|_| {
+            format!(
+                "Invalid multicast address: '{}'. Expected <ip>:<port>.",
+                addr_str
+            )
+        })?),
+        None => None,
+    };
+
+    let broadcast_addr = match broadcast_addr_str.or_else(|| std::env::var("BROADCAST_ADDR").ok()) {
+        Some(addr_str) => Some(addr_str.parse::<SocketAddr>().map_err(|_| {
+            format!(
+                "Invalid broadcast address: '{}'. Expected <ip>:<port>.",
+                addr_str
+            )
+        })?),
+        None => multicast_addr,
+    };
+
+    let mock_trade_addr = match mock_trade_addr_str {
+        Some(addr_str) => Some(addr_str.parse::<SocketAddr>().map_err(|_| {
+            format!(
+                "Invalid mock trade address: '{}'. Expected <ip>:<port>.",
+                addr_str
+            )
+        })?),
+        None => None,
+    };
+
+    let multicast_interfaces = match multicast_interfaces_str {
+        Some(s) => parse_interface_list(&s)?,
+        None => Vec::new(),
+    };
+
+    let multicast_ttl: u32 = match multicast_ttl_str {
+        Some(s) => match s.parse() {
+            Ok(ttl) if ttl <= 255 => ttl,
+            _ => return Err(format!("Invalid multicast TTL: '{}'. Must be 0-255.", s)),
+        },
+        None => 1,
+    };
+
+    let multicast_loopback = match multicast_loopback_str.as_deref() {
+        None | Some("on") => true,
+        Some("off") => false,
+        Some(s) => {
+            return Err(format!(
+                "Invalid multicast loopback: '{}'. Expected on or off.",
+                s
+            ));
+        }
+    };
 
     let size_str: &str = test_order_book_size_str
         .as_deref() // Converts Option<String> to Option<&str>
@@ -77,9 +549,423 @@ pub fn get_config() -> Result<(String, u16, u32), String> {
         0
     });
 
-    Ok((
-        tag_string,
-        prod_id,
+    let test_book_scenario = match test_book_scenario_str {
+        Some(s) => parse_test_book_scenario(&s)?,
+        None => TestBookScenario::NonCrossing,
+    };
+
+    let bench_orders = match bench_orders_str {
+        Some(s) => Some(parse_human_readable_u32(&s).map_err(|e| {
+            format!("Invalid bench order count '{}': {}", s, e)
+        })?),
+        None => None,
+    };
+
+    let bench_warmup = match bench_warmup_str {
+        Some(s) => parse_human_readable_u32(&s)
+            .map_err(|e| format!("Invalid bench warm-up count '{}': {}", s, e))?,
+        None => 0,
+    };
+    if bench_compare_layouts && bench_orders.is_none() {
+        return Err("Invalid bench layout comparison: --bench-compare-layouts requires --bench.".to_string());
+    }
+
+    let cpu_core: usize = match cpu_core_str {
+        Some(s) => s
+            .parse()
+            .map_err(|_| format!("Invalid core id: '{}'. Must be a valid core index.", s))?,
+        None => 1,
+    };
+
+    let receive_core: Option<usize> = match receive_core_str {
+        Some(s) => Some(
+            s.parse()
+                .map_err(|_| format!("Invalid receive core id: '{}'. Must be a valid core index.", s))?,
+        ),
+        None => None,
+    };
+    if receive_core == Some(cpu_core) {
+        return Err(format!("--receive-core {} is the matching thread's core; pick another", cpu_core));
+    }
+
+    let priority_mode = match priority_mode_str.as_deref() {
+        None | Some("price-time") => PriorityMode::PriceTime,
+        Some("time-price") => PriorityMode::TimePrice,
+        Some(s) => {
+            return Err(format!(
+                "Invalid priority mode: '{}'. Expected price-time or time-price.",
+                s
+            ));
+        }
+    };
+
+    let bucket_layout = match bucket_layout_str.as_deref() {
+        None | Some("aos") => BucketLayout::Aos,
+        Some("soa") => BucketLayout::Soa,
+        Some(s) => {
+            return Err(format!("Invalid bucket layout: '{}'. Expected aos or soa.", s));
+        }
+    };
+
+    let lot_size = match lot_size_str {
+        Some(s) => match parse_human_readable_u32(&s) {
+            Ok(0) => return Err("Invalid lot size: must be at least 1.".to_string()),
+            Ok(size) => size,
+            Err(e) => return Err(format!("Invalid lot size '{}': {}", s, e)),
+        },
+        None => 1,
+    };
+
+    let max_slippage_ticks = match max_slippage_ticks_str {
+        Some(s) => Some(s.parse::<u64>().map_err(|_| {
+            format!("Invalid max slippage: '{}'. Must be a number of ticks.", s)
+        })?),
+        None => None,
+    };
+
+    let price_band_ticks = match price_band_ticks_str {
+        Some(s) => Some(s.parse::<u64>().map_err(|_| {
+            format!("Invalid price band: '{}'. Must be a number of ticks.", s)
+        })?),
+        None => None,
+    };
+
+    let trade_price_mode = match trade_price_mode_str.as_deref() {
+        None | Some("resting") => TradePriceMode::Resting,
+        Some("midpoint") => TradePriceMode::Midpoint,
+        Some("aggressor") => TradePriceMode::Aggressor,
+        Some("reference") => TradePriceMode::Reference,
+        Some(s) => {
+            return Err(format!(
+                "Invalid trade price mode: '{}'. Expected resting, midpoint, aggressor or reference.",
+                s
+            ));
+        }
+    };
+
+    let reference_price = match reference_price_str {
+        Some(s) => Some(s.parse::<i64>().map_err(|_| {
+            format!("Invalid reference price: '{}'. Must be a valid i64.", s)
+        })?),
+        None => None,
+    };
+    if trade_price_mode == TradePriceMode::Reference && reference_price.is_none() {
+        return Err("Invalid trade price mode: reference requires --reference-price.".to_string());
+    }
+
+    let tape_aggregation = match tape_aggregation_str.as_deref() {
+        None | Some("off") => TapeAggregation::Off,
+        Some("by-price") => TapeAggregation::ByPrice,
+        Some(s) => {
+            return Err(format!(
+                "Invalid tape aggregation: '{}'. Expected off or by-price.",
+                s
+            ));
+        }
+    };
+
+    let price_rounding = match price_rounding_str.as_deref() {
+        None | Some("half-up") => PriceRounding::HalfUp,
+        Some("half-even") => PriceRounding::HalfEven,
+        Some(s) => {
+            return Err(format!(
+                "Invalid price rounding: '{}'. Expected half-up or half-even.",
+                s
+            ));
+        }
+    };
+
+    let mut auction_rules = AuctionRules {
+        rounding: price_rounding,
+        ..AuctionRules::default()
+    };
+    if let Some(s) = auction_tie_break_str {
+        auction_rules.tie_breaks = parse_tie_break_list(&s)?;
+    }
+    if let Some(s) = auction_reference_price_str {
+        auction_rules.reference_price = Some(s.parse::<i64>().map_err(|_| {
+            format!("Invalid auction reference price: '{}'. Must be a valid i64.", s)
+        })?);
+    }
+    if let Some(s) = auction_max_imbalance_str {
+        auction_rules.max_imbalance_pct = Some(s.parse::<u32>().map_err(|_| {
+            format!("Invalid auction max imbalance: '{}'. Must be a percentage of matched volume.", s)
+        })?);
+    }
+
+    let max_orders_per_side = match max_orders_per_side_str {
+        Some(s) => Some(parse_human_readable_u32(&s).map_err(|e| {
+            format!("Invalid max orders per side '{}': {}", s, e)
+        })?),
+        None => None,
+    };
+
+    let book_full_policy = match book_full_policy_str.as_deref() {
+        None | Some("reject") => BookFullPolicy::Reject,
+        Some("evict-worst") => BookFullPolicy::EvictWorst,
+        Some(s) => {
+            return Err(format!(
+                "Invalid book full policy: '{}'. Expected reject or evict-worst.",
+                s
+            ));
+        }
+    };
+
+    let mock_match_policy = match mock_match_policy_str.as_deref() {
+        None | Some("against-real") => MockMatchPolicy::AgainstReal,
+        Some("mock-only") => MockMatchPolicy::MockOnly,
+        Some(s) => {
+            return Err(format!(
+                "Invalid mock policy: '{}'. Expected against-real or mock-only.",
+                s
+            ));
+        }
+    };
+
+    let wash_trade_policy = match wash_trade_policy_str.as_deref() {
+        None | Some("off") => WashTradePolicy::Off,
+        Some("warn") => WashTradePolicy::Warn,
+        Some("reject") => WashTradePolicy::Reject,
+        Some(s) => {
+            return Err(format!(
+                "Invalid wash trade policy: '{}'. Expected off, warn or reject.",
+                s
+            ));
+        }
+    };
+
+    let timestamp_source = match timestamp_source_str.as_deref() {
+        None | Some("client") => TimestampSource::Client,
+        Some("engine") => TimestampSource::Engine,
+        Some(s) => {
+            return Err(format!(
+                "Invalid timestamp source: '{}'. Expected client or engine.",
+                s
+            ));
+        }
+    };
+
+    let max_clock_skew_ms = match max_clock_skew_str {
+        Some(s) => Some(s.parse::<u64>().map_err(|_| {
+            format!("Invalid max clock skew: '{}'. Must be a whole number of milliseconds.", s)
+        })?),
+        None => None,
+    };
+
+    let checksum_policy = match checksum_policy_str.as_deref() {
+        None | Some("count") => ChecksumPolicy::Count,
+        Some("alert") => ChecksumPolicy::Alert,
+        Some(s) => {
+            return Err(format!(
+                "Invalid checksum policy: '{}'. Expected count or alert.",
+                s
+            ));
+        }
+    };
+
+    let checksum_alert_threshold = match checksum_alert_threshold_str {
+        Some(s) => parse_human_readable_u32(&s).map_err(|e| {
+            format!("Invalid checksum alert threshold '{}': {}", s, e)
+        })?,
+        None => 100,
+    };
+
+    let shard_index: u32 = match shard_index_str {
+        Some(s) => s
+            .parse()
+            .map_err(|_| format!("Invalid shard index: '{}'. Must be a valid u32.", s))?,
+        None => 0,
+    };
+    let shard_count: u32 = match shard_count_str {
+        Some(s) => s
+            .parse()
+            .map_err(|_| format!("Invalid shard count: '{}'. Must be a valid u32.", s))?,
+        None => 1,
+    };
+    if shard_count == 0 || shard_index >= shard_count {
+        return Err(format!(
+            "Invalid sharding: index {} with count {}. Index must be below a non-zero count.",
+            shard_index, shard_count
+        ));
+    }
+
+    let log_level = match log_level_str {
+        Some(s) => parse_log_level(&s)?,
+        None => LogLevel::Info,
+    };
+
+    let snapshot_interval_secs = match snapshot_interval_str {
+        Some(s) => match s.parse::<u64>() {
+            Ok(secs) if secs > 0 => secs,
+            _ => {
+                return Err(format!(
+                    "Invalid snapshot interval: '{}'. Must be a positive number of seconds.",
+                    s
+                ));
+            }
+        },
+        None => 60,
+    };
+
+    let compact_interval_secs = match compact_interval_str {
+        Some(s) => match s.parse::<u64>() {
+            Ok(secs) if secs > 0 => Some(secs),
+            _ => {
+                return Err(format!(
+                    "Invalid compact interval: '{}'. Must be a positive number of seconds.",
+                    s
+                ));
+            }
+        },
+        None => None,
+    };
+
+    let compact_floor = match compact_floor_str {
+        Some(s) => parse_human_readable_u32(&s).map_err(|e| {
+            format!("Invalid compact floor '{}': {}", s, e)
+        })? as usize,
+        None => DEFAULT_COMPACT_FLOOR,
+    };
+
+    let timer_source = match timer_source_str.as_deref() {
+        None | Some("tsc") => TimerSource::Tsc,
+        Some("monotonic") => TimerSource::Monotonic,
+        Some(s) => {
+            return Err(format!(
+                "Invalid timer source: '{}'. Expected tsc or monotonic.",
+                s
+            ));
+        }
+    };
+
+    Ok(EngineConfig {
+        instance_tag: tag_string,
+        instance_name,
+        product_id: prod_id,
+        products,
+        unknown_product_policy,
         test_order_book_size,
-    ))
-}
\ No newline at end of file
+        test_book_scenario,
+        multicast_addr,
+        broadcast_addr,
+        mock_trade_addr,
+        multicast_interfaces,
+        multicast_ttl,
+        multicast_loopback,
+        bench_orders,
+        bench_warmup,
+        bench_compare_layouts,
+        cpu_core,
+        receive_core,
+        allow_negative_prices,
+        lot_size,
+        priority_mode,
+        bucket_layout,
+        max_slippage_ticks,
+        price_band_ticks,
+        trade_price_mode,
+        reference_price,
+        tape_aggregation,
+        price_rounding,
+        auction_rules,
+        max_orders_per_side,
+        book_full_policy,
+        mock_match_policy,
+        wash_trade_policy,
+        timestamp_source,
+        max_clock_skew_ms,
+        shard_index,
+        shard_count,
+        log_level,
+        timer_source,
+        audit_log_path,
+        snapshot_path,
+        snapshot_interval_secs,
+        wal_path,
+        seed_book_path,
+        compact_interval_secs,
+        compact_floor,
+        prefault,
+        checksum_policy,
+        checksum_alert_threshold,
+    })
+}
+
+/// Parses `non-crossing`, `crossing`, `one-sided` or `random[:seed]`.
+fn parse_test_book_scenario(s: &str) -> Result<TestBookScenario, String> {
+    let (name, seed) = match s.split_once(':') {
+        Some((name, seed)) => (name, Some(seed)),
+        None => (s, None),
+    };
+
+    match (name, seed) {
+        ("non-crossing", None) => Ok(TestBookScenario::NonCrossing),
+        ("crossing", None) => Ok(TestBookScenario::Crossing),
+        ("one-sided", None) => Ok(TestBookScenario::OneSided),
+        ("random", None) => Ok(TestBookScenario::Random(1)),
+        ("random", Some(seed)) => seed
+            .parse()
+            .map(TestBookScenario::Random)
+            .map_err(|_| format!("Invalid random seed: '{}'. Must be a valid u64.", seed)),
+        _ => Err(format!(
+            "Invalid test book scenario: '{}'. Expected non-crossing, crossing, one-sided or random[:seed].",
+            s
+        )),
+    }
+}
+
+/// Parses a comma-separated list of IPv4 interface addresses, e.g. `10.0.0.5,10.1.0.5`.
+fn parse_interface_list(s: &str) -> Result<Vec<Ipv4Addr>, String> {
+    s.split(',')
+        .map(str::trim)
+        .filter(|part| !part.is_empty())
+        .map(|part| {
+            part.parse::<Ipv4Addr>().map_err(|_| {
+                format!("Invalid interface address: '{}'. Expected an IPv4 address.", part)
+            })
+        })
+        .collect()
+}
+
+/// Parses an ordered auction tie-break list such as `volume,imbalance,pressure,reference`.
+fn parse_tie_break_list(s: &str) -> Result<Vec<AuctionTieBreak>, String> {
+    let rules = s
+        .split(',')
+        .map(str::trim)
+        .filter(|part| !part.is_empty())
+        .map(|part| match part {
+            "volume" => Ok(AuctionTieBreak::MaxVolume),
+            "imbalance" => Ok(AuctionTieBreak::MinImbalance),
+            "pressure" => Ok(AuctionTieBreak::MarketPressure),
+            "reference" => Ok(AuctionTieBreak::Reference),
+            _ => Err(format!(
+                "Invalid auction tie-break rule: '{}'. Expected volume, imbalance, pressure or reference.",
+                part
+            )),
+        })
+        .collect::<Result<Vec<AuctionTieBreak>, String>>()?;
+    if rules.is_empty() {
+        return Err("Auction tie-break list must name at least one rule.".to_string());
+    }
+    Ok(rules)
+}
+
+/// Parses a product range `100-120` or a comma-separated list `100,105,110`.
+fn parse_product_set(s: &str) -> Result<ProductSet, String> {
+    let parse_id = |part: &str| {
+        part.trim()
+            .parse::<u16>()
+            .map_err(|_| format!("Invalid product id '{}' in '{}'. Must be a valid u16.", part, s))
+    };
+
+    if let Some((first, last)) = s.split_once('-') {
+        let (first, last) = (parse_id(first)?, parse_id(last)?);
+        if first > last {
+            return Err(format!("Invalid product range '{}': start is above end.", s));
+        }
+        return Ok(ProductSet::Range(first, last));
+    }
+
+    let ids = s.split(',').map(parse_id).collect::<Result<Vec<u16>, String>>()?;
+    Ok(ProductSet::List(ids))
+}
@@ -2,15 +2,167 @@
 // continuous_order_book.rs
 // ================================
 
-use ahash::AHashMap;
+use ahash::{AHashMap, AHashSet};
+use std::cmp::Reverse;
+use std::collections::BinaryHeap;
 use std::collections::VecDeque;
+use std::sync::mpsc::Sender;
 
 use crate::data_types::*;
-use crate::date_time_tool::current_timestamp;
+use crate::date_time_tool::{current_timestamp, saturating_duration_u32};
 use crate::high_resolution_timer::HighResolutionTimer;
+use crate::number_tool::round_to_tick;
 
 // --- FIFO bucket per price ---
+//
+// Every change to a queue goes through these methods, which keep the scan columns of
+// a `BucketLayout::Soa` level in step with its orders.
+impl OrdersBucket {
+    pub fn is_empty(&self) -> bool {
+        self.orders.is_empty()
+    }
+
+    /// Queues `order` last, copying its scan fields under `BucketLayout::Soa`.
+    pub fn push_back(&mut self, order: Order, layout: BucketLayout) {
+        if layout == BucketLayout::Soa {
+            let scan = self.scan.get_or_insert_with(Box::default);
+            scan.quantity.push_back(order.quantity);
+            scan.price.push_back(order.price);
+            scan.submit_time.push_back(order.submit_time);
+            scan.order_id.push_back(order.order_id);
+            scan.aon.push_back(order.aon);
+        }
+        self.orders.push_back(order);
+    }
+
+    pub fn remove(&mut self, pos: usize) -> Option<Order> {
+        if let Some(scan) = self.scan.as_mut() {
+            scan.quantity.remove(pos);
+            scan.price.remove(pos);
+            scan.submit_time.remove(pos);
+            scan.order_id.remove(pos);
+            scan.aon.remove(pos);
+        }
+        self.orders.remove(pos)
+    }
+
+    /// Takes `qty` off the order at `pos` and returns what it has left.
+    pub fn fill(&mut self, pos: usize, qty: u32) -> u32 {
+        if let Some(scan) = self.scan.as_mut() {
+            scan.quantity[pos] -= qty;
+        }
+        let order = &mut self.orders[pos];
+        order.quantity -= qty;
+        order.quantity
+    }
+
+    /// Keeps the orders `keep` accepts, in queue order.
+    pub fn retain(&mut self, mut keep: impl FnMut(&Order) -> bool) {
+        let Some(scan) = self.scan.as_mut() else {
+            self.orders.retain(keep);
+            return;
+        };
+        let mut pos = 0;
+        while pos < self.orders.len() {
+            if keep(&self.orders[pos]) {
+                pos += 1;
+                continue;
+            }
+            self.orders.remove(pos);
+            scan.quantity.remove(pos);
+            scan.price.remove(pos);
+            scan.submit_time.remove(pos);
+            scan.order_id.remove(pos);
+            scan.aon.remove(pos);
+        }
+    }
+
+    /// Empties the queue, yielding its orders front to back.
+    pub fn drain(&mut self) -> std::collections::vec_deque::Drain<'_, Order> {
+        self.scan = None;
+        self.orders.drain(..)
+    }
+
+    /// Position of the first order an aggressor with `remaining` quantity may trade
+    /// with: not an all-or-none order larger than that, nor one `skipped(order_id,
+    /// price)` excludes. Reads only the scan columns when the level has them.
+    #[inline(always)]
+    pub fn fillable_position(&self, remaining: u32, skipped: &impl Fn(u64, i64) -> bool) -> Option<usize> {
+        match self.scan.as_deref() {
+            Some(scan) => (0..scan.order_id.len()).position(|pos| {
+                (!scan.aon[pos] || scan.quantity[pos] <= remaining) && !skipped(scan.order_id[pos], scan.price[pos])
+            }),
+            None => self
+                .orders
+                .iter()
+                .position(|o| (!o.aon || o.quantity <= remaining) && !skipped(o.order_id, o.price)),
+        }
+    }
+
+    /// Queue position of `order_id`, if it rests in this level.
+    pub fn position_of(&self, order_id: u64) -> Option<usize> {
+        match self.scan.as_deref() {
+            Some(scan) => scan.order_id.iter().position(|&id| id == order_id),
+            None => self.orders.iter().position(|o| o.order_id == order_id),
+        }
+    }
 
+    #[inline(always)]
+    pub fn submit_time(&self, pos: usize) -> u64 {
+        match self.scan.as_deref() {
+            Some(scan) => scan.submit_time[pos],
+            None => self.orders[pos].submit_time,
+        }
+    }
+
+    /// Quantity of the level shown in public depth, iceberg reserves left out.
+    pub fn displayed_quantity(&self) -> u32 {
+        self.orders.iter().map(Order::displayed_quantity).sum()
+    }
+
+    /// Resting quantity of the level.
+    pub fn quantity(&self) -> u32 {
+        match self.scan.as_deref() {
+            Some(scan) => scan.quantity.iter().sum(),
+            None => self.orders.iter().map(|o| o.quantity).sum(),
+        }
+    }
+
+    /// Adds or drops the scan columns to match `layout`.
+    pub fn set_layout(&mut self, layout: BucketLayout) {
+        let orders = std::mem::take(&mut self.orders);
+        self.scan = None;
+        for order in orders {
+            self.push_back(order, layout);
+        }
+    }
+
+    /// Heap bytes held, from allocated capacity.
+    pub fn memory_footprint(&self) -> usize {
+        let scan = self.scan.as_deref().map_or(0, |scan| {
+            size_of::<ScanColumns>()
+                + scan.quantity.capacity() * size_of::<u32>()
+                + scan.price.capacity() * size_of::<i64>()
+                + scan.submit_time.capacity() * size_of::<u64>()
+                + scan.order_id.capacity() * size_of::<u64>()
+                + scan.aon.capacity() * size_of::<bool>()
+        });
+        self.orders.capacity() * size_of::<Order>() + scan
+    }
+
+    pub fn shrink_to_fit(&mut self) {
+        self.orders.shrink_to_fit();
+        if self.orders.is_empty() {
+            self.scan = None;
+        } else if let Some(scan) = self.scan.as_mut() {
+            scan.quantity.shrink_to_fit();
+            scan.price.shrink_to_fit();
+            scan.submit_time.shrink_to_fit();
+            scan.order_id.shrink_to_fit();
+            scan.aon.shrink_to_fit();
+        }
+    }
+}
 
 // --- Price Ladder Order Book ---
 
@@ -21,7 +173,7 @@ impl ContinuousOrderBook {
     // ----------------------------
     pub fn new(
         tick: u64,
-        base_price: u64,
+        base_price: i64,
         max_levels: usize,
         trade_cap: usize,
     ) -> Self {
@@ -33,19 +185,198 @@ impl ContinuousOrderBook {
             tick,
             base_price,
             levels: max_levels,
-            order_map: AHashMap::with_capacity(1024),
+            order_map: AHashMap::with_capacity(DEFAULT_COMPACT_FLOOR),
             total_bid_volumn: 0,
             total_ask_volumn: 0,
             match_result: MatchResult::new(trade_cap),
             timer:HighResolutionTimer::start(), 
             //most cpu runs on this frequency, change to higher if you are using higher frequency CPU
+            instance_tag: [0; 16],
+            priority_mode: PriorityMode::PriceTime,
+            bucket_layout: BucketLayout::Aos,
+            max_slippage_ticks: None,
+            price_band_ticks: None,
+            band_reference: None,
+            trade_price_mode: TradePriceMode::Resting,
+            reference_price: None,
+            price_rounding: PriceRounding::HalfUp,
+            auction_rules: AuctionRules::default(),
+            bid_order_count: 0,
+            ask_order_count: 0,
+            max_orders_per_side: None,
+            book_full_policy: BookFullPolicy::Reject,
+            mock_match_policy: MockMatchPolicy::AgainstReal,
+            mock_book: None,
+            compact_floor: DEFAULT_COMPACT_FLOOR,
+            book_state: BookState::Empty,
+            book_state_sender: None,
+            session: SessionStats::default(),
+            received_orders: 0,
+            matched_orders: 0,
+            filled_orders: FilledOrders::default(),
+            gtd_expiries: BinaryHeap::new(),
+            depth_cache: DepthCache::default(),
+        }
+    }
+
+    /// An empty book with this one's ladder and matching settings, for another product.
+    pub fn empty_like(&self) -> Self {
+        let mut book = Self::new(self.tick, self.base_price, self.levels, self.match_result.order_execution_list.capacity());
+        book.instance_tag = self.instance_tag;
+        book.priority_mode = self.priority_mode;
+        book.bucket_layout = self.bucket_layout;
+        book.max_slippage_ticks = self.max_slippage_ticks;
+        book.price_band_ticks = self.price_band_ticks;
+        book.trade_price_mode = self.trade_price_mode;
+        book.reference_price = self.reference_price;
+        book.price_rounding = self.price_rounding;
+        book.auction_rules = self.auction_rules.clone();
+        book.max_orders_per_side = self.max_orders_per_side;
+        book.book_full_policy = self.book_full_policy;
+        book.mock_match_policy = self.mock_match_policy;
+        book.compact_floor = self.compact_floor;
+        book
+    }
+
+    /// Lays out every level's queue per `layout`; resting orders keep their places.
+    pub fn set_bucket_layout(&mut self, layout: BucketLayout) {
+        self.bucket_layout = layout;
+        for bucket in self.bids.iter_mut().chain(self.asks.iter_mut()) {
+            if !bucket.is_empty() || bucket.scan.is_some() {
+                bucket.set_layout(layout);
+            }
+        }
+        if let Some(mock_book) = self.mock_book.as_mut() {
+            mock_book.set_bucket_layout(layout);
+        }
+    }
+
+    /// Sends this book's `BookState` transitions to `sender`, tagged with `product_id`.
+    /// Only changes are sent, so a sweep of the whole ask side produces a single
+    /// `BidOnly` event.
+    pub fn publish_book_state(&mut self, product_id: u16, sender: Sender<(u16, BookState)>) {
+        self.book_state_sender = Some((product_id, sender));
+    }
+
+    fn current_book_state(&self) -> BookState {
+        // resting orders always have quantity left, so volume tells whether a side is empty
+        match (self.total_bid_volumn > 0, self.total_ask_volumn > 0) {
+            (true, true) => BookState::TwoSided,
+            (true, false) => BookState::BidOnly,
+            (false, true) => BookState::AskOnly,
+            (false, false) => BookState::Empty,
+        }
+    }
+
+    fn update_book_state(&mut self) {
+        let state = self.current_book_state();
+        if state == self.book_state {
+            return;
+        }
+        self.book_state = state;
+        if let Some((product_id, sender)) = &self.book_state_sender {
+            // a dropped receiver just means nobody is listening any more
+            let _ = sender.send((*product_id, state));
         }
     }
     
     #[inline(always)]
-    fn price_to_index(&self, price: u64) -> usize {
+    fn price_to_index(&self, price: i64) -> usize {
         //println!("{:?}", (price,self.base_price,self.tick));
-        ((price - self.base_price) / self.tick) as usize
+        ((price - self.base_price) as u64 / self.tick) as usize
+    }
+
+    /// Whether a limit order at `price` maps onto a level of the ladder and so can rest.
+    pub fn price_in_range(&self, price: i64) -> bool {
+        let offset = price as i128 - self.base_price as i128;
+        offset >= 0 && offset / (self.tick as i128) < self.levels as i128
+    }
+
+    /// Deepest opposite-side level the order may trade at: its own price level for
+    /// a limit order, the far end of the ladder for a market order.
+    #[inline(always)]
+    fn limit_index(&self, order: &Order) -> isize {
+        if order.price_type == ORDER_PRICE_TYPE_LIMIT {
+            let idx = (order.price.saturating_sub(self.base_price).max(0) as u64 / self.tick) as usize;
+            idx.min(self.levels - 1) as isize
+        } else if order.is_buy() {
+            self.levels as isize - 1
+        } else {
+            0
+        }
+    }
+
+    /// Whether a resting order at `price` is past the limit of `order`. A level spans a
+    /// whole tick, so the level of the limit can hold orders on either side of it.
+    /// All-or-none orders too large for the aggressor are passed over in place by
+    /// `OrdersBucket::fillable_position`, as are those priced past its limit.
+    #[inline(always)]
+    fn past_limit(order: &Order, price: i64) -> bool {
+        order.price_type == ORDER_PRICE_TYPE_LIMIT
+            && if order.is_buy() { price > order.price } else { price < order.price }
+    }
+
+    /// First `(level, position)` in `range` holding an order the aggressor may trade
+    /// with, used in price-priority mode.
+    fn best_fillable(
+        buckets: &[OrdersBucket],
+        mut range: impl Iterator<Item = usize>,
+        remaining: u32,
+        skipped: &impl Fn(u64, i64) -> bool,
+    ) -> Option<(usize, usize)> {
+        range.find_map(|idx| buckets[idx].fillable_position(remaining, skipped).map(|pos| (idx, pos)))
+    }
+
+    /// `(level, position)` in `range` of the tradable order that arrived first, used in
+    /// time-priority mode. On equal submit times the level yielded first by `range`
+    /// (the better price) wins. Every level in the range is scanned, so market orders
+    /// pay for the whole ladder.
+    fn earliest_fillable(
+        buckets: &[OrdersBucket],
+        range: impl Iterator<Item = usize>,
+        remaining: u32,
+        skipped: &impl Fn(u64, i64) -> bool,
+    ) -> Option<(usize, usize)> {
+        range
+            .filter_map(|idx| {
+                let pos = buckets[idx].fillable_position(remaining, skipped)?;
+                Some((idx, pos, buckets[idx].submit_time(pos)))
+            })
+            .min_by_key(|&(_, _, submit_time)| submit_time)
+            .map(|(idx, pos, _)| (idx, pos))
+    }
+
+    /// For a market order, tightens `limit_idx` to `max_slippage_ticks` levels beyond
+    /// the level of its first fill. Levels are one tick apart, so ticks map to indexes.
+    #[inline(always)]
+    fn apply_slippage_limit(&self, order: &Order, first_fill_idx: usize, limit_idx: &mut isize) {
+        let Some(max_ticks) = self.max_slippage_ticks else {
+            return;
+        };
+        if order.price_type != ORDER_PRICE_TYPE_MARKET {
+            return;
+        }
+        let max_ticks = max_ticks.min(self.levels as u64) as isize;
+        if order.is_buy() {
+            *limit_idx = (*limit_idx).min(first_fill_idx as isize + max_ticks);
+        } else {
+            *limit_idx = (*limit_idx).max(first_fill_idx as isize - max_ticks);
+        }
+    }
+
+    /// Execution price of `order` against `resting`, per `trade_price_mode`. Two market
+    /// orders have no price between them and trade at `market_reference_price`.
+    #[inline(always)]
+    fn trade_price(&self, order: &Order, resting: &Order) -> i64 {
+        if order.price_type == ORDER_PRICE_TYPE_MARKET && resting.price_type == ORDER_PRICE_TYPE_MARKET {
+            return self.market_reference_price().unwrap_or(resting.price);
+        }
+        let grid = PriceGrid {
+            tick: self.tick,
+            origin: self.base_price,
+            rounding: self.price_rounding,
+        };
+        self.trade_price_mode.price(order, resting, self.reference_price, grid)
     }
 
     // ----------------------------
@@ -53,21 +384,80 @@ impl ContinuousOrderBook {
     // ----------------------------
     fn add_order(&mut self, order: Order) {
         let idx = self.price_to_index(order.price);
+        if order.time_in_force == TIF_GTD {
+            self.gtd_expiries.push(Reverse((order.expire_time, order.order_id)));
+        }
 
+        self.mark_depth_dirty(order.is_buy());
         if order.is_buy() {
-            self.bids[idx].orders.push_back(order.clone());
+            self.bids[idx].push_back(order.clone(), self.bucket_layout);
             self.best_bid = self.best_bid.max(idx as isize);
             self.total_bid_volumn += order.quantity;
+            self.bid_order_count += 1;
             self.order_map.insert(order.order_id, (true, idx));
         } else {
-            self.asks[idx].orders.push_back(order.clone());
+            self.asks[idx].push_back(order.clone(), self.bucket_layout);
             self.best_ask = self.best_ask.min(idx as isize);
             self.total_ask_volumn += order.quantity;
+            self.ask_order_count += 1;
             self.order_map.insert(order.order_id, (false, idx));
         }
     }
-    pub fn fuel_order(&mut self, order: Order){
+
+    /// Rests `order` subject to `max_orders_per_side`, applying the book-full policy.
+    fn rest_order(&mut self, order: Order) {
+        let (count, is_buy) = if order.is_buy() {
+            (self.bid_order_count, true)
+        } else {
+            (self.ask_order_count, false)
+        };
+        if self.max_orders_per_side.is_none_or(|max| count < max) {
+            self.add_order(order);
+            return;
+        }
+
+        if self.book_full_policy == BookFullPolicy::EvictWorst
+            && let Some(worst_id) = self.worst_order_id(is_buy, order.price)
+        {
+            self.match_result.evicted_order = self.remove_order(worst_id);
+            self.add_order(order);
+            return;
+        }
+        self.match_result.residual_rejected = true;
+    }
+
+    /// Last-in-queue order at the worst price of a side, if that price is strictly worse
+    /// than `price`. Walks up from the far end of the ladder; only runs when a side is full.
+    fn worst_order_id(&self, is_buy: bool, price: i64) -> Option<u64> {
+        let worst = if is_buy {
+            self.bids.iter().find_map(|b| b.orders.back())
+        } else {
+            self.asks.iter().rev().find_map(|b| b.orders.back())
+        }?;
+        let better = if is_buy { price > worst.price } else { price < worst.price };
+        better.then_some(worst.order_id)
+    }
+    /// Rests `order` without matching; a mock order rests in the mock book, created on
+    /// first use, as a plain buy or sell like `match_in_mock_book` leaves it.
+    pub fn fuel_order(&mut self, mut order: Order){
+        if order.is_mocked_order() {
+            order.order_type = if order.is_buy() { ORDER_TYPE_BUY } else { ORDER_TYPE_SELL };
+            if self.mock_book.is_none() {
+                self.mock_book = Some(Box::new(self.empty_like()));
+            }
+            if let Some(mock_book) = self.mock_book.as_mut() {
+                mock_book.fuel_order(order);
+            }
+            return;
+        }
         self.add_order(order);
+        self.update_book_state();
+    }
+
+    /// The book `order` rests in: this one for a real order, the mock book for a mock
+    /// order, `None` while there is no mock book yet.
+    pub fn resting_book(&self, order: &Order) -> Option<&ContinuousOrderBook> {
+        if order.is_mocked_order() { self.mock_book.as_deref() } else { Some(self) }
     }
 
     // ----------------------------
@@ -75,60 +465,331 @@ impl ContinuousOrderBook {
     // ----------------------------
     pub fn match_order(&mut self, mut order: Order) {
         self.match_result.order_execution_list.clear();
+        self.match_result.residual_rejected = false;
+        self.match_result.evicted_order = None;
         self.match_result.start_time = self.timer.ns() as u64;
 
+        // mock orders never trade with or rest among real orders
+        if order.is_mocked_order() {
+            match self.mock_match_policy {
+                MockMatchPolicy::AgainstReal => {
+                    let (_, executions) = self.mock_match(&order);
+                    self.match_result.order_execution_list.extend(executions);
+                }
+                MockMatchPolicy::MockOnly => self.match_in_mock_book(order),
+            }
+            self.match_result.end_time = self.timer.ns() as u64;
+            return;
+        }
+
+        if !self.is_killed(&order) {
+            if order.is_buy() {
+                self.match_buy(&mut order);
+            } else {
+                self.match_sell(&mut order);
+            }
+        }
+
+        if order.quantity > 0 && order.rests_residual() {
+            self.rest_order(order);
+        } else if order.quantity == 0 && !order.is_mocked_order() {
+            self.filled_orders.record(order.order_id);
+        }
+
+        self.match_result.end_time = self.timer.ns() as u64;
+        self.update_book_state();
+    }
+
+    /// Matches a mock order in the mock book, created on first use. It holds mock orders
+    /// as plain buys and sells so they trade and rest there like real ones; the fills
+    /// are copied back flagged as mocked.
+    fn match_in_mock_book(&mut self, mut order: Order) {
+        order.order_type = if order.is_buy() { ORDER_TYPE_BUY } else { ORDER_TYPE_SELL };
+        if self.mock_book.is_none() {
+            self.mock_book = Some(Box::new(self.empty_like()));
+        }
+        let Some(mock_book) = self.mock_book.as_mut() else {
+            return;
+        };
+        mock_book.match_order(order);
+
+        let result = &mock_book.match_result;
+        self.match_result.residual_rejected = result.residual_rejected;
+        self.match_result.evicted_order = result.evicted_order.clone();
+        self.match_result
+            .order_execution_list
+            .extend(result.order_execution_list.iter().map(|execution| OrderExecution {
+                is_mocked_result: true,
+                ..execution.clone()
+            }));
+    }
+
+    /// Whether the residual of `order` rests after matching: a real order's in this
+    /// book, a mock order's in the mock book under `MockMatchPolicy::MockOnly`.
+    pub fn rests_residual(&self, order: &Order) -> bool {
+        order.rests_residual()
+            && (!order.is_mocked_order() || self.mock_match_policy == MockMatchPolicy::MockOnly)
+    }
+
+    /// Whether the order must not trade at all: fill or kill and all or none only trade
+    /// when the whole quantity is available up front, a minimum quantity when at least
+    /// that much is.
+    fn is_killed(&self, order: &Order) -> bool {
+        let required = if order.time_in_force == TIF_FOK || order.aon {
+            order.quantity
+        } else {
+            order.min_qty.min(order.quantity)
+        };
+        required > 0 && self.fillable_quantity(order) < required
+    }
+
+    /// Fills `order` would get against the book as it stands, without touching it.
+    /// Returns the quantity left over and the executions, flagged as mocked. Follows
+    /// the same priority, all-or-none, slippage and trade price rules as `match_order`;
+    /// what would happen to the residual (rest, cancel, book full) is not modelled.
+    pub fn mock_match(&self, order: &Order) -> (u32, Vec<OrderExecution>) {
+        let mut remaining = order.quantity;
+        let mut executions = Vec::new();
+        if self.is_killed(order) {
+            return (remaining, executions);
+        }
+
+        let is_buy = order.is_buy();
+        let buckets = if is_buy { &self.asks } else { &self.bids };
+        let mut best = if is_buy { self.best_ask } else { self.best_bid };
+        let mut limit_idx = self.limit_index(order);
+        let start_time = self.timer.ns() as u64;
+        // every order traded except the last is used up, so they are skipped by id
+        // instead of being removed
+        let mut used_up: AHashSet<u64> = AHashSet::new();
+
+        while remaining > 0 && if is_buy { best <= limit_idx } else { best >= limit_idx } {
+            if buckets[best as usize].orders.iter().all(|o| used_up.contains(&o.order_id)) {
+                best += if is_buy { 1 } else { -1 };
+                continue;
+            }
+
+            let skipped = |order_id: u64, price: i64| used_up.contains(&order_id) || Self::past_limit(order, price);
+            let next = if is_buy {
+                let range = best as usize..=limit_idx as usize;
+                match self.priority_mode {
+                    PriorityMode::PriceTime => Self::best_fillable(buckets, range, remaining, &skipped),
+                    PriorityMode::TimePrice => Self::earliest_fillable(buckets, range, remaining, &skipped),
+                }
+            } else {
+                let range = (limit_idx as usize..=best as usize).rev();
+                match self.priority_mode {
+                    PriorityMode::PriceTime => Self::best_fillable(buckets, range, remaining, &skipped),
+                    PriorityMode::TimePrice => Self::earliest_fillable(buckets, range, remaining, &skipped),
+                }
+            };
+            let Some((idx, pos)) = next else {
+                break;
+            };
+            if executions.is_empty() {
+                self.apply_slippage_limit(order, idx, &mut limit_idx);
+            }
+
+            let resting = &buckets[idx].orders[pos];
+
+            let qty = remaining.min(resting.quantity);
+            remaining -= qty;
+            if qty == resting.quantity {
+                used_up.insert(resting.order_id);
+            }
+            let (buy_order_id, sell_order_id) = if is_buy {
+                (order.order_id, resting.order_id)
+            } else {
+                (resting.order_id, order.order_id)
+            };
+            executions.push(OrderExecution {
+                instance_tag: self.instance_tag,
+                product_id: order.product_id,
+                buy_order_id,
+                sell_order_id,
+                price: self.trade_price(order, resting),
+                quantity: qty,
+                trade_time_network: 0,
+                internal_match_time: saturating_duration_u32(start_time, self.timer.ns() as u64),
+                is_mocked_result: true,
+                aggressor_side: if is_buy { ORDER_TYPE_BUY } else { ORDER_TYPE_SELL },
+                clock_skew_detected: false,
+                buy_client_ref: if is_buy { order.client_ref } else { resting.client_ref },
+                sell_client_ref: if is_buy { resting.client_ref } else { order.client_ref },
+            });
+        }
+        (remaining, executions)
+    }
+
+    /// Opposite-side quantity the order could trade against, counted up to the order's
+    /// own quantity. All-or-none orders count only when they fit in what is left.
+    /// Slippage limits are not applied.
+    fn fillable_quantity(&self, order: &Order) -> u32 {
+        // the level of the limit may hold orders either side of it, so the walk stops
+        // after that level and leaves out the ones past the limit
+        let limit_idx = self.limit_index(order);
         if order.is_buy() {
-            self.match_buy(&mut order);
+            let resting = self
+                .iter_asks()
+                .take_while(|o| self.limit_index(o) <= limit_idx)
+                .filter(|o| !Self::past_limit(order, o.price));
+            Self::take_fillable(resting, order.quantity)
         } else {
-            self.match_sell(&mut order);
+            let resting = self
+                .iter_bids()
+                .take_while(|o| self.limit_index(o) >= limit_idx)
+                .filter(|o| !Self::past_limit(order, o.price));
+            Self::take_fillable(resting, order.quantity)
         }
+    }
 
-        if order.quantity > 0 && order.price_type == ORDER_PRICE_TYPE_LIMIT {
-            self.add_order(order);
+    fn take_fillable<'a>(resting: impl Iterator<Item = &'a Order>, quantity: u32) -> u32 {
+        let mut remaining = quantity;
+        for o in resting {
+            if remaining == 0 {
+                break;
+            }
+            if o.aon && o.quantity > remaining {
+                continue;
+            }
+            remaining -= o.quantity.min(remaining);
         }
-        
-        self.match_result.end_time = self.timer.ns() as u64;
+        quantity - remaining
+    }
+
+    /// Price a market order trades at against a resting market order: the last trade,
+    /// or before any trade the configured reference price.
+    pub fn market_reference_price(&self) -> Option<i64> {
+        self.session.ohlc.map(|ohlc| ohlc.close).or(self.reference_price)
+    }
+
+    /// Lowest and highest price continuous trading may execute at: `price_band_ticks`
+    /// either side of the price the last auction crossed at, or of the last trade when
+    /// no auction has crossed yet. `None` without a band or anything to anchor it.
+    pub fn price_band(&self) -> Option<(i64, i64)> {
+        let width = (self.price_band_ticks? as i128 * self.tick as i128).min(i64::MAX as i128) as i64;
+        let reference = self.band_reference.or(self.session.ohlc.map(|ohlc| ohlc.close))?;
+        Some((reference.saturating_sub(width), reference.saturating_add(width)))
+    }
+
+    /// Whether `order` would execute outside the price band. Mock orders never move the
+    /// real price, so they are never outside it.
+    pub fn breaks_price_band(&self, order: &Order) -> bool {
+        let Some((low, high)) = self.price_band() else {
+            return false;
+        };
+        if order.is_mocked_order() {
+            return false;
+        }
+        let (_, executions) = self.mock_match(order);
+        executions.iter().any(|execution| execution.price < low || execution.price > high)
+    }
+
+    /// Whether market order `order` would meet a resting market order first. Market
+    /// orders never rest through matching; one can only arrive by a hand-made book.
+    pub fn meets_resting_market(&self, order: &Order) -> bool {
+        let best = if order.is_buy() { self.iter_asks().next() } else { self.iter_bids().next() };
+        order.price_type == ORDER_PRICE_TYPE_MARKET && best.is_some_and(|resting| resting.price_type == ORDER_PRICE_TYPE_MARKET)
+    }
+
+    /// Whether `order` would cross a resting quote of its own trader: a bid at or above
+    /// its price for a sell, an ask at or below it for a buy, any opposite quote for a
+    /// market order. `ignore` is left out, for the order a replace cancels. Anonymous
+    /// orders (trader 0) never self-cross.
+    pub fn crosses_own_quote(&self, order: &Order, ignore: Option<u64>) -> bool {
+        if order.trader_id == 0 {
+            return false;
+        }
+        let is_market = order.price_type != ORDER_PRICE_TYPE_LIMIT;
+        let own = |o: &Order| o.trader_id == order.trader_id && Some(o.order_id) != ignore;
+        if order.is_buy() {
+            self.iter_asks().take_while(|o| is_market || o.price <= order.price).any(own)
+        } else {
+            self.iter_bids().take_while(|o| is_market || o.price >= order.price).any(own)
+        }
+    }
+
+    // ----------------------------
+    // Resting order iteration
+    // ----------------------------
+    /// Resting bids in price-time priority: best price first, arrival order within a
+    /// price. Walks the ladder in place, so the book stays borrowed while iterating
+    /// and no match can run meanwhile.
+    pub fn iter_bids(&self) -> impl Iterator<Item = &Order> {
+        let top = (self.best_bid + 1).clamp(0, self.levels as isize) as usize;
+        self.bids[..top].iter().rev().flat_map(|bucket| bucket.orders.iter())
+    }
+
+    /// Resting asks in price-time priority: best price first, arrival order within a
+    /// price. Borrows the book like `iter_bids`.
+    pub fn iter_asks(&self) -> impl Iterator<Item = &Order> {
+        let start = self.best_ask.clamp(0, self.levels as isize) as usize;
+        self.asks[start..].iter().flat_map(|bucket| bucket.orders.iter())
     }
 
     // ----------------------------
     // BUY vs ASK
     // ----------------------------
     fn match_buy(&mut self, order: &mut Order) {
-        while order.quantity > 0 && self.best_ask <= self.best_bid {
-            let idx = self.best_ask as usize;
-            let bucket = &mut self.asks[idx];
-
-            if bucket.orders.is_empty() {
+        let mut limit_idx = self.limit_index(order);
+        let mut has_filled = false;
+        while order.quantity > 0 && self.best_ask <= limit_idx {
+            if self.asks[self.best_ask as usize].is_empty() {
                 self.best_ask += 1;
                 continue;
             }
 
-            let resting = bucket.orders.front_mut().unwrap();
-
-            if order.price_type == ORDER_PRICE_TYPE_LIMIT && order.price < resting.price {
+            // all-or-none orders too large to take whole stay put while later ones trade
+            let range = self.best_ask as usize..=limit_idx as usize;
+            let next = match self.priority_mode {
+                PriorityMode::PriceTime => Self::best_fillable(&self.asks, range, order.quantity, &|_, price| Self::past_limit(order, price)),
+                PriorityMode::TimePrice => Self::earliest_fillable(&self.asks, range, order.quantity, &|_, price| Self::past_limit(order, price)),
+            };
+            let Some((idx, pos)) = next else {
                 break;
+            };
+            if !has_filled {
+                has_filled = true;
+                self.apply_slippage_limit(order, idx, &mut limit_idx);
             }
+            let trade_price = self.trade_price(order, &self.asks[idx].orders[pos]);
+            self.mark_depth_dirty(false);
+            let bucket = &mut self.asks[idx];
 
-            let qty = order.quantity.min(resting.quantity);
+            let qty = order.quantity.min(bucket.orders[pos].quantity);
             order.quantity -= qty;
-            resting.quantity -= qty;
+            let left = bucket.fill(pos, qty);
+            let resting = &bucket.orders[pos];
             self.total_ask_volumn -= qty;
 
             self.match_result.order_execution_list.push(OrderExecution {
-                instance_tag: [0; 16],
+                instance_tag: self.instance_tag,
                 product_id: order.product_id,
                 buy_order_id: order.order_id,
                 sell_order_id: resting.order_id,
-                price: resting.price,
+                price: trade_price,
                 quantity: qty,
                 trade_time_network: 0,
-                internal_match_time: 0,
+                // time from the start of this match to the moment the fill was produced
+                internal_match_time: saturating_duration_u32(
+                    self.match_result.start_time,
+                    self.timer.ns() as u64,
+                ),
                 is_mocked_result: order.is_mocked_order(),
+                aggressor_side: ORDER_TYPE_BUY,
+                clock_skew_detected: false,
+                buy_client_ref: order.client_ref,
+                sell_client_ref: resting.client_ref,
             });
+            if !order.is_mocked_order() {
+                self.session.record(trade_price, qty);
+            }
 
-            if resting.quantity == 0 {
-                let o = bucket.orders.pop_front().unwrap();
+            if left == 0 {
+                let o = bucket.remove(pos).unwrap();
                 self.order_map.remove(&o.order_id);
+                self.filled_orders.record(o.order_id);
+                self.ask_order_count -= 1;
             }
         }
     }
@@ -137,53 +798,575 @@ impl ContinuousOrderBook {
     // SELL vs BID
     // ----------------------------
     fn match_sell(&mut self, order: &mut Order) {
-        while order.quantity > 0 && self.best_bid >= self.best_ask {
-            let idx = self.best_bid as usize;
-            let bucket = &mut self.bids[idx];
-
-            if bucket.orders.is_empty() {
+        let mut limit_idx = self.limit_index(order);
+        let mut has_filled = false;
+        while order.quantity > 0 && self.best_bid >= limit_idx {
+            if self.bids[self.best_bid as usize].is_empty() {
                 self.best_bid -= 1;
                 continue;
             }
 
-            let resting = bucket.orders.front_mut().unwrap();
-
-            if order.price_type == ORDER_PRICE_TYPE_LIMIT && order.price > resting.price {
+            // all-or-none orders too large to take whole stay put while later ones trade
+            let range = (limit_idx as usize..=self.best_bid as usize).rev();
+            let next = match self.priority_mode {
+                PriorityMode::PriceTime => Self::best_fillable(&self.bids, range, order.quantity, &|_, price| Self::past_limit(order, price)),
+                PriorityMode::TimePrice => Self::earliest_fillable(&self.bids, range, order.quantity, &|_, price| Self::past_limit(order, price)),
+            };
+            let Some((idx, pos)) = next else {
                 break;
+            };
+            if !has_filled {
+                has_filled = true;
+                self.apply_slippage_limit(order, idx, &mut limit_idx);
             }
+            let trade_price = self.trade_price(order, &self.bids[idx].orders[pos]);
+            self.mark_depth_dirty(true);
+            let bucket = &mut self.bids[idx];
 
-            let qty = order.quantity.min(resting.quantity);
+            let qty = order.quantity.min(bucket.orders[pos].quantity);
             order.quantity -= qty;
-            resting.quantity -= qty;
+            let left = bucket.fill(pos, qty);
+            let resting = &bucket.orders[pos];
             self.total_bid_volumn -= qty;
 
             self.match_result.order_execution_list.push(OrderExecution {
-                instance_tag: [0; 16],
+                instance_tag: self.instance_tag,
                 product_id: order.product_id,
                 buy_order_id: resting.order_id,
                 sell_order_id: order.order_id,
-                price: resting.price,
+                price: trade_price,
                 quantity: qty,
                 trade_time_network: 0,
-                internal_match_time: 0,
+                // time from the start of this match to the moment the fill was produced
+                internal_match_time: saturating_duration_u32(
+                    self.match_result.start_time,
+                    self.timer.ns() as u64,
+                ),
                 is_mocked_result: order.is_mocked_order(),
+                aggressor_side: ORDER_TYPE_SELL,
+                clock_skew_detected: false,
+                buy_client_ref: resting.client_ref,
+                sell_client_ref: order.client_ref,
             });
+            if !order.is_mocked_order() {
+                self.session.record(trade_price, qty);
+            }
 
-            if resting.quantity == 0 {
-                let o = bucket.orders.pop_front().unwrap();
+            if left == 0 {
+                let o = bucket.remove(pos).unwrap();
                 self.order_map.remove(&o.order_id);
+                self.filled_orders.record(o.order_id);
+                self.bid_order_count -= 1;
             }
         }
     }
 
+    /// Starts a new OHLC period at session rollover; the next trade opens it.
+    pub fn reset_ohlc(&mut self) {
+        self.session.ohlc = None;
+    }
+
+    // ----------------------------
+    // Auction handoff
+    // ----------------------------
+    /// Removes every resting order, bids then asks, each side in price-time priority,
+    /// and leaves the book empty.
+    pub fn drain_resting_orders(&mut self) -> Vec<Order> {
+        let mut orders = Vec::with_capacity(self.order_map.len());
+        let top = (self.best_bid + 1).clamp(0, self.levels as isize) as usize;
+        for bucket in self.bids[..top].iter_mut().rev() {
+            orders.extend(bucket.drain());
+        }
+        let start = self.best_ask.clamp(0, self.levels as isize) as usize;
+        for bucket in self.asks[start..].iter_mut() {
+            orders.extend(bucket.drain());
+        }
+
+        self.order_map.clear();
+        self.mark_depth_dirty(true);
+        self.mark_depth_dirty(false);
+        self.best_bid = -1;
+        self.best_ask = self.levels as isize;
+        self.total_bid_volumn = 0;
+        self.total_ask_volumn = 0;
+        self.bid_order_count = 0;
+        self.ask_order_count = 0;
+        self.update_book_state();
+        orders
+    }
+
+    /// Uncrosses the resting orders together with those already collected in `pool`,
+    /// as in an intraday volatility auction, then rests every residual again.
+    ///
+    /// Book orders come back with their order ids, reduced quantities and queue
+    /// positions; limit residuals that only lived in the pool rest behind them in
+    /// arrival order, and the pool is left empty. For limit orders quantity is
+    /// conserved: what rests afterwards plus twice the executed quantity is what
+    /// rested and pooled before.
+    ///
+    /// An auction refused for its imbalance puts the book orders back in their queue
+    /// positions, leaves the pooled ones in `pool` and returns the extension.
+    pub fn run_auction(
+        &mut self,
+        pool: &mut CallAuctionPool,
+        product_id: u16,
+    ) -> Result<(MatchResult, AuctionSummary), AuctionExtended> {
+        let start_ts = current_timestamp();
+        let book_orders = self.drain_resting_orders();
+        for order in &book_orders {
+            pool.add_order(order.clone());
+        }
+
+        let (result, summary) = match pool.execute_auction(
+            self.tick,
+            self.base_price,
+            self.instance_tag,
+            product_id,
+            start_ts,
+            &self.auction_rules,
+        ) {
+            Ok(uncrossed) => uncrossed,
+            Err(extended) => {
+                let book_ids: AHashSet<u64> = book_orders.iter().map(|o| o.order_id).collect();
+                pool.bids.retain(|o| !book_ids.contains(&o.order_id));
+                pool.asks.retain(|o| !book_ids.contains(&o.order_id));
+                for order in book_orders {
+                    self.add_order(order);
+                }
+                self.update_book_state();
+                return Err(extended);
+            }
+        };
+
+        for execution in result.order_execution_list.iter().filter(|e| !e.is_mocked_result) {
+            self.session.record(execution.price, execution.quantity);
+        }
+        if summary.matched_volume > 0 {
+            self.band_reference = Some(summary.price);
+        }
+
+        let mut residuals: AHashMap<u64, Order> = pool
+            .bids
+            .drain(..)
+            .chain(pool.asks.drain(..))
+            .map(|o| (o.order_id, o))
+            .collect();
+        for order in &book_orders {
+            match residuals.remove(&order.order_id) {
+                Some(residual) => self.add_order(residual),
+                None => self.filled_orders.record(order.order_id),
+            }
+        }
+        let mut pool_only: Vec<Order> = residuals
+            .into_values()
+            .filter(|o| o.rests_residual() && self.price_in_range(o.price))
+            .collect();
+        pool_only.sort_by_key(|o| o.submit_time);
+        for order in pool_only {
+            self.add_order(order);
+        }
+
+        self.update_book_state();
+        Ok((result, summary))
+    }
+
+    // ----------------------------
+    // Depth (L2)
+    // ----------------------------
+    /// Returns up to `n` non-empty levels per side as `(bids, asks)`, best first.
+    ///
+    /// Public views leave `include_hidden` off and see only the displayed peak of each
+    /// iceberg order; internal views turn it on for the full resting quantity. The
+    /// displayed depth of a side unchanged since it was last walked for at least `n`
+    /// levels is read from the cache instead.
+    pub fn depth(&self, n: usize, include_hidden: bool) -> (Vec<DepthLevel>, Vec<DepthLevel>) {
+        (self.side_depth(true, n, include_hidden), self.side_depth(false, n, include_hidden))
+    }
+
+    fn side_depth(&self, is_buy: bool, n: usize, include_hidden: bool) -> Vec<DepthLevel> {
+        let cache = if is_buy { &self.depth_cache.bids } else { &self.depth_cache.asks };
+        if !include_hidden
+            && let Some((walked, levels)) = cache.borrow().as_ref()
+            && (n <= *walked || levels.len() < *walked)
+        {
+            return levels.iter().take(n).cloned().collect();
+        }
+
+        self.depth_cache.walks.set(self.depth_cache.walks.get() + 1);
+        let mut levels = Vec::with_capacity(n);
+        if is_buy {
+            let mut idx = self.best_bid;
+            while idx >= 0 && levels.len() < n {
+                if let Some(level) = Self::level_of(&self.bids[idx as usize], include_hidden) {
+                    levels.push(level);
+                }
+                idx -= 1;
+            }
+        } else {
+            let mut idx = self.best_ask.max(0) as usize;
+            while idx < self.levels && levels.len() < n {
+                if let Some(level) = Self::level_of(&self.asks[idx], include_hidden) {
+                    levels.push(level);
+                }
+                idx += 1;
+            }
+        }
+        if !include_hidden {
+            *cache.borrow_mut() = Some((n, levels.clone()));
+        }
+        levels
+    }
+
+    /// Drops the cached depth of one side; called on every change to its levels.
+    #[inline(always)]
+    fn mark_depth_dirty(&self, is_buy: bool) {
+        let cache = if is_buy { &self.depth_cache.bids } else { &self.depth_cache.asks };
+        cache.take();
+    }
+
+    /// Captures best prices, the top `n` displayed levels per side and the side and
+    /// session totals at one instant. The view holds copies, so matching afterwards leaves it as it
+    /// was; only the top `n` levels are copied, not the book.
+    pub fn snapshot_view(&self, n: usize) -> BookView {
+        let (bids, asks) = self.depth(n, false);
+        let (best_bid, best_ask) = self.best_prices();
+        BookView {
+            best_bid,
+            best_ask,
+            bids: bids.into(),
+            asks: asks.into(),
+            bid_order_count: self.bid_order_count,
+            ask_order_count: self.ask_order_count,
+            total_bid_volume: self.total_bid_volumn,
+            total_ask_volume: self.total_ask_volumn,
+            session: self.session,
+        }
+    }
+
+    /// Best resting bid and ask prices, `None` for an empty side. Levels span a whole
+    /// tick, so the best price within the best non-empty level is taken.
+    pub fn best_prices(&self) -> (Option<i64>, Option<i64>) {
+        let top = (self.best_bid + 1).clamp(0, self.levels as isize) as usize;
+        let bid = self.bids[..top]
+            .iter()
+            .rev()
+            .find(|bucket| !bucket.orders.is_empty())
+            .and_then(|bucket| bucket.orders.iter().map(|o| o.price).max());
+        let start = self.best_ask.clamp(0, self.levels as isize) as usize;
+        let ask = self.asks[start..]
+            .iter()
+            .find(|bucket| !bucket.orders.is_empty())
+            .and_then(|bucket| bucket.orders.iter().map(|o| o.price).min());
+        (bid, ask)
+    }
+
+    /// Whether the best bid is at or above the best ask; a locked book counts as crossed.
+    /// Matching leaves neither behind, so outside resting all-or-none orders that could
+    /// not trade whole, `true` means the book is inconsistent.
+    pub fn is_crossed(&self) -> bool {
+        matches!(self.best_prices(), (Some(bid), Some(ask)) if bid >= ask)
+    }
+
+    /// Whether the best bid equals the best ask.
+    pub fn is_locked(&self) -> bool {
+        matches!(self.best_prices(), (Some(bid), Some(ask)) if bid == ask)
+    }
+
+    /// Stable 64-bit FNV-1a hash of `depth(n, false)`: each level's price and quantity, bids
+    /// then asks, with the level counts so an empty side cannot collide with the other.
+    /// The same top `n` always hashes the same, across runs and builds, so a replica
+    /// rebuilding the book from the feed can compare it with the primary's.
+    pub fn top_of_book_digest(&self, n: usize) -> u64 {
+        const FNV_OFFSET: u64 = 0xcbf2_9ce4_8422_2325;
+        const FNV_PRIME: u64 = 0x0000_0100_0000_01b3;
+        let (bids, asks) = self.depth(n, false);
+        let mut hash = FNV_OFFSET;
+        let mut feed = |bytes: &[u8]| {
+            for &b in bytes {
+                hash = (hash ^ b as u64).wrapping_mul(FNV_PRIME);
+            }
+        };
+        for side in [&bids, &asks] {
+            feed(&(side.len() as u32).to_be_bytes());
+            for level in side {
+                feed(&level.price.to_be_bytes());
+                feed(&level.quantity.to_be_bytes());
+            }
+        }
+        hash
+    }
+
+    fn level_of(bucket: &OrdersBucket, include_hidden: bool) -> Option<DepthLevel> {
+        let front = bucket.orders.front()?;
+        Some(DepthLevel {
+            price: front.price,
+            quantity: if include_hidden { bucket.quantity() } else { bucket.displayed_quantity() },
+        })
+    }
+
+    // ----------------------------
+    // Memory
+    // ----------------------------
+    /// Estimated heap bytes held by the book, from allocated capacity rather than
+    /// length, so room left behind by a burst of since-cancelled orders shows. Walks
+    /// every level of both ladders.
+    pub fn memory_footprint(&self) -> usize {
+        let ladders = (self.bids.capacity() + self.asks.capacity()) * size_of::<OrdersBucket>();
+        let queues: usize = self
+            .bids
+            .iter()
+            .chain(self.asks.iter())
+            .map(OrdersBucket::memory_footprint)
+            .sum();
+        // one control byte per hash map slot on top of key and value
+        let order_map = self.order_map.capacity() * (size_of::<(u64, (bool, usize))>() + 1);
+        let executions = self.match_result.order_execution_list.capacity() * size_of::<OrderExecution>();
+        let mock_book = self.mock_book.as_ref().map_or(0, |book| size_of::<Self>() + book.memory_footprint());
+        ladders + queues + order_map + executions + self.filled_orders.memory_footprint() + mock_book
+    }
+
+    /// Releases spare capacity: every level queue down to its orders, the id maps down
+    /// to `compact_floor` orders or their contents if larger. Resting orders keep their
+    /// levels and queue positions, so `order_map` stays valid; the ladders themselves
+    /// have a fixed length.
+    pub fn compact(&mut self) {
+        for bucket in self.bids.iter_mut().chain(self.asks.iter_mut()) {
+            bucket.shrink_to_fit();
+        }
+        self.order_map.shrink_to(self.compact_floor);
+        self.filled_orders.shrink_to(self.compact_floor);
+        // entries of GTD orders that already left the book
+        let order_map = &self.order_map;
+        self.gtd_expiries.retain(|Reverse((_, order_id))| order_map.contains_key(order_id));
+        self.gtd_expiries.shrink_to_fit();
+        if let Some(book) = self.mock_book.as_mut() {
+            book.compact();
+        }
+    }
+
+    /// Faults in the memory resting orders will use before trading starts, so the first
+    /// orders do not pay for it, and returns the order capacity prefaulted.
+    ///
+    /// The id map and the filled-order memory are sized for `max_orders_per_side` on
+    /// both sides, or `compact_floor` orders without a cap, never above
+    /// `MAX_PREFAULT_ORDERS`. They are filled with placeholders and emptied again,
+    /// keeping their capacity. The level queues are not prefaulted: each still
+    /// allocates when its first order arrives, since reserving room at every level of
+    /// both ladders would take far more memory than the book ever uses. A book that
+    /// already holds orders is left alone. Compaction gives back whatever is above
+    /// `compact_floor`.
+    pub fn prefault(&mut self) -> usize {
+        if !self.order_map.is_empty() {
+            return 0;
+        }
+        let capacity = self
+            .max_orders_per_side
+            .map_or(self.compact_floor, |max| 2 * max as usize)
+            .min(MAX_PREFAULT_ORDERS);
+        self.order_map.reserve(capacity);
+        for id in 0..capacity as u64 {
+            self.order_map.insert(id, (true, 0));
+        }
+        self.order_map.clear();
+        self.filled_orders.prefault(capacity.min(FILLED_ORDER_MEMORY));
+        capacity
+    }
+
+    /// Whether the id map holds room for more than four times what it needs, the sign
+    /// a burst has passed and `compact` is worth its walk over the ladders.
+    pub fn is_overgrown(&self) -> bool {
+        self.order_map.capacity() > 4 * self.order_map.len().max(self.compact_floor)
+    }
+
+    // ----------------------------
+    // Order lookup
+    // ----------------------------
+    /// Current state of a resting order, or `None` once it is filled or cancelled.
+    pub fn get_order(&self, order_id: u64) -> Option<OrderView> {
+        let &(is_buy, idx) = self.order_map.get(&order_id)?;
+        let bucket = if is_buy { &self.bids[idx] } else { &self.asks[idx] };
+        bucket
+            .position_of(order_id)
+            .map(|pos| &bucket.orders[pos])
+            .map(|o| OrderView {
+                order_type: o.order_type,
+                price: o.price,
+                remaining_quantity: o.quantity,
+                submit_time: o.submit_time,
+                client_ref: o.client_ref,
+            })
+    }
+
+    // ----------------------------
+    // Cancel / replace
+    // ----------------------------
+    /// Cancels `old_id` and matches `new_order` in its place as one step.
+    ///
+    /// The replacement is validated before anything is removed, so on `Err` the old
+    /// order is still resting exactly where it was, queue position included. That
+    /// covers room for the residual too: the replacement rests on the side the old
+    /// order leaves, which only lacks a free slot when the side was already over
+    /// `max_orders_per_side` (a snapshot taken under a higher limit).
+    pub fn cancel_replace(&mut self, old_id: u64, new_order: Order) -> Result<(), RejectReason> {
+        let &(is_buy, _) = self.order_map.get(&old_id).ok_or(RejectReason::ReplaceRejected)?;
+
+        // a mock replacement would rest in the mock book, not in the freed slot
+        if new_order.is_mocked_order() || new_order.is_buy() != is_buy || new_order.quantity == 0 {
+            return Err(RejectReason::ReplaceRejected);
+        }
+        if new_order.rests_residual() && !self.price_in_range(new_order.price) {
+            return Err(RejectReason::PriceOutOfRange);
+        }
+        if new_order.order_id != old_id && self.order_map.contains_key(&new_order.order_id) {
+            return Err(RejectReason::ReplaceRejected);
+        }
+
+        let count = if is_buy { self.bid_order_count } else { self.ask_order_count };
+        let full_without_old = self.max_orders_per_side.is_some_and(|max| count > max);
+        if new_order.rests_residual() && full_without_old {
+            let evictable = self.book_full_policy == BookFullPolicy::EvictWorst
+                && self
+                    .worst_order_id(is_buy, new_order.price)
+                    .is_some_and(|worst_id| worst_id != old_id);
+            if !evictable {
+                return Err(RejectReason::BookFull);
+            }
+        }
+
+        self.cancel_order(old_id);
+        self.match_order(new_order);
+        Ok(())
+    }
+
     // ----------------------------
     // Cancel order (O(1))
     // ----------------------------
-    pub fn cancel_order(&mut self, order_id: u64) -> bool {
-        let (is_buy, idx) = match self.order_map.remove(&order_id) {
-            Some(v) => v,
-            None => return false,
+    /// Removes a resting order, returning the quantity it still had; `None` when the
+    /// id does not rest here. Mock orders resting in the mock book are found too.
+    pub fn cancel_order(&mut self, order_id: u64) -> Option<u32> {
+        let Some(removed) = self.remove_order(order_id) else {
+            return self.mock_book.as_mut()?.cancel_order(order_id);
         };
+        self.update_book_state();
+        Some(removed.quantity)
+    }
+
+    /// Whether `order_id` is among the book's, or the mock book's, recently filled orders.
+    pub fn was_filled(&self, order_id: u64) -> bool {
+        self.filled_orders.contains(order_id) || self.mock_book.as_ref().is_some_and(|book| book.was_filled(order_id))
+    }
+
+    /// Cancels every resting order of `trader_id` on one side at exactly `price`,
+    /// returning how many were removed; 0 when none of them rests there. Other
+    /// traders' orders, and orders sharing the tick bucket at another price, are kept.
+    /// `on_cancel` sees each removed order, for its cancel notice.
+    pub fn cancel_at_price(
+        &mut self,
+        is_buy: bool,
+        price: i64,
+        trader_id: u32,
+        on_cancel: &mut impl FnMut(&Order),
+    ) -> usize {
+        if !self.price_in_range(price) {
+            return 0;
+        }
+        let idx = self.price_to_index(price);
+        let bucket = if is_buy { &mut self.bids[idx] } else { &mut self.asks[idx] };
+
+        let mut removed = Vec::new();
+        bucket.retain(|o| {
+            if o.price != price || o.trader_id != trader_id {
+                return true;
+            }
+            removed.push(o.clone());
+            false
+        });
+
+        if !removed.is_empty() {
+            self.mark_depth_dirty(is_buy);
+        }
+        for order in &removed {
+            self.order_map.remove(&order.order_id);
+            if is_buy {
+                self.total_bid_volumn -= order.quantity;
+                self.bid_order_count -= 1;
+            } else {
+                self.total_ask_volumn -= order.quantity;
+                self.ask_order_count -= 1;
+            }
+            on_cancel(order);
+        }
+        self.update_book_state();
+        removed.len()
+    }
+
+    /// Cancels every resting DAY order at the end of the session, in the mock book too,
+    /// and returns how many were removed. `on_cancel` sees each removed order, for its
+    /// cancel notice. GTC and GTD orders keep their queue positions; the id map and
+    /// side totals are brought up to date once, after both ladders are swept.
+    pub fn cancel_day_orders(&mut self, on_cancel: &mut impl FnMut(&Order)) -> usize {
+        let mut removed = Vec::new();
+        let top = (self.best_bid + 1).clamp(0, self.levels as isize) as usize;
+        let start = self.best_ask.clamp(0, self.levels as isize) as usize;
+        for bucket in self.bids[..top].iter_mut().chain(self.asks[start..].iter_mut()) {
+            bucket.retain(|o| {
+                if o.time_in_force != TIF_DAY {
+                    return true;
+                }
+                removed.push(o.clone());
+                false
+            });
+        }
+
+        for order in &removed {
+            self.order_map.remove(&order.order_id);
+            self.mark_depth_dirty(order.is_buy());
+            if order.is_buy() {
+                self.total_bid_volumn -= order.quantity;
+                self.bid_order_count -= 1;
+            } else {
+                self.total_ask_volumn -= order.quantity;
+                self.ask_order_count -= 1;
+            }
+            on_cancel(order);
+        }
+        self.update_book_state();
+
+        let mock_removed = self.mock_book.as_mut().map_or(0, |book| book.cancel_day_orders(on_cancel));
+        removed.len() + mock_removed
+    }
+
+    /// Removes every resting GTD order whose `expire_time` is at or before `now`, in the
+    /// mock book too, and returns how many were removed. `on_expire` sees each removed
+    /// order, for its expiry notice. Costs a heap peek when nothing is due.
+    pub fn expire_orders(&mut self, now: u64, on_expire: &mut impl FnMut(&Order)) -> usize {
+        let mut expired = 0;
+        while let Some(&Reverse((expire_time, order_id))) = self.gtd_expiries.peek() {
+            if expire_time > now {
+                break;
+            }
+            self.gtd_expiries.pop();
+            // the id may since have been filled, cancelled or replaced by a later order
+            let still_due = self.order_map.get(&order_id).is_some_and(|&(is_buy, idx)| {
+                let bucket = if is_buy { &self.bids[idx] } else { &self.asks[idx] };
+                bucket.orders.iter().any(|o| {
+                    o.order_id == order_id && o.time_in_force == TIF_GTD && o.expire_time == expire_time
+                })
+            });
+            if still_due && let Some(order) = self.remove_order(order_id) {
+                on_expire(&order);
+                expired += 1;
+            }
+        }
+        if expired > 0 {
+            self.update_book_state();
+        }
+        expired + self.mock_book.as_mut().map_or(0, |book| book.expire_orders(now, on_expire))
+    }
+
+    /// Takes a resting order out of the book and returns it.
+    fn remove_order(&mut self, order_id: u64) -> Option<Order> {
+        let (is_buy, idx) = self.order_map.remove(&order_id)?;
 
         let bucket = if is_buy {
             &mut self.bids[idx]
@@ -191,15 +1374,415 @@ impl ContinuousOrderBook {
             &mut self.asks[idx]
         };
 
-        if let Some(pos) = bucket.orders.iter().position(|o| o.order_id == order_id) {
-            let o = bucket.orders.remove(pos).unwrap();
-            if is_buy {
-                self.total_bid_volumn -= o.quantity;
-            } else {
-                self.total_ask_volumn -= o.quantity;
+        let pos = bucket.position_of(order_id)?;
+        let o = bucket.remove(pos).unwrap();
+        self.mark_depth_dirty(is_buy);
+        if is_buy {
+            self.total_bid_volumn -= o.quantity;
+            self.bid_order_count -= 1;
+        } else {
+            self.total_ask_volumn -= o.quantity;
+            self.ask_order_count -= 1;
+        }
+        Some(o)
+    }
+}
+
+impl TradePriceRule for TradePriceMode {
+    /// In midpoint mode a limit aggressor that improves on the resting price splits the
+    /// difference; the midpoint is rounded onto the grid and never passes either order's
+    /// price. In reference mode the reference price is clamped the same way; without one
+    /// the resting price is used.
+    #[inline(always)]
+    fn price(&self, aggressor: &Order, resting: &Order, reference: Option<i64>, grid: PriceGrid) -> i64 {
+        let is_limit = aggressor.price_type == ORDER_PRICE_TYPE_LIMIT;
+        match self {
+            TradePriceMode::Resting => resting.price,
+            TradePriceMode::Aggressor => if is_limit { aggressor.price } else { resting.price },
+            TradePriceMode::Reference => {
+                let Some(reference) = reference else {
+                    return resting.price;
+                };
+                // a market aggressor has no limit, so only the resting price bounds it
+                let (low, high) = match (aggressor.is_buy(), is_limit) {
+                    (true, true) => (resting.price, aggressor.price),
+                    (true, false) => (resting.price, i64::MAX),
+                    (false, true) => (aggressor.price, resting.price),
+                    (false, false) => (i64::MIN, resting.price),
+                };
+                reference.clamp(low, high)
+            }
+            TradePriceMode::Midpoint => {
+                if !is_limit || aggressor.price == resting.price {
+                    return resting.price;
+                }
+                // rounded on the doubled grid so a midpoint between two units stays exact
+                let mid = round_to_tick(aggressor.price + resting.price, 2 * grid.origin, 2 * grid.tick, grid.rounding) / 2;
+                mid.clamp(aggressor.price.min(resting.price), aggressor.price.max(resting.price))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const GRID: PriceGrid = PriceGrid {
+        tick: 10,
+        origin: 0,
+        rounding: PriceRounding::HalfUp,
+    };
+
+    fn order(order_type: u8, price_type: u8, price: i64) -> Order {
+        Order {
+            product_id: 1,
+            order_type,
+            price_type,
+            quantity: 10,
+            order_id: 1,
+            price,
+            submit_time: 0,
+            expire_time: 0,
+            time_in_force: TIF_GTC,
+            min_qty: 0,
+            aon: false,
+            trader_id: 0,
+            client_ref: [0; 8],
+            display_quantity: 0,
+        }
+    }
+
+    fn buy_limit(price: i64) -> Order {
+        order(ORDER_TYPE_BUY, ORDER_PRICE_TYPE_LIMIT, price)
+    }
+
+    fn market(order_type: u8) -> Order {
+        order(order_type, ORDER_PRICE_TYPE_MARKET, 0)
+    }
+
+    #[test]
+    fn resting_rule_takes_the_resting_price() {
+        let resting = order(ORDER_TYPE_SELL, ORDER_PRICE_TYPE_LIMIT, 100);
+        assert_eq!(TradePriceMode::Resting.price(&buy_limit(120), &resting, Some(110), GRID), 100);
+        assert_eq!(TradePriceMode::Resting.price(&market(ORDER_TYPE_BUY), &resting, None, GRID), 100);
+    }
+
+    #[test]
+    fn aggressor_rule_takes_the_aggressor_limit() {
+        let resting = order(ORDER_TYPE_SELL, ORDER_PRICE_TYPE_LIMIT, 100);
+        assert_eq!(TradePriceMode::Aggressor.price(&buy_limit(120), &resting, None, GRID), 120);
+        // a market order has no limit of its own
+        assert_eq!(TradePriceMode::Aggressor.price(&market(ORDER_TYPE_BUY), &resting, None, GRID), 100);
+    }
+
+    #[test]
+    fn midpoint_rule_splits_the_difference_on_the_grid() {
+        let resting = order(ORDER_TYPE_SELL, ORDER_PRICE_TYPE_LIMIT, 100);
+        assert_eq!(TradePriceMode::Midpoint.price(&buy_limit(120), &resting, None, GRID), 110);
+        assert_eq!(TradePriceMode::Midpoint.price(&buy_limit(100), &resting, None, GRID), 100);
+        assert_eq!(TradePriceMode::Midpoint.price(&market(ORDER_TYPE_BUY), &resting, None, GRID), 100);
+
+        // 105 lies halfway between two ticks
+        assert_eq!(TradePriceMode::Midpoint.price(&buy_limit(110), &resting, None, GRID), 110);
+        let half_even = PriceGrid {
+            rounding: PriceRounding::HalfEven,
+            ..GRID
+        };
+        assert_eq!(TradePriceMode::Midpoint.price(&buy_limit(110), &resting, None, half_even), 100);
+    }
+
+    #[test]
+    fn reference_rule_stays_between_both_prices() {
+        let resting = order(ORDER_TYPE_SELL, ORDER_PRICE_TYPE_LIMIT, 100);
+        assert_eq!(TradePriceMode::Reference.price(&buy_limit(120), &resting, Some(105), GRID), 105);
+        assert_eq!(TradePriceMode::Reference.price(&buy_limit(120), &resting, Some(150), GRID), 120);
+        assert_eq!(TradePriceMode::Reference.price(&buy_limit(120), &resting, Some(90), GRID), 100);
+        assert_eq!(TradePriceMode::Reference.price(&buy_limit(120), &resting, None, GRID), 100);
+
+        let resting = order(ORDER_TYPE_BUY, ORDER_PRICE_TYPE_LIMIT, 100);
+        assert_eq!(TradePriceMode::Reference.price(&market(ORDER_TYPE_SELL), &resting, Some(40), GRID), 40);
+        assert_eq!(TradePriceMode::Reference.price(&market(ORDER_TYPE_SELL), &resting, Some(150), GRID), 100);
+    }
+
+    /// A book of ten-unit levels from zero.
+    fn small_book(priority_mode: PriorityMode) -> ContinuousOrderBook {
+        let mut book = ContinuousOrderBook::new(10, 0, 100, 16);
+        book.priority_mode = priority_mode;
+        book
+    }
+
+    fn limit(order_id: u64, order_type: u8, price: i64, submit_time: u64) -> Order {
+        Order {
+            order_id,
+            submit_time,
+            ..order(order_type, ORDER_PRICE_TYPE_LIMIT, price)
+        }
+    }
+
+    fn sell_order_ids(book: &ContinuousOrderBook) -> Vec<u64> {
+        book.match_result.order_execution_list.iter().map(|execution| execution.sell_order_id).collect()
+    }
+
+    #[test]
+    fn priority_modes_fill_in_their_order_and_skip_orders_past_the_limit() {
+        let mut fills = Vec::new();
+        for priority_mode in [PriorityMode::PriceTime, PriorityMode::TimePrice] {
+            let mut book = small_book(priority_mode);
+            // the limit level 20..30 holds 28, past a buy limit of 27, queued first and oldest
+            for ask in [
+                limit(5, ORDER_TYPE_SELL, 28, 0),
+                limit(1, ORDER_TYPE_SELL, 25, 1),
+                limit(2, ORDER_TYPE_SELL, 12, 2),
+                limit(3, ORDER_TYPE_SELL, 18, 3),
+            ] {
+                book.fuel_order(ask);
+            }
+            book.match_order(Order { quantity: 100, ..limit(9, ORDER_TYPE_BUY, 27, 4) });
+            fills.push(sell_order_ids(&book));
+
+            assert!(book.get_order(5).is_some());
+            assert_eq!(book.get_order(9).map(|view| view.remaining_quantity), Some(70));
+        }
+        assert_eq!(fills, [vec![2, 3, 1], vec![1, 2, 3]]);
+    }
+
+    #[test]
+    fn partly_filled_limit_rests_at_the_top_of_its_own_side_in_either_book() {
+        let sides = [
+            (ORDER_TYPE_BUY, ORDER_TYPE_SELL, ORDER_TYPE_MOCK_BUY, ORDER_TYPE_MOCK_SELL),
+            (ORDER_TYPE_SELL, ORDER_TYPE_BUY, ORDER_TYPE_MOCK_SELL, ORDER_TYPE_MOCK_BUY),
+        ];
+        for (aggressor, resting, mock_aggressor, mock_resting) in sides {
+            let (resting_price, limit_price) = if aggressor == ORDER_TYPE_BUY { (20, 30) } else { (30, 20) };
+            for (aggressor, resting) in [(aggressor, resting), (mock_aggressor, mock_resting)] {
+                let mut book = small_book(PriorityMode::PriceTime);
+                book.mock_match_policy = MockMatchPolicy::MockOnly;
+                book.fuel_order(Order { quantity: 5, ..limit(1, resting, resting_price, 0) });
+                let order = Order { quantity: 8, ..limit(2, aggressor, limit_price, 1) };
+                book.match_order(order.clone());
+                assert_eq!(book.match_result.order_execution_list.len(), 1);
+
+                let held = book.resting_book(&order).unwrap();
+                let level = vec![DepthLevel { price: limit_price, quantity: 3 }];
+                if order.is_buy() {
+                    assert_eq!(held.best_bid, 3);
+                    assert!(held.best_ask > held.best_bid);
+                    assert_eq!(held.best_prices(), (Some(limit_price), None));
+                    assert_eq!(held.depth(1, false), (level, vec![]));
+                } else {
+                    assert_eq!(held.best_ask, 2);
+                    assert!(held.best_bid < held.best_ask);
+                    assert_eq!(held.best_prices(), (None, Some(limit_price)));
+                    assert_eq!(held.depth(1, false), (vec![], level));
+                }
+                assert_eq!(held.get_order(2).map(|view| view.remaining_quantity), Some(3));
             }
-            return true;
         }
-        false
+    }
+
+    #[test]
+    fn depth_is_walked_again_only_for_a_changed_side() {
+        let mut book = small_book(PriorityMode::PriceTime);
+        book.fuel_order(limit(1, ORDER_TYPE_BUY, 10, 0));
+        book.fuel_order(limit(2, ORDER_TYPE_BUY, 20, 1));
+        book.fuel_order(limit(3, ORDER_TYPE_SELL, 40, 2));
+
+        let first = book.depth(5, false);
+        assert_eq!(book.depth_cache.walks.get(), 2);
+        // unchanged, and fewer levels than the walk covered
+        assert_eq!(book.depth(5, false), first);
+        assert_eq!(*book.snapshot_view(1).bids, first.0[..1]);
+        book.top_of_book_digest(3);
+        assert_eq!(book.depth_cache.walks.get(), 2);
+        // more levels than were walked, on a side that had them all
+        book.depth(8, false);
+        assert_eq!(book.depth_cache.walks.get(), 2);
+
+        // a fill on the ask side leaves the bid side cached
+        book.match_order(Order { quantity: 4, ..limit(4, ORDER_TYPE_BUY, 40, 3) });
+        let (bids, asks) = book.depth(5, false);
+        assert_eq!(book.depth_cache.walks.get(), 3);
+        assert_eq!(bids, first.0);
+        assert_eq!(asks, [DepthLevel { price: 40, quantity: 6 }]);
+
+        book.cancel_order(2);
+        assert_eq!(book.depth(5, false).0, [DepthLevel { price: 10, quantity: 10 }]);
+        assert_eq!(book.depth_cache.walks.get(), 4);
+    }
+
+    #[test]
+    fn displayed_depth_leaves_out_the_iceberg_reserve() {
+        let mut book = small_book(PriorityMode::PriceTime);
+        book.fuel_order(Order { quantity: 100, display_quantity: 10, ..limit(1, ORDER_TYPE_SELL, 20, 0) });
+        book.fuel_order(Order { quantity: 5, ..limit(2, ORDER_TYPE_SELL, 20, 1) });
+        book.fuel_order(Order { quantity: 7, ..limit(3, ORDER_TYPE_SELL, 30, 2) });
+
+        let level = |price, quantity| DepthLevel { price, quantity };
+        let displayed = book.depth(5, false).1;
+        let full = book.depth(5, true).1;
+        assert_eq!(displayed, [level(20, 15), level(30, 7)]);
+        assert_eq!(full, [level(20, 105), level(30, 7)]);
+        assert_eq!(full[0].quantity - displayed[0].quantity, 90);
+        // the cached displayed depth is not handed out for a full one, nor the other way round
+        assert_eq!(book.depth(5, false).1, displayed);
+
+        // once less than the peak is left, all of it shows
+        book.match_order(Order { quantity: 96, ..limit(4, ORDER_TYPE_BUY, 20, 3) });
+        assert_eq!(book.depth(1, false).1, [level(20, 9)]);
+        assert_eq!(book.depth(1, true).1, [level(20, 9)]);
+    }
+
+    /// Buy id, sell id, price and quantity of one execution.
+    type Fill = (u64, u64, i64, u32);
+    /// Order id, price and quantity of one resting order.
+    type Resting = (u64, i64, u32);
+
+    /// Every fill and the resting orders left after `orders` went through a book of
+    /// `layout`, cancelling every fifth order id along the way.
+    fn run_layout(layout: BucketLayout, priority_mode: PriorityMode, orders: &[Order]) -> (Vec<Fill>, Vec<Resting>) {
+        let mut book = ContinuousOrderBook::new(1, 9_999_000, 2_001, 64);
+        book.priority_mode = priority_mode;
+        book.set_bucket_layout(layout);
+        let mut fills = Vec::new();
+        for order in orders {
+            book.match_order(order.clone());
+            fills.extend(
+                book.match_result
+                    .order_execution_list
+                    .iter()
+                    .map(|execution| (execution.buy_order_id, execution.sell_order_id, execution.price, execution.quantity)),
+            );
+            if order.order_id % 5 == 0 {
+                book.cancel_order(order.order_id - 2);
+            }
+        }
+        let resting = book
+            .iter_bids()
+            .chain(book.iter_asks())
+            .map(|order| (order.order_id, order.price, order.quantity))
+            .collect();
+        (fills, resting)
+    }
+
+    #[test]
+    fn soa_layout_matches_like_aos() {
+        let params = OrderGenParams {
+            price_spread: 1_000,
+            ..OrderGenParams::default()
+        };
+        let mut orders = crate::test_order_book_builder::generate_orders(5_000, 7, &params);
+        for order in orders.iter_mut().filter(|order| order.order_id % 7 == 0) {
+            order.aon = true;
+        }
+
+        for priority_mode in [PriorityMode::PriceTime, PriorityMode::TimePrice] {
+            let aos = run_layout(BucketLayout::Aos, priority_mode, &orders);
+            let soa = run_layout(BucketLayout::Soa, priority_mode, &orders);
+            assert!(!aos.0.is_empty() && !aos.1.is_empty());
+            assert_eq!(soa, aos);
+        }
+    }
+
+    fn ask_ids(book: &ContinuousOrderBook) -> Vec<u64> {
+        book.iter_asks().map(|order| order.order_id).collect()
+    }
+
+    /// A book of three asks queued at the same price 20, ids 1, 2, 3.
+    fn queued_asks() -> ContinuousOrderBook {
+        let mut book = small_book(PriorityMode::PriceTime);
+        for order_id in 1..=3 {
+            book.fuel_order(limit(order_id, ORDER_TYPE_SELL, 20, order_id));
+        }
+        book
+    }
+
+    #[test]
+    fn failed_replace_keeps_the_original_at_its_place_in_the_queue() {
+        let mut book = queued_asks();
+        let rejected = [
+            (limit(7, ORDER_TYPE_BUY, 20, 4), RejectReason::ReplaceRejected),
+            (limit(7, ORDER_TYPE_SELL, 5_000, 4), RejectReason::PriceOutOfRange),
+            (limit(3, ORDER_TYPE_SELL, 30, 4), RejectReason::ReplaceRejected),
+            (Order { quantity: 0, ..limit(7, ORDER_TYPE_SELL, 20, 4) }, RejectReason::ReplaceRejected),
+        ];
+        for (replacement, reason) in rejected {
+            assert_eq!(book.cancel_replace(1, replacement), Err(reason));
+            assert_eq!(ask_ids(&book), [1, 2, 3]);
+        }
+
+        book.match_order(limit(9, ORDER_TYPE_BUY, 20, 5));
+        assert_eq!(sell_order_ids(&book), [1]);
+    }
+
+    #[test]
+    fn replace_requeues_the_replacement_behind_its_level() {
+        let mut book = queued_asks();
+        assert_eq!(book.cancel_replace(1, Order { quantity: 4, ..limit(7, ORDER_TYPE_SELL, 20, 4) }), Ok(()));
+        assert!(book.get_order(1).is_none());
+        assert_eq!(ask_ids(&book), [2, 3, 7]);
+        assert_eq!(book.get_order(7).map(|view| view.remaining_quantity), Some(4));
+
+        // a replacement that crosses trades right away
+        book.fuel_order(limit(8, ORDER_TYPE_BUY, 10, 5));
+        assert_eq!(book.cancel_replace(7, limit(6, ORDER_TYPE_SELL, 10, 6)), Ok(()));
+        assert_eq!(sell_order_ids(&book), [6]);
+        assert_eq!(ask_ids(&book), [2, 3]);
+    }
+
+    #[test]
+    fn oversized_all_or_none_order_is_passed_over_in_place_until_it_fits() {
+        let mut book = small_book(PriorityMode::PriceTime);
+        book.fuel_order(Order { quantity: 30, aon: true, ..limit(1, ORDER_TYPE_SELL, 20, 1) });
+        book.fuel_order(limit(2, ORDER_TYPE_SELL, 20, 2));
+        book.fuel_order(limit(3, ORDER_TYPE_SELL, 30, 3));
+
+        book.match_order(Order { quantity: 15, ..limit(8, ORDER_TYPE_BUY, 30, 4) });
+        assert_eq!(sell_order_ids(&book), [2, 3]);
+        assert_eq!(ask_ids(&book), [1, 3]);
+        assert_eq!(book.get_order(1).map(|view| view.remaining_quantity), Some(30));
+
+        book.match_order(Order { quantity: 30, ..limit(9, ORDER_TYPE_BUY, 20, 5) });
+        assert_eq!(sell_order_ids(&book), [1]);
+        assert_eq!(book.match_result.order_execution_list[0].quantity, 30);
+        assert_eq!(ask_ids(&book), [3]);
+    }
+
+    #[test]
+    fn auction_conserves_quantity_and_order_ids() {
+        let mut book = small_book(PriorityMode::PriceTime);
+        let orders = [
+            Order { quantity: 30, ..limit(1, ORDER_TYPE_BUY, 40, 1) },
+            Order { quantity: 20, ..limit(2, ORDER_TYPE_BUY, 30, 2) },
+            Order { quantity: 25, ..limit(3, ORDER_TYPE_SELL, 20, 3) },
+            Order { quantity: 10, ..limit(4, ORDER_TYPE_SELL, 30, 4) },
+            Order { quantity: 15, ..limit(5, ORDER_TYPE_SELL, 50, 5) },
+        ];
+        // a crossed book, as one left by a halt
+        for order in &orders {
+            book.add_order(order.clone());
+        }
+        let side_total = |is_buy: bool| orders.iter().filter(|o| o.is_buy() == is_buy).map(|o| o.quantity).sum::<u32>();
+
+        let (result, _) = book.run_auction(&mut CallAuctionPool::new(16), 1).unwrap();
+        let executed: u32 = result.order_execution_list.iter().map(|execution| execution.quantity).sum();
+        assert!(executed > 0);
+        let bid_residual: u32 = book.iter_bids().map(|order| order.quantity).sum();
+        let ask_residual: u32 = book.iter_asks().map(|order| order.quantity).sum();
+        assert_eq!(side_total(true), executed + bid_residual);
+        assert_eq!(side_total(false), executed + ask_residual);
+
+        for order in &orders {
+            let executed: u32 = result
+                .order_execution_list
+                .iter()
+                .filter(|execution| execution.buy_order_id == order.order_id || execution.sell_order_id == order.order_id)
+                .map(|execution| execution.quantity)
+                .sum();
+            let residual = book.get_order(order.order_id).map_or(0, |view| view.remaining_quantity);
+            assert_eq!(order.quantity, executed + residual, "order {}", order.order_id);
+            assert_eq!(residual == 0, book.was_filled(order.order_id), "order {}", order.order_id);
+        }
+        assert!(book.iter_bids().chain(book.iter_asks()).all(|resting| orders.iter().any(|order| order.order_id == resting.order_id)));
     }
 }
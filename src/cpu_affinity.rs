@@ -50,4 +50,26 @@ pub fn set_core(core_id: usize) -> bool {
         let _ = core_id;
         false
     }
-}
\ No newline at end of file
+}
+
+/// 解除当前线程的 CPU 核心绑定，恢复为可在任意核心上运行
+/// 返回值: 成功返回 true，失败或平台不支持返回 false
+pub fn unpin_core() -> bool {
+    #[cfg(target_os = "linux")]
+    {
+        unsafe {
+            // 内核会与 cpuset 允许的核心取交集，多余的位被忽略
+            let mut set: libc::cpu_set_t = std::mem::zeroed();
+            for core_id in 0..libc::CPU_SETSIZE as usize {
+                libc::CPU_SET(core_id, &mut set);
+            }
+            let tid = libc::pthread_self();
+            libc::pthread_setaffinity_np(tid, std::mem::size_of::<libc::cpu_set_t>(), &set) == 0
+        }
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    {
+        false
+    }
+}
@@ -1,15 +1,57 @@
 // --- Message Type Constants ---
 
 use std::sync::Arc;
-use ahash::AHashMap;
-use std::collections::VecDeque;
+use std::sync::atomic::{AtomicU64, Ordering};
+use ahash::{AHashMap, AHashSet};
+use std::cell::{Cell, RefCell};
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, VecDeque};
+use std::sync::mpsc::Sender;
+use std::time::Instant;
 
+use crate::date_time_tool::saturating_duration;
 use crate::high_resolution_timer::HighResolutionTimer;
 
 pub const MSG_ORDER_SUBMIT: u8 = 1; // Client -> Engine: Order submission
 pub const MSG_ORDER_CANCEL: u8 = 2; // Client -> Engine: Order cancellation
+pub const MSG_ORDER_REPLACE: u8 = 3; // Client -> Engine: Atomic cancel of one order and submit of another
+pub const MSG_ORDER_QUERY: u8 = 4; // Client -> Engine: Ask for the state of one resting order
+pub const MSG_CANCEL_AT_PRICE: u8 = 5; // Client -> Engine: Cancel every resting order at one price on one side
+pub const MSG_WHATIF: u8 = 6; // Client -> Engine: Preview the fills of an order without submitting it
+pub const MSG_BOOK_ADMIN: u8 = 7; // Client -> Engine: Maintenance command (BOOK_ADMIN_*) for one product's book
+pub const MSG_SET_TRADING_STATE: u8 = 8; // Client -> Engine: Move one product to another TradingState
+pub const MSG_ORDER_CANCEL_BATCH: u8 = 9; // Client -> Engine: One chunk of a cancel of many orders of one product
 pub const MSG_TRADE_BROADCAST: u8 = 10; // Engine -> Client: OrderExecution broadcast
 pub const MSG_STATUS_BROADCAST: u8 = 11; // Engine -> Client: Status broadcast
+pub const MSG_MARKETDATA: u8 = 12; // Engine -> Client: Top-of-book depth snapshot (one frame per side)
+pub const MSG_ORDER_ACK: u8 = 13; // Engine -> Client: Order acknowledgment
+pub const MSG_UNFILLED_MARKET: u8 = 14; // Engine -> Client: Market order residual that was dropped
+pub const MSG_ORDER_QUERY_RESPONSE: u8 = 15; // Engine -> Client: Answer to MSG_ORDER_QUERY
+pub const MSG_ORDER_FILLED: u8 = 16; // Engine -> Client: Per-aggressor summary of one match
+pub const MSG_SESSION_SUMMARY: u8 = 17; // Engine -> Client: Running traded volume and turnover of one product
+pub const MSG_CANCEL_ACK: u8 = 18; // Engine -> Client: Order cancelled, with the quantity taken off the book
+pub const MSG_CANCEL_REJECT: u8 = 19; // Engine -> Client: Cancel failed, with the CancelRejectReason code
+pub const MSG_WHATIF_RESPONSE: u8 = 20; // Engine -> Client: Answer to MSG_WHATIF
+pub const MSG_BOOK_MEMORY: u8 = 21; // Engine -> Client: Estimated memory held by one book, answer to MSG_BOOK_ADMIN
+pub const MSG_TRADING_STATE: u8 = 22; // Engine -> Client: A product's TradingState changed
+pub const MSG_BOOK_DIGEST: u8 = 23; // Engine -> Client: Periodic hash of one product's top of book
+pub const MSG_SNAPSHOT_HEADER: u8 = 24; // Snapshot file only: sequence and engine counters the snapshot covers
+pub const MSG_SNAPSHOT_PRODUCT: u8 = 25; // Snapshot file only: counters of one product's book
+pub const MSG_STATUS_EXTENSION: u8 = 26; // Engine -> Client: Instance uptime, engine version and matcher queue depth, after the status frames
+pub const MSG_SEQUENCE: u8 = 27; // Engine -> Client: Sequence number of the frames after it, first in every datagram
+
+// --- Order Layout Versions (top three bits of the type byte of order and replace frames) ---
+pub const MSG_TYPE_MASK: u8 = 0x1F; // Type byte bits holding the message type
+pub const MSG_VERSION_SHIFT: u8 = 5; // Type byte bits above this hold the layout version
+pub const ORDER_LAYOUT_UNVERSIONED: u8 = 0; // Clients predating versions: optional fields read when non-zero
+pub const ORDER_LAYOUT_LEGACY: u8 = 1; // 40-byte order, every later field at its default
+pub const ORDER_LAYOUT_EXTENDED: u8 = 2; // Adds time in force, minimum quantity, all-or-none, trader ID and display quantity
+pub const ORDER_LAYOUT_CURRENT: u8 = ORDER_LAYOUT_EXTENDED; // Written by serialize_order
+
+// --- Book Admin Commands (MSG_BOOK_ADMIN) ---
+pub const BOOK_ADMIN_REPORT_MEMORY: u8 = 1; // Report the book's memory footprint
+pub const BOOK_ADMIN_SHRINK: u8 = 2; // Compact the book down to its floor, then report the footprint
+pub const BOOK_ADMIN_REGISTER: u8 = 3; // Open an empty book for a product not configured, then report the footprint
 
 // --- Order Type Constants ---
 pub const ORDER_TYPE_BUY: u8 = 1; // Order side: Buy
@@ -22,14 +64,42 @@ pub const ORDER_TYPE_MOCK_SELL: u8 = 4; // Order side: mock sell
 pub const ORDER_PRICE_TYPE_LIMIT: u8 = 1; // Order price type: Limit
 pub const ORDER_PRICE_TYPE_MARKET: u8 = 2; // Order price type: Market
 
-pub const TRADE_TYPE_REAL: u8 = 0; // Order price type: Limit
-pub const TRADE_TYPE_MOCK: u8 = 1; // Order price type: Market
+// --- Time In Force Constants ---
+pub const TIF_GTC: u8 = 0; // Good till cancel; also what legacy frames (zero byte) decode to
+pub const TIF_DAY: u8 = 1; // Rests until the end of the trading session
+pub const TIF_IOC: u8 = 2; // Immediate or cancel: fill what crosses, drop the rest
+pub const TIF_FOK: u8 = 3; // Fill or kill: fill the whole quantity on arrival or nothing
+pub const TIF_GTD: u8 = 4; // Good till date: rests until expire_time (engine wall clock, Nanoseconds)
+
+// --- Order Ack Status Constants ---
+pub const ORDER_ACK_RESTING: u8 = 1; // Accepted, nothing filled, full quantity rests
+pub const ORDER_ACK_FILLED: u8 = 2; // Fully filled on arrival
+pub const ORDER_ACK_PARTIALLY_FILLED: u8 = 3; // Partially filled, resting_quantity holds what rests
+pub const ORDER_ACK_CANCELLED: u8 = 4; // Nothing filled and nothing rests (IOC/FOK/market without liquidity)
+pub const ORDER_ACK_REJECTED: u8 = 5; // Not accepted, reject_reason holds the RejectReason code
+
+// How many fully filled order ids a book remembers to tell a late cancel why it failed
+pub const FILLED_ORDER_MEMORY: usize = 65_536;
+
+// Orders a book's id maps keep room for after compaction; also their initial capacity
+pub const DEFAULT_COMPACT_FLOOR: usize = 1024;
+
+// Most orders a book's id map is prefaulted for, about 30 MB, however high the side cap
+pub const MAX_PREFAULT_ORDERS: usize = 1 << 20;
+
+pub const TRADE_TYPE_REAL: u8 = 0; // Trade print of a real fill
+pub const TRADE_TYPE_MOCK: u8 = 1; // Trade print of a mock order's fill; the book was not touched
 
 
 // --- Message Size Constant ---
 pub const MESSAGE_TOTAL_SIZE: usize = 64; // All network packets are 64 bytes fixed size.
 
 
+// --- Market Data Constant ---
+pub const MARKET_DATA_DEPTH: usize = 5; // Price levels per side in a MSG_MARKETDATA snapshot
+pub const BOOK_DIGEST_DEPTH: usize = 10; // Price levels per side hashed into a MSG_BOOK_DIGEST
+
+
 // --- Data Structure Definitions ---
 
 // Order Structure (for MSG_ORDER_SUBMIT)
@@ -41,12 +111,18 @@ pub struct Order {
     pub quantity: u32,    // Quantity (4 bytes)
 
     pub order_id: u64,    // Unique order ID (8 bytes)
-    pub price: u64,       // Price (8 bytes)
+    pub price: i64,       // Price (8 bytes, negative only for products that allow it)
 
 
     pub submit_time: u64, // Submission timestamp (Nanoseconds) (8 bytes)
-    pub expire_time: u64, // Expiration timestamp (Nanoseconds. 0 means GTC) (8 bytes)
-                          // Total Payload Size: 40 bytes
+    pub expire_time: u64, // Expiration timestamp (Nanoseconds), read for TIF_GTD only (8 bytes)
+    pub time_in_force: u8, // TIF_* (1 byte)
+    pub min_qty: u32,     // Minimum quantity to execute on arrival, 0 means none (4 bytes at payload 49)
+    pub aon: bool,        // All or none: never partially filled, resting or arriving (1 byte)
+    pub trader_id: u32,   // Submitting trader, 0 when anonymous (4 bytes at payload 54)
+    pub client_ref: [u8; 8], // Client's own reference, echoed back, never read by the engine (8 bytes at payload 41, submits only)
+    pub display_quantity: u32, // Iceberg peak shown in public depth, 0 shows the whole quantity (4 bytes at payload 58)
+                          // Total Payload Size: 62 bytes (41..49 carries a replace's old order ID)
 }
 
 // Order Cancellation Structure (for MSG_ORDER_CANCEL)
@@ -58,6 +134,125 @@ pub struct CancelOrder {
     
 }
 
+// Cancel of many orders of one product (for MSG_ORDER_CANCEL_BATCH), sent as one frame
+// per MAX_IDS_PER_CHUNK order IDs; each frame cancels its own IDs as it arrives
+#[derive(Debug, Clone, PartialEq)]
+pub struct CancelOrderBatch {
+    pub product_id: u16,     // Product identifier (2 bytes)
+    pub order_ids: Vec<u64>, // Order IDs to cancel, after a chunk index, chunk count and ID count (1 byte each)
+                             // Total Payload Size: 5 + 8 bytes per ID, at most 45 bytes a frame
+}
+
+pub const MAX_IDS_PER_CHUNK: usize = 5; // Order IDs one MSG_ORDER_CANCEL_BATCH frame carries
+
+// Price Level Cancellation Structure (for MSG_CANCEL_AT_PRICE)
+#[derive(Debug, Clone)]
+pub struct CancelAtPrice {
+    pub product_id: u16, // Product identifier (2 bytes)
+    pub order_type: u8,  // Side whose level is cleared (BUY/SELL) (1 byte)
+    pub price: i64,      // Exact price of the orders to cancel (8 bytes)
+    pub trader_id: u32,  // Only this trader's orders are cancelled; 0 cancels nothing (4 bytes)
+                         // Total Payload Size: 15 bytes
+}
+
+// Cancel/Replace Structure (for MSG_ORDER_REPLACE)
+// Same layout as MSG_ORDER_SUBMIT with the replaced order's ID appended.
+#[derive(Debug, Clone)]
+pub struct ReplaceOrder {
+    pub old_order_id: u64, // Resting order to cancel (8 bytes)
+    pub order: Order,      // Order submitted in its place (41 bytes)
+                           // Total Payload Size: 49 bytes
+}
+
+// Order Query Structure (for MSG_ORDER_QUERY)
+#[derive(Debug, Clone)]
+pub struct OrderQuery {
+    pub product_id: u16, // Product identifier (2 bytes)
+    pub order_id: u64,   // Order ID to look up (8 bytes)
+                         // Total Payload Size: 10 bytes
+}
+
+// Trading State Structure (for MSG_SET_TRADING_STATE and MSG_TRADING_STATE)
+#[derive(Debug, Clone, PartialEq)]
+pub struct TradingStateChange {
+    pub product_id: u16,      // Product identifier (2 bytes)
+    pub state: TradingState,  // New state (1 byte)
+                              // Total Payload Size: 3 bytes
+}
+
+// Book Admin Structure (for MSG_BOOK_ADMIN)
+#[derive(Debug, Clone)]
+pub struct BookAdmin {
+    pub product_id: u16, // Product identifier (2 bytes)
+    pub command: u8,     // BOOK_ADMIN_* (1 byte)
+                         // Total Payload Size: 3 bytes
+}
+
+// Book Memory Structure (for MSG_BOOK_MEMORY)
+// Every shard hosting the product answers, told apart by the instance tag.
+#[derive(Debug, Clone, PartialEq)]
+pub struct BookMemoryReport {
+    pub instance_tag: [u8; 16],   // 16-byte engine instance tag
+    pub product_id: u16,          // Product identifier (2 bytes)
+    pub footprint_bytes: u64,     // Estimated heap bytes held by the book, allocated capacity included (8 bytes)
+    pub resting_orders: u32,      // Orders resting on both sides (4 bytes)
+                                  // Total Payload Size: 30 bytes
+}
+
+// Book Digest Structure (for MSG_BOOK_DIGEST)
+// A consumer hashing its own rebuilt book the same way and getting another digest
+// has lost messages or diverged.
+#[derive(Debug, Clone, PartialEq)]
+pub struct BookDigest {
+    pub instance_tag: [u8; 16],   // 16-byte engine instance tag
+    pub product_id: u16,          // Product identifier (2 bytes)
+    pub depth: u8,                // Levels per side hashed (1 byte)
+    pub digest: u64,              // ContinuousOrderBook::top_of_book_digest, quantities in lots (8 bytes)
+                                  // Total Payload Size: 27 bytes
+}
+
+// Snapshot Header Structure (for MSG_SNAPSHOT_HEADER, first frame of a snapshot file)
+#[derive(Debug, Clone, PartialEq)]
+pub struct SnapshotHeader {
+    pub sequence: u64,              // Last write-ahead log sequence applied (8 bytes)
+    pub matched_orders: u64,        // (8 bytes)
+    pub total_received_orders: u64, // (8 bytes)
+    pub cancelled_orders: u64,      // (8 bytes)
+    pub clock_skew_count: u64,      // (8 bytes)
+    pub malformed_frames: u64,      // (8 bytes)
+    pub checksum_failures: u64,     // (8 bytes)
+                                    // Total Payload Size: 56 bytes
+}
+
+// Snapshot Product Structure (for MSG_SNAPSHOT_PRODUCT, ahead of each product's orders)
+#[derive(Debug, Clone, PartialEq)]
+pub struct SnapshotProduct {
+    pub product_id: u16,      // Product identifier (2 bytes)
+    pub received_orders: u64, // (8 bytes)
+    pub matched_orders: u64,  // (8 bytes)
+                              // Total Payload Size: 18 bytes
+}
+
+// Read-only view of a resting order
+#[derive(Debug, Clone, PartialEq)]
+pub struct OrderView {
+    pub order_type: u8,          // Order side (1 byte)
+    pub price: i64,              // Resting price (8 bytes)
+    pub remaining_quantity: u32, // Quantity still resting (4 bytes)
+    pub submit_time: u64,        // Client submission timestamp (8 bytes)
+    pub client_ref: [u8; 8],     // Client reference the order was submitted with (8 bytes)
+}
+
+// Order Query Response Structure (for MSG_ORDER_QUERY_RESPONSE)
+// `view` is None when the order is not resting: filled, cancelled or never seen.
+#[derive(Debug, Clone, PartialEq)]
+pub struct OrderQueryResponse {
+    pub product_id: u16,         // Product identifier (2 bytes)
+    pub order_id: u64,           // Queried order ID (8 bytes)
+    pub view: Option<OrderView>, // Found flag (1 byte) + view (29 bytes)
+                                 // Total Payload Size: 32 bytes
+}
+
 // Broadcast Status Structure (for MSG_STATUS_BROADCAST)
 #[derive(Debug, Clone)]
 pub struct BroadcastStats {
@@ -65,13 +260,256 @@ pub struct BroadcastStats {
     pub product_id: u16,            // Product identifier (2 bytes)
     pub bids_order_count: u32,             // Current order book size (4 bytes)
     pub ask_order_count: u32,              // Current order book size (4 bytes)
-    pub matched_orders: u32,        // Total matched orders count (4 bytes)
-    pub total_received_orders: u32, // Total received orders count (4 bytes)
+    pub matched_orders: u32,        // Executions of this product (4 bytes)
+    pub total_received_orders: u32, // Orders received for this product (4 bytes)
     pub start_time: u64,            // Program start time (Nanoseconds) (8 bytes)
                                     // Total Payload Size: 42 bytes
     pub total_bid_volumn: u32,
     pub total_ask_volumn: u32,
-    
+    // The instance's own counters, the same in every product's frame
+    pub clock_skew_count: u32,      // Orders whose submit_time was ahead of the engine clock (4 bytes)
+    pub malformed_frames: u32,      // Datagrams dropped for having the wrong size (4 bytes)
+    pub checksum_failures: u32,     // Frames dropped for a bad checksum (4 bytes)
+}
+
+// Status Extension Structure (for MSG_STATUS_EXTENSION, one per instance)
+#[derive(Debug, Clone, PartialEq)]
+pub struct StatusExtension {
+    pub instance_tag: [u8; 16],   // 16-byte engine instance tag
+    pub uptime_ns: u64,           // Monotonic time since the engine started (8 bytes)
+    pub engine_version: [u8; 3],  // Major, minor and patch of the engine build (3 bytes)
+    pub queue_depth: u32,         // Commands waiting for the matcher thread (4 bytes)
+    pub max_queue_depth: u32,     // Most waiting at once since the previous frame (4 bytes)
+                                  // Total Payload Size: 35 bytes
+}
+
+// Sequence Header Structure (for MSG_SEQUENCE). Frames sent to one destination are
+// numbered from 0 in the order sent, across all frame types; a restart starts over.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SequenceHeader {
+    pub first_seq: u64,   // Sequence number of the frame following this one (8 bytes)
+    pub frame_count: u16, // Frames following in the same datagram, numbered on from first_seq (2 bytes)
+                          // Total Payload Size: 10 bytes
+}
+
+// One aggregated price level of the book
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct DepthLevel {
+    pub price: i64,     // Level price (8 bytes)
+    pub quantity: u32,  // Total resting quantity at the level (4 bytes)
+}
+
+// Top-of-book snapshot (for MSG_MARKETDATA); missing levels are zero-padded.
+// Quantities are in base units (lots x lot_size), saturating at u32::MAX.
+#[derive(Debug, Clone, PartialEq)]
+pub struct MarketDataSnapshot {
+    pub product_id: u16,
+    pub bid_count: u8,
+    pub ask_count: u8,
+    pub bids: [DepthLevel; MARKET_DATA_DEPTH], // Best bid first
+    pub asks: [DepthLevel; MARKET_DATA_DEPTH], // Best ask first
+}
+
+// One book frozen at one instant, so several reads agree with each other; clones
+// share the levels
+#[derive(Debug, Clone, PartialEq)]
+pub struct BookView {
+    pub best_bid: Option<i64>,      // None for an empty side
+    pub best_ask: Option<i64>,
+    pub bids: Arc<[DepthLevel]>,    // Best first, at most the depth asked for
+    pub asks: Arc<[DepthLevel]>,
+    pub bid_order_count: u32,
+    pub ask_order_count: u32,
+    pub total_bid_volume: u32,      // Whole side, not just the captured levels
+    pub total_ask_volume: u32,
+    pub session: SessionStats,
+}
+
+// Running trade totals of one product since the engine started (for MSG_SESSION_SUMMARY)
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct SessionStats {
+    pub volume: u64,    // Sum of execution quantities, in lots (8 bytes)
+    pub turnover: i128, // Sum of price x quantity; trades at negative prices subtract (16 bytes)
+    pub ohlc: Option<Ohlc>, // Trade prices since the last reset, None before the first trade
+}
+
+// Open, high, low and close trade prices of one product
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Ohlc {
+    pub open: i64,  // First trade price
+    pub high: i64,  // Highest trade price
+    pub low: i64,   // Lowest trade price
+    pub close: i64, // Last trade price
+}
+
+// Engine-wide order totals. Bumped by the matching thread with relaxed increments;
+// any thread holding the Arc reads them without a lock. The queue gauges are moved by
+// the threads on either end of the matcher's command channel.
+#[derive(Debug, Default)]
+pub struct EngineCounters {
+    pub matched_orders: AtomicU64,
+    pub total_received_orders: AtomicU64,
+    pub queued_commands: AtomicU64,
+    pub max_queued_commands: AtomicU64, // High-water mark since take_max_queued
+}
+
+impl EngineCounters {
+    pub fn add_matched(&self, executions: u64) {
+        self.matched_orders.fetch_add(executions, Ordering::Relaxed);
+    }
+
+    pub fn add_received(&self) {
+        self.total_received_orders.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn matched(&self) -> u64 {
+        self.matched_orders.load(Ordering::Relaxed)
+    }
+
+    pub fn received(&self) -> u64 {
+        self.total_received_orders.load(Ordering::Relaxed)
+    }
+
+    /// Counts a command sent to the matcher thread, ahead of sending it.
+    pub fn command_queued(&self) {
+        let depth = self.queued_commands.fetch_add(1, Ordering::Relaxed) + 1;
+        self.max_queued_commands.fetch_max(depth, Ordering::Relaxed);
+    }
+
+    /// Counts a command the matcher thread took off its queue, or one never sent.
+    pub fn command_taken(&self) {
+        self.queued_commands.fetch_sub(1, Ordering::Relaxed);
+    }
+
+    pub fn queued(&self) -> u64 {
+        self.queued_commands.load(Ordering::Relaxed)
+    }
+
+    /// Most commands waiting at once since the last call, restarting from the current depth.
+    pub fn take_max_queued(&self) -> u64 {
+        self.max_queued_commands.swap(self.queued(), Ordering::Relaxed)
+    }
+
+    /// Sets both totals, as a snapshot recorded them.
+    pub fn restore(&self, matched_orders: u64, total_received_orders: u64) {
+        self.matched_orders.store(matched_orders, Ordering::Relaxed);
+        self.total_received_orders.store(total_received_orders, Ordering::Relaxed);
+    }
+}
+
+// Most recently filled order ids of one book, oldest evicted first
+#[derive(Debug, Default)]
+pub struct FilledOrders {
+    pub ids: AHashSet<u64>,
+    pub arrival: VecDeque<u64>, // Same ids in the order they filled
+}
+
+impl FilledOrders {
+    pub fn record(&mut self, order_id: u64) {
+        if !self.ids.insert(order_id) {
+            return;
+        }
+        self.arrival.push_back(order_id);
+        if self.arrival.len() > FILLED_ORDER_MEMORY
+            && let Some(oldest) = self.arrival.pop_front()
+        {
+            self.ids.remove(&oldest);
+        }
+    }
+
+    pub fn contains(&self, order_id: u64) -> bool {
+        self.ids.contains(&order_id)
+    }
+
+    /// Heap bytes held by the set and queue, counting a control byte per set slot.
+    pub fn memory_footprint(&self) -> usize {
+        self.ids.capacity() * (size_of::<u64>() + 1) + self.arrival.capacity() * size_of::<u64>()
+    }
+
+    pub fn shrink_to(&mut self, min_capacity: usize) {
+        self.ids.shrink_to(min_capacity);
+        self.arrival.shrink_to(min_capacity);
+    }
+
+    /// Makes room for `capacity` ids and writes all of it once, leaving both empty.
+    pub fn prefault(&mut self, capacity: usize) {
+        self.ids.reserve(capacity);
+        self.arrival.reserve(capacity);
+        for id in 0..capacity as u64 {
+            self.ids.insert(id);
+            self.arrival.push_back(id);
+        }
+        self.ids.clear();
+        self.arrival.clear();
+    }
+}
+
+// Cancel Acknowledgment Structure (for MSG_CANCEL_ACK)
+#[derive(Debug, Clone, PartialEq)]
+pub struct CancelAck {
+    pub product_id: u16,          // Product identifier (2 bytes)
+    pub order_id: u64,            // Cancelled order ID (8 bytes)
+    pub cancelled_quantity: u32,  // Quantity that was resting when cancelled (4 bytes)
+                                  // Total Payload Size: 14 bytes
+}
+
+// Cancel Reject Structure (for MSG_CANCEL_REJECT)
+#[derive(Debug, Clone, PartialEq)]
+pub struct CancelReject {
+    pub product_id: u16,          // Product identifier from the cancel (2 bytes)
+    pub order_id: u64,            // Order ID the cancel named (8 bytes)
+    pub reason: CancelRejectReason, // Why nothing was cancelled (1 byte)
+                                  // Total Payload Size: 11 bytes
+}
+
+// What-If Response Structure (for MSG_WHATIF_RESPONSE)
+// Fills the previewed order would get against the book at the time of the request.
+#[derive(Debug, Clone, PartialEq)]
+pub struct WhatIfResponse {
+    pub product_id: u16,          // Product identifier (2 bytes)
+    pub order_id: u64,            // Previewed order ID (8 bytes)
+    pub filled_quantity: u32,     // Quantity that would fill (4 bytes)
+    pub residual_quantity: u32,   // Quantity left after matching (4 bytes)
+    pub average_price: i64,       // Volume weighted average fill price, truncated; 0 without fills (8 bytes)
+    pub worst_price: i64,         // Highest fill price for a buy, lowest for a sell; 0 without fills (8 bytes)
+    pub fills: u32,               // Resting orders that would be traded against (4 bytes)
+                                  // Total Payload Size: 38 bytes
+}
+
+// Order Acknowledgment Structure (for MSG_ORDER_ACK)
+#[derive(Debug, Clone, PartialEq)]
+pub struct OrderAck {
+    pub product_id: u16,        // Product identifier (2 bytes)
+    pub order_id: u64,          // Acknowledged order ID (8 bytes)
+    pub filled_quantity: u32,   // Quantity filled on arrival (4 bytes)
+    pub resting_quantity: u32,  // Quantity left resting in the book (4 bytes)
+    pub status: u8,             // ORDER_ACK_* (1 byte)
+    pub reject_reason: u8,      // RejectReason code, 0 unless rejected or evicted (1 byte)
+    pub client_ref: [u8; 8],    // Client reference of the acknowledged order (8 bytes)
+                                // Total Payload Size: 28 bytes
+}
+
+// Unfilled Market Structure (for MSG_UNFILLED_MARKET)
+// Market orders never rest, so whatever the book could not fill is cancelled.
+#[derive(Debug, Clone, PartialEq)]
+pub struct UnfilledMarket {
+    pub product_id: u16,          // Product identifier (2 bytes)
+    pub order_id: u64,            // Market order ID (8 bytes)
+    pub unfilled_quantity: u32,   // Quantity left after matching (4 bytes)
+                                  // Total Payload Size: 14 bytes
+}
+
+// Order Filled Summary Structure (for MSG_ORDER_FILLED)
+// Sent once per matched aggressor in addition to the individual trade prints.
+#[derive(Debug, Clone, PartialEq)]
+pub struct OrderSummary {
+    pub product_id: u16,        // Product identifier (2 bytes)
+    pub order_id: u64,          // Aggressor order ID (8 bytes)
+    pub filled_quantity: u64,   // Total quantity filled, in base units (8 bytes)
+    pub average_price: i64,     // Volume weighted average fill price, truncated (8 bytes)
+    pub counterparties: u32,    // Resting orders traded against (4 bytes)
+    pub client_ref: [u8; 8],    // Aggressor's client reference (8 bytes)
+                                // Total Payload Size: 38 bytes
 }
 
 // Match Result Structure (for MSG_TRADE_BROADCAST)
@@ -81,20 +519,27 @@ pub struct OrderExecution {
     pub product_id: u16,          // Product identifier (2 bytes)
     pub buy_order_id: u64,        // Buyer's order ID (8 bytes)
     pub sell_order_id: u64,       // Seller's order ID (8 bytes)
-    pub price: u64,               // OrderExecution price (8 bytes)
+    pub price: i64,               // OrderExecution price (8 bytes)
     pub quantity: u32,            // OrderExecution quantity (4 bytes)
     pub trade_time_network: u32,  // OrderExecution timestamp (Nanoseconds) (4 bytes)
     pub internal_match_time: u32, // Total Payload Size: 46 bytes
     pub is_mocked_result: bool,
+    pub aggressor_side: u8,       // Taker side (ORDER_TYPE_BUY/ORDER_TYPE_SELL), 0 for auction (1 byte)
+    pub clock_skew_detected: bool, // Client submit_time was ahead of the engine, trade_time_network clamped to 0 (1 byte)
+    pub buy_client_ref: [u8; 8],  // Buyer's client reference; not on the trade frame, which is full
+    pub sell_client_ref: [u8; 8], // Seller's client reference
 }
 #[derive(Debug, Clone)]
 pub struct MatchResult {
     pub order_execution_list:Vec<OrderExecution>,
     pub start_time: u64,
     pub end_time: u64,
-    
-
-
+    // Engine receive to match completion (Nanoseconds), independent of the client clock
+    pub receive_to_match_time: u32,
+    // The residual could not rest because its side of the book was full
+    pub residual_rejected: bool,
+    // Resting order cancelled to make room for this one (evict-worst policy)
+    pub evicted_order: Option<Order>,
 }
 impl MatchResult {
     pub fn new(cap: usize) -> Self {
@@ -102,6 +547,9 @@ impl MatchResult {
             order_execution_list: Vec::with_capacity(cap),
             start_time: 0,
             end_time: 0,
+            receive_to_match_time: 0,
+            residual_rejected: false,
+            evicted_order: None,
         }
     }
     pub fn add_order_execution(&mut self,trade: OrderExecution){
@@ -110,45 +558,423 @@ impl MatchResult {
      pub fn total_count(& self)->u32{
         self.order_execution_list.len() as u32
      }
+     /// Executions that traded against the book; mock fills are left out.
+     pub fn real_count(&self) -> u32 {
+        self.order_execution_list.iter().filter(|e| !e.is_mocked_result).count() as u32
+     }
+     /// Splits the result into its real and its mock executions, each keeping the
+     /// timings of the whole match.
+     pub fn split_mocked(&self) -> (MatchResult, MatchResult) {
+        let (mocked, real): (Vec<OrderExecution>, Vec<OrderExecution>) =
+            self.order_execution_list.iter().cloned().partition(|e| e.is_mocked_result);
+        let with = |order_execution_list| MatchResult {
+            order_execution_list,
+            start_time: self.start_time,
+            end_time: self.end_time,
+            receive_to_match_time: self.receive_to_match_time,
+            residual_rejected: self.residual_rejected,
+            evicted_order: self.evicted_order.clone(),
+        };
+        (with(real), with(mocked))
+     }
      pub fn total_time(& self)-> u64{
-       self.end_time - self.start_time
+       saturating_duration(self.start_time, self.end_time)
      }
-     pub fn time_per_trade(&self)->u32{
+     /// Average time per execution in fractional nanoseconds, so a short match spread
+     /// over many executions doesn't truncate to 0.
+     pub fn time_per_trade(&self)->f64{
         if self.total_count() == 0 {
-            return 0
+            return 0.0
+        }
+        self.total_time() as f64 / self.total_count() as f64
+     }
+
+     /// Summarizes the fills of the aggressor that produced this result, `None` when
+     /// nothing traded. Each execution is against a distinct resting order. Fill
+     /// quantities are in lots; the summary reports them in base units of `lot_size`.
+     pub fn order_summary(&self, lot_size: u32) -> Option<OrderSummary> {
+        let first = self.order_execution_list.first()?;
+        let (order_id, client_ref) = if first.aggressor_side == ORDER_TYPE_BUY {
+            (first.buy_order_id, first.buy_client_ref)
+        } else {
+            (first.sell_order_id, first.sell_client_ref)
+        };
+
+        // price * quantity overflows i64 for large prices, so accumulate in i128
+        let mut notional: i128 = 0;
+        let mut filled_quantity: u64 = 0;
+        for execution in &self.order_execution_list {
+            notional += execution.price as i128 * execution.quantity as i128;
+            filled_quantity += execution.quantity as u64;
         }
-        (self.total_time() / self.total_count() as u64) as u32
+
+        Some(OrderSummary {
+            product_id: first.product_id,
+            order_id,
+            filled_quantity: filled_quantity * lot_size as u64,
+            average_price: (notional / filled_quantity as i128) as i64,
+            counterparties: self.order_execution_list.len() as u32,
+            client_ref,
+        })
      }
 }
+// Synthetic book shapes produced by the TestOrderBookBuilder
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TestBookScenario {
+    NonCrossing,    // bids below asks, nothing matches
+    Crossing,       // every bid crosses one ask
+    OneSided,       // deep bid side, empty ask side
+    Random(u64),    // seeded random prices and sides
+}
+
+// Products an instance accepts besides its own product_id, each with a book of its own
+#[derive(Debug, Clone, PartialEq, Default)]
+pub enum ProductSet {
+    #[default]
+    None,               // the instance's own product only
+    Range(u16, u16),    // first..=last
+    List(Vec<u16>),     // explicit ids
+}
+
+impl ProductSet {
+    pub fn contains(&self, product_id: u16) -> bool {
+        match self {
+            ProductSet::None => false,
+            ProductSet::Range(first, last) => (*first..=*last).contains(&product_id),
+            ProductSet::List(ids) => ids.contains(&product_id),
+        }
+    }
+}
+
+// Which resting order a crossing order meets first
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum PriorityMode {
+    #[default]
+    PriceTime,      // best price first, FIFO within a level
+    TimePrice,      // earliest order anywhere in the crossing range first
+}
+
+// Which sides of the book hold resting orders; a change is a liquidity event
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum BookState {
+    TwoSided,
+    BidOnly,
+    AskOnly,
+    #[default]
+    Empty,
+}
+
+// Where the execution price of a crossing limit order comes from
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum TradePriceMode {
+    #[default]
+    Resting,        // the resting order's price
+    Midpoint,       // halfway between aggressor limit and resting price, on the tick grid
+    Aggressor,      // the aggressor's limit price; market orders take the resting price
+    Reference,      // the reference price, kept between the resting price and the aggressor's limit
+}
+
+// Tick grid of a book, for execution prices that fall between two ticks
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PriceGrid {
+    pub tick: u64,
+    pub origin: i64,                // A price on the grid; every other one is a whole number of ticks away
+    pub rounding: PriceRounding,
+}
+
+// Chooses the execution price of one fill; a book calls the TradePriceMode it is
+// configured with for every execution
+pub trait TradePriceRule {
+    fn price(&self, aggressor: &Order, resting: &Order, reference: Option<i64>, grid: PriceGrid) -> i64;
+}
+
+// How the executions of one match are printed on the trade feed
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum TapeAggregation {
+    #[default]
+    Off,            // one print per execution
+    ByPrice,        // consecutive executions at one price become a single print
+}
+
+// How loudly a frame with a bad checksum is reported; it is dropped either way
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum ChecksumPolicy {
+    #[default]
+    Count,          // lossy link: count it, log at most once per second
+    Alert,          // integrity-critical link: log an error for every one
+}
+
+// How a price between two ticks lands on the grid
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum PriceRounding {
+    #[default]
+    HalfUp,         // nearest tick, exact halves go up
+    HalfEven,       // nearest tick, exact halves go to the even tick
+}
+
+// One rule for choosing among auction prices; rules apply in order, each keeping
+// only the prices that are best under it
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum AuctionTieBreak {
+    MaxVolume,      // most executable quantity
+    MinImbalance,   // least quantity left over on the heavier side
+    MarketPressure, // highest price if every candidate has a buy surplus, lowest if a sell surplus
+    Reference,      // closest to the reference price, skipped without one
+}
+
+// How an auction picks its equilibrium price among the candidate ticks
+#[derive(Debug, Clone, PartialEq)]
+pub struct AuctionRules {
+    pub tie_breaks: Vec<AuctionTieBreak>,
+    pub reference_price: Option<i64>,
+    pub rounding: PriceRounding,  // for the middle of a range tied under every rule
+    pub max_imbalance_pct: Option<u32>, // refuse to cross above this imbalance, in % of matched volume
+}
+
+impl Default for AuctionRules {
+    fn default() -> Self {
+        Self {
+            tie_breaks: vec![AuctionTieBreak::MaxVolume, AuctionTieBreak::MinImbalance],
+            reference_price: None,
+            rounding: PriceRounding::HalfUp,
+            max_imbalance_pct: None,
+        }
+    }
+}
+
+// Whose clock an order's submit_time, and so its time priority, comes from
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum TimestampSource {
+    #[default]
+    Client,         // as sent, trusted for priority and network latency
+    Engine,         // overwritten with the engine's receive time
+}
+
+// What to do with an order for a product neither configured nor registered
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum UnknownProductPolicy {
+    #[default]
+    Reject,         // rejected until a BOOK_ADMIN_REGISTER opens its book
+    AutoCreate,     // its book is opened on the first order
+}
+
+// What to do with an order that would cross a resting quote of its own trader
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum WashTradePolicy {
+    #[default]
+    Off,            // not checked
+    Warn,           // logged, the order still matches
+    Reject,         // rejected before it reaches the book
+}
+
+// What to do with a passive order when its side already holds the maximum
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum BookFullPolicy {
+    #[default]
+    Reject,         // the new order does not rest
+    EvictWorst,     // cancel the worst-priced, last-in-queue order if the new one is better
+}
+
+// Trading phase of one product, deciding which messages it accepts
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+#[repr(u8)]
+pub enum TradingState {
+    PreOpen = 1,        // not yet open: cancels only
+    #[default]
+    Continuous = 2,     // orders match as they arrive
+    Halted = 3,         // trading stopped: cancels only
+    Closed = 4,         // nothing is accepted, the book is frozen
+}
+
+impl TradingState {
+    pub fn from_u8(value: u8) -> Option<Self> {
+        match value {
+            1 => Some(TradingState::PreOpen),
+            2 => Some(TradingState::Continuous),
+            3 => Some(TradingState::Halted),
+            4 => Some(TradingState::Closed),
+            _ => None,
+        }
+    }
+
+    /// Why a new order or replace is refused in this state; `None` when it is accepted.
+    pub fn order_reject_reason(self) -> Option<RejectReason> {
+        match self {
+            TradingState::PreOpen => Some(RejectReason::ProductNotOpen),
+            TradingState::Continuous => None,
+            TradingState::Halted => Some(RejectReason::TradingHalted),
+            TradingState::Closed => Some(RejectReason::ProductClosed),
+        }
+    }
+
+    pub fn accepts_cancels(self) -> bool {
+        self != TradingState::Closed
+    }
+}
+
+// What resting liquidity mock orders trade against
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum MockMatchPolicy {
+    #[default]
+    AgainstReal,    // priced against real resting orders, none of which is consumed
+    MockOnly,       // traded and rested in a separate book holding only mock orders
+}
+
+// Parameters for the deterministic benchmark order generator
+#[derive(Debug, Clone)]
+pub struct OrderGenParams {
+    pub product_id: u16,
+    pub first_order_id: u64,
+    pub mid_price: i64,         // limit prices are drawn from mid_price ± price_spread
+    pub price_spread: u64,
+    pub min_quantity: u32,      // quantities are drawn from min..=max, biased to small sizes
+    pub max_quantity: u32,
+    pub market_order_percent: u8, // share of MARKET orders, 0..=100
+}
+
+impl Default for OrderGenParams {
+    fn default() -> Self {
+        Self {
+            product_id: 7,
+            first_order_id: 1,
+            mid_price: 10_000_000,
+            price_spread: 500_000,
+            min_quantity: 1,
+            max_quantity: 100,
+            market_order_percent: 5,
+        }
+    }
+}
+
+// Why an order was not accepted; the discriminant is the wire/audit reason code
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[repr(u8)]
+pub enum RejectReason {
+    UnknownProduct = 1,     // product neither configured nor registered on this engine
+    NegativePrice = 2,      // price below zero on a product without negative prices
+    PriceOutOfRange = 3,    // resting price outside the book's price ladder
+    ReplaceRejected = 4,    // cancel/replace failed validation, original kept
+    ShardMismatch = 5,      // replacement order id belongs to another shard
+    BookFull = 6,           // side at its resting order limit, or evicted to make room
+    WashTrade = 7,          // would cross a resting quote of the same trader
+    ProductNotOpen = 8,     // product is in pre-open
+    TradingHalted = 9,      // product is halted; only cancels are accepted
+    ProductClosed = 10,     // product is closed for the session
+    ClockSkew = 11,         // submit_time further ahead of the engine clock than tolerated
+    Expired = 12,           // GTD order whose expire_time has already passed
+    NoReferencePrice = 13,  // market order meeting a resting market order before any trade or reference price
+    PriceBand = 14,         // would trade outside the price band; the product was halted
+}
+
+impl RejectReason {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            RejectReason::UnknownProduct => "UNKNOWN_PRODUCT",
+            RejectReason::NegativePrice => "NEGATIVE_PRICE",
+            RejectReason::PriceOutOfRange => "PRICE_OUT_OF_RANGE",
+            RejectReason::ReplaceRejected => "REPLACE_REJECTED",
+            RejectReason::ShardMismatch => "SHARD_MISMATCH",
+            RejectReason::BookFull => "BOOK_FULL",
+            RejectReason::WashTrade => "WASH_TRADE",
+            RejectReason::ProductNotOpen => "PRODUCT_NOT_OPEN",
+            RejectReason::TradingHalted => "TRADING_HALTED",
+            RejectReason::ProductClosed => "PRODUCT_CLOSED",
+            RejectReason::ClockSkew => "CLOCK_SKEW",
+            RejectReason::Expired => "EXPIRED",
+            RejectReason::NoReferencePrice => "NO_REFERENCE_PRICE",
+            RejectReason::PriceBand => "PRICE_BAND",
+        }
+    }
+}
+
+// Why a cancel took nothing off the book, carried in MSG_CANCEL_REJECT
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[repr(u8)]
+pub enum CancelRejectReason {
+    NotFound = 1,      // no resting order with that id, and none filled recently
+    AlreadyFilled = 2, // the order traded completely before the cancel arrived
+    WrongProduct = 3,  // the order rests under another product, or the product is not hosted
+    ProductClosed = 4, // the product is closed and its book frozen
+}
+
+impl CancelRejectReason {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            CancelRejectReason::NotFound => "NOT_FOUND",
+            CancelRejectReason::AlreadyFilled => "ALREADY_FILLED",
+            CancelRejectReason::WrongProduct => "WRONG_PRODUCT",
+            CancelRejectReason::ProductClosed => "PRODUCT_CLOSED",
+        }
+    }
+}
+
 // Enum to unify incoming messages from the network
 #[derive(Debug)]
 pub enum IncomingMessage {
     Order(Order),
     Cancel(CancelOrder),
+    CancelBatch(CancelOrderBatch),
+    Replace(ReplaceOrder),
+    Query(OrderQuery),
+    CancelAtPrice(CancelAtPrice),
+    WhatIf(Order),
+    BookAdmin(BookAdmin),
+    SetTradingState(TradingStateChange),
 }
 
-// Type alias for indexing into the main orders Vec.
-// u32 is used to maximize CPU cache density for indexing, covering up to 4.2 billion orders.
-pub type OrderIndex = u32;
-
 pub trait ResultSender: Send + Sync {
-    fn send_result(&self, result: MatchResult);
-}
-
-
+    fn send_result(&self, result: &MatchResult);
 
-#[derive(Debug, Clone, Copy)]
-pub struct MatchedRestingOrder {
-    pub order_index: OrderIndex, // Index in the bids or asks vector
-    pub matched_quantity: u32,   // Quantity matched from this resting order
-    pub is_buy: bool,            // true if the order is from the bids array (buy side)
+    fn send_ack(&self, _ack: &OrderAck) {}
+    fn send_unfilled_market(&self, _unfilled: &UnfilledMarket) {}
+    fn send_query_response(&self, _response: &OrderQueryResponse) {}
+    fn send_order_summary(&self, _summary: &OrderSummary) {}
+    fn send_cancel_ack(&self, _ack: &CancelAck) {}
+    fn send_cancel_reject(&self, _reject: &CancelReject) {}
+    fn send_whatif_response(&self, _response: &WhatIfResponse) {}
+    fn send_book_memory(&self, _report: &BookMemoryReport) {}
+    fn send_trading_state(&self, _change: &TradingStateChange) {}
 }
 
+
 #[derive(Default, Clone,Debug)]
 pub struct OrdersBucket {
     pub orders: VecDeque<Order>,
+    // BucketLayout::Soa only: the fields a fill scan reads, position for position with `orders`
+    pub scan: Option<Box<ScanColumns>>,
+}
+
+// Per-field copies of a level's queue, so a scan for the next fillable order walks a
+// few dense arrays instead of whole orders
+#[derive(Default, Clone, Debug)]
+pub struct ScanColumns {
+    pub quantity: VecDeque<u32>,
+    pub price: VecDeque<i64>,
+    pub submit_time: VecDeque<u64>,
+    pub order_id: VecDeque<u64>,
+    pub aon: VecDeque<bool>,
 }
 
+// How the price levels lay out their queues
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum BucketLayout {
+    #[default]
+    Aos,    // orders only; every scan reads whole orders
+    Soa,    // orders plus ScanColumns, which the scans read instead
+}
+
+
+// Top levels of each side as `depth` last walked them; a side's entry is dropped
+// whenever an order on that side rests, trades or leaves, and walked again on the
+// next read
+#[derive(Default, Debug)]
+pub struct DepthCache {
+    // levels asked for and the levels found, fewer only when the side ran out
+    pub bids: RefCell<Option<(usize, Vec<DepthLevel>)>>,
+    pub asks: RefCell<Option<(usize, Vec<DepthLevel>)>>,
+    // how many times a side was walked rather than read from the cache
+    pub walks: Cell<u64>,
+}
 
 // The core Order Book structure (T in Vec<T>)
 // This implements the layered indexing (Price-Time Priority).
@@ -164,7 +990,7 @@ pub struct ContinuousOrderBook {
 
     // price mapping
     pub tick: u64,
-    pub base_price: u64,
+    pub base_price: i64, // price of level 0, below zero for products with negative prices
     pub levels: usize,
 
     // order_id → (is_buy, price_index)
@@ -177,6 +1003,57 @@ pub struct ContinuousOrderBook {
     pub match_result: MatchResult,
 
     pub timer: HighResolutionTimer,
+
+    // stamped on every execution so consumers know which engine produced it
+    pub instance_tag: [u8; 16],
+
+    pub priority_mode: PriorityMode,
+    pub bucket_layout: BucketLayout,
+
+    // how many levels past its first fill a market order may walk; None is unbounded
+    pub max_slippage_ticks: Option<u64>,
+
+    // how far from the band reference continuous trading may go before the product
+    // halts, in ticks, and the price of the last auction that crossed
+    pub price_band_ticks: Option<u64>,
+    pub band_reference: Option<i64>,
+
+    pub trade_price_mode: TradePriceMode,
+    pub reference_price: Option<i64>, // for TradePriceMode::Reference, on the tick grid
+    pub price_rounding: PriceRounding,
+    pub auction_rules: AuctionRules,
+
+    // resting order counts per side, and the cap that bounds them
+    pub bid_order_count: u32,
+    pub ask_order_count: u32,
+    pub max_orders_per_side: Option<u32>,
+    pub book_full_policy: BookFullPolicy,
+
+    // mock orders: whose liquidity they see, and the book they keep under MockOnly
+    pub mock_match_policy: MockMatchPolicy,
+    pub mock_book: Option<Box<ContinuousOrderBook>>,
+
+    // orders the id maps keep room for when compacted
+    pub compact_floor: usize,
+
+    // last reported side occupancy, and where transitions are sent as which product (if anyone listens)
+    pub book_state: BookState,
+    pub book_state_sender: Option<(u16, Sender<(u16, BookState)>)>,
+
+    // traded totals of every real (not mocked) execution
+    pub session: SessionStats,
+    // orders received for this product and its real executions, for its status frame
+    pub received_orders: u64,
+    pub matched_orders: u64,
+
+    // fully filled order ids, so a cancel arriving after the fill can be told so
+    pub filled_orders: FilledOrders,
+
+    // (expire_time, order_id) of every GTD order rested, soonest first; entries of
+    // orders filled or cancelled since are skipped when they come due
+    pub gtd_expiries: BinaryHeap<Reverse<(u64, u64)>>,
+
+    pub depth_cache: DepthCache,
 }
 
 // Engine State and Context
@@ -184,13 +1061,33 @@ pub struct ContinuousOrderBook {
 pub struct EngineState {
     pub instance_tag: [u8; 16],
     pub product_id: u16,
-    // Order Book
+    pub allow_negative_prices: bool,
+    pub lot_size: u32, // base units per lot; the book itself only counts lots
+    pub shard_index: u32,
+    pub shard_count: u32,
+    pub wash_trade_policy: WashTradePolicy,
+    pub timestamp_source: TimestampSource,
+    pub last_engine_stamp: u64, // Last submit_time given out under TimestampSource::Engine
+    pub max_clock_skew_ns: Option<u64>, // Orders stamped further ahead are rejected, nearer ones clamped
+    pub products: ProductSet, // further products accepted by this instance
+    pub unknown_product_policy: UnknownProductPolicy,
+    pub trading_states: AHashMap<u16, TradingState>, // products not listed are Continuous
+    // Order Book of product_id; the other accepted products get theirs on first use or registration
     pub continuous_order_book: ContinuousOrderBook,
+    pub product_books: AHashMap<u16, ContinuousOrderBook>,
+    pub book_state_sender: Option<Sender<(u16, BookState)>>, // handed to every book, including later ones
     pub call_auction_pool:  CallAuctionPool,
-    // Counters
-    pub matched_orders: u64,
-    pub total_received_orders: u64,
+    // Counters; the order totals are shared, so a reader on another thread needs no lock
+    pub counters: Arc<EngineCounters>,
     pub start_time: u64, // Nanoseconds
+    pub started_at: Instant, // Monotonic counterpart of start_time, for the uptime
+    // Set by the matcher when counters changed since the last status frame
+    pub stats_dirty: bool,
+    pub clock_skew_count: u64,
+    pub malformed_frames: u64,
+    pub checksum_failures: u64,
+    pub cancelled_orders: u64, // Cancels that took an order off a book; not in the full status frame
+    pub sequence: u64, // Inbound frames journaled to the write-ahead log, counted across restarts
 }
 
 #[derive(Debug)]
@@ -199,8 +1096,50 @@ pub struct CallAuctionPool {
     pub asks: Vec<Order>,
 }
 
+// Outcome of one auction uncross, reported next to its executions
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct AuctionSummary {
+    pub price: i64,                 // Equilibrium price, 0 when nothing crossed
+    pub matched_volume: u32,        // Quantity executed at the equilibrium price
+    pub eligible_buy_volume: u32,   // Bid quantity priced at or above the equilibrium
+    pub eligible_sell_volume: u32,  // Ask quantity priced at or below the equilibrium
+    pub surplus_side: u8,           // ORDER_TYPE_BUY/SELL of the heavier side, 0 when balanced
+    pub imbalance: u32,             // Eligible quantity of the heavier side left unmatched
+}
 
+// An auction that was not crossed because its projected imbalance was too large;
+// every order stays where it was and the auction continues
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct AuctionExtended {
+    pub price: i64,                 // Equilibrium price the auction would have crossed at
+    pub matched_volume: u32,        // Quantity that would have executed there
+    pub surplus_side: u8,           // ORDER_TYPE_BUY/SELL of the heavier side
+    pub imbalance: u32,             // Quantity of the heavier side that would be left unmatched
+}
 
+impl SessionStats {
+    /// Adds one execution to the totals.
+    #[inline(always)]
+    pub fn record(&mut self, price: i64, quantity: u32) {
+        self.volume += quantity as u64;
+        self.turnover += price as i128 * quantity as i128;
+        match &mut self.ohlc {
+            Some(ohlc) => {
+                ohlc.high = ohlc.high.max(price);
+                ohlc.low = ohlc.low.min(price);
+                ohlc.close = price;
+            }
+            None => {
+                self.ohlc = Some(Ohlc {
+                    open: price,
+                    high: price,
+                    low: price,
+                    close: price,
+                });
+            }
+        }
+    }
+}
 
 impl Order {
     #[inline(always)]
@@ -217,6 +1156,25 @@ impl Order {
     pub fn is_mocked_order(&self) -> bool {
         self.order_type > 2
     }
+
+    /// Whether an unfilled residual is added to the book after matching. Where a mock
+    /// order's residual goes depends on the book's `MockMatchPolicy`.
+    #[inline(always)]
+    pub fn rests_residual(&self) -> bool {
+        self.price_type == ORDER_PRICE_TYPE_LIMIT
+            && self.time_in_force != TIF_IOC
+            && self.time_in_force != TIF_FOK
+    }
+
+    /// Quantity shown in public depth: the iceberg peak, never more than is left. The
+    /// reserve behind it still trades at the order's place in the queue.
+    #[inline(always)]
+    pub fn displayed_quantity(&self) -> u32 {
+        match self.display_quantity {
+            0 => self.quantity,
+            peak => peak.min(self.quantity),
+        }
+    }
 }
 
 
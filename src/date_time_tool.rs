@@ -1,10 +1,36 @@
-use std::time::{SystemTime, UNIX_EPOCH};
+use std::sync::OnceLock;
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
 
+/// Wall clock and monotonic clock read together once, at the first timestamp taken.
+static CLOCK_ANCHOR: OnceLock<(u64, Instant)> = OnceLock::new();
+
+/// Elapsed time between two timestamps, 0 when `end` is earlier than `start`.
+///
+/// Unsigned deltas would panic in debug and wrap in release when clocks step
+/// backwards or two clock sources are mixed, so every duration goes through here.
+#[inline(always)]
+pub fn saturating_duration(start: u64, end: u64) -> u64 {
+    end.saturating_sub(start)
+}
+
+/// `saturating_duration` clamped into the 4-byte wire fields.
+#[inline(always)]
+pub fn saturating_duration_u32(start: u64, end: u64) -> u32 {
+    saturating_duration(start, end).min(u32::MAX as u64) as u32
+}
+
+/// Engine clock in Nanoseconds since the Unix epoch.
+///
+/// The wall clock is read only once, at boot; after that time advances with the
+/// monotonic clock. NTP steps therefore never move engine time backwards, which
+/// keeps receive timestamps ordered and every duration non-negative.
 pub fn current_timestamp() -> u64 {
-    //time::Instant::now().elapsed().as_nanos() as u64
-    let now_nanos = SystemTime::now()
-        .duration_since(UNIX_EPOCH)
-        .expect("fail")
-        .as_nanos() as u64;
-    now_nanos
+    let (boot_wall_ns, boot_instant) = CLOCK_ANCHOR.get_or_init(|| {
+        let wall_ns = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .expect("fail")
+            .as_nanos() as u64;
+        (wall_ns, Instant::now())
+    });
+    boot_wall_ns + boot_instant.elapsed().as_nanos() as u64
 }
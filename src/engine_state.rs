@@ -1,144 +1,785 @@
-use crate::data_types::{BroadcastStats, CallAuctionPool, EngineState, MESSAGE_TOTAL_SIZE};
-use crate::message_codec;
+use std::fs::{self, File};
+use std::io::{self, BufWriter, Read, Write};
+use std::path::Path;
+use std::sync::Arc;
+use std::sync::mpsc::{self, Receiver};
+use std::time::Instant;
+
+use ahash::AHashMap;
+
+use crate::config::EngineConfig;
+use crate::data_types::{AuctionExtended, AuctionSummary, BookView, BroadcastStats, CallAuctionPool, EngineState, MESSAGE_TOTAL_SIZE, MSG_ORDER_SUBMIT};
+use crate::data_types::{BookState, EngineCounters, StatusExtension, MSG_SESSION_SUMMARY, MSG_SNAPSHOT_HEADER, MSG_SNAPSHOT_PRODUCT, MSG_TRADING_STATE, SnapshotHeader, SnapshotProduct};
+use crate::data_types::{ORDER_TYPE_MOCK_BUY, ORDER_TYPE_MOCK_SELL, TradingStateChange};
 
 use crate::data_types::ContinuousOrderBook;
 // use crate::data_types::CallAuctionPool;
-use crate::data_types::{
-     ORDER_PRICE_TYPE_LIMIT, ORDER_TYPE_BUY, ORDER_TYPE_SELL, Order,
-};
-use std::sync::Arc;
-use std::time::{SystemTime, UNIX_EPOCH};
+use crate::data_types::{DepthLevel, MARKET_DATA_DEPTH, MarketDataSnapshot, MatchResult, Order, RejectReason, TestBookScenario, TimestampSource, TradingState, UnknownProductPolicy};
+use crate::date_time_tool::{current_timestamp, saturating_duration};
+use crate::message_codec::{self, CodecError};
+use crate::test_order_book_builder::TestOrderBookBuilder;
+use crate::log_warn;
 
 
 impl EngineState {
     /// Creates a new EngineState instance with initialized components.
-    pub fn new(instance_tag: [u8; 16], product_id: u16) -> Self {
-        let now_nanos = SystemTime::now()
-            .duration_since(UNIX_EPOCH)
-            .expect("fail")
-            .as_nanos() as u64;
+    ///
+    /// Sockets are owned by the `NetworkHandler`, not the state, so the book can be
+    /// driven by the local benchmark without any network setup.
+    pub fn new(config: &EngineConfig) -> Self {
+        let instance_tag = config.instance_tag_bytes();
+        let product_id = config.product_id;
+        let now_nanos = current_timestamp();
+
+        // products with negative prices centre the ladder on zero
+        let base_price = if config.allow_negative_prices { -500_000 * 100000 } else { 1 };
+        let mut continuous_order_book = ContinuousOrderBook::new(100000, base_price,1_000_000,100);
+        continuous_order_book.instance_tag = instance_tag;
+        continuous_order_book.priority_mode = config.priority_mode;
+        continuous_order_book.set_bucket_layout(config.bucket_layout);
+        continuous_order_book.max_slippage_ticks = config.max_slippage_ticks;
+        continuous_order_book.price_band_ticks = config.price_band_ticks;
+        continuous_order_book.trade_price_mode = config.trade_price_mode;
+        continuous_order_book.reference_price = config.reference_price;
+        continuous_order_book.price_rounding = config.price_rounding;
+        continuous_order_book.auction_rules = config.auction_rules.clone();
+        continuous_order_book.max_orders_per_side = config.max_orders_per_side;
+        continuous_order_book.book_full_policy = config.book_full_policy;
+        continuous_order_book.mock_match_policy = config.mock_match_policy;
+        continuous_order_book.compact_floor = config.compact_floor;
 
         EngineState {
             instance_tag,
             product_id,
+            allow_negative_prices: config.allow_negative_prices,
+            lot_size: config.lot_size,
+            shard_index: config.shard_index,
+            shard_count: config.shard_count,
+            wash_trade_policy: config.wash_trade_policy,
+            timestamp_source: config.timestamp_source,
+            last_engine_stamp: 0,
+            max_clock_skew_ns: config.max_clock_skew_ms.map(|ms| ms * 1_000_000),
+            products: config.products.clone(),
+            unknown_product_policy: config.unknown_product_policy,
+            trading_states: AHashMap::new(),
             //continuous_order_book: Arc::new((ContinuousOrderBook::new(10000, 100)),
             //call_auction_pool:Arc::new(CallAuctionPool::new(10000)),
-            continuous_order_book: ContinuousOrderBook::new(100000, 1,1_000_000,100),
+            continuous_order_book,
+            product_books: AHashMap::new(),
+            book_state_sender: None,
             call_auction_pool: CallAuctionPool::new(1000),
-            matched_orders: 0,
-            total_received_orders:0 ,
+            counters: Arc::new(EngineCounters::default()),
             start_time: now_nanos,
+            started_at: Instant::now(),
+            stats_dirty: false,
+            clock_skew_count: 0,
+            malformed_frames: 0,
+            checksum_failures: 0,
+            cancelled_orders: 0,
+            sequence: 0,
         }
     }
     
-    /// Creates a self-contained handler for status broadcasting logic.
+    /// Whether `order_id` belongs to this instance's shard. Submits and cancels use the
+    /// same rule, so a cancel always lands on the instance holding the order.
+    pub fn owns_order(&self, order_id: u64) -> bool {
+        order_id % self.shard_count as u64 == self.shard_index as u64
+    }
 
-    
+    /// Under `TimestampSource::Engine`, replaces the client's submit_time with the
+    /// engine's receive time. Orders read from one datagram share a receive time, so
+    /// each stamp is at least one nanosecond past the last to keep arrival order.
+    /// Returns whether the engine stamped the order.
+    pub fn stamp_submit_time(&mut self, order: &mut Order, received_time: u64) -> bool {
+        if self.timestamp_source != TimestampSource::Engine {
+            return false;
+        }
+        self.last_engine_stamp = received_time.max(self.last_engine_stamp + 1);
+        order.submit_time = self.last_engine_stamp;
+        true
+    }
+
+    /// Whether orders for `product_id` are handled by this instance: configured and
+    /// registered products, or any product under `UnknownProductPolicy::AutoCreate`.
+    pub fn accepts_product(&self, product_id: u16) -> bool {
+        product_id == self.product_id
+            || self.products.contains(product_id)
+            || self.product_books.contains_key(&product_id)
+            || self.unknown_product_policy == UnknownProductPolicy::AutoCreate
+    }
+
+    /// Trading state of `product_id`; products never moved are `Continuous`.
+    pub fn trading_state(&self, product_id: u16) -> TradingState {
+        self.trading_states.get(&product_id).copied().unwrap_or_default()
+    }
+
+    /// Book of `product_id`, if it has one yet.
+    pub fn book(&self, product_id: u16) -> Option<&ContinuousOrderBook> {
+        if product_id == self.product_id {
+            Some(&self.continuous_order_book)
+        } else {
+            self.product_books.get(&product_id)
+        }
+    }
+
+    /// Mutable book of `product_id`, if it has one yet.
+    pub fn book_mut(&mut self, product_id: u16) -> Option<&mut ContinuousOrderBook> {
+        if product_id == self.product_id {
+            Some(&mut self.continuous_order_book)
+        } else {
+            self.product_books.get_mut(&product_id)
+        }
+    }
+
+    /// Book of an accepted product. Further products get an empty book with the same
+    /// settings as the instance's own on first use; `None` for products not accepted.
+    pub fn open_book(&mut self, product_id: u16) -> Option<&mut ContinuousOrderBook> {
+        if product_id == self.product_id {
+            return Some(&mut self.continuous_order_book);
+        }
+        if !self.accepts_product(product_id) {
+            return None;
+        }
+        Some(self.register_product(product_id))
+    }
+
+    /// Opens the book of `product_id` whatever the unknown product policy, so later
+    /// orders for it are accepted. Registering a product twice keeps its book.
+    pub fn register_product(&mut self, product_id: u16) -> &mut ContinuousOrderBook {
+        if product_id == self.product_id {
+            return &mut self.continuous_order_book;
+        }
+        let template = &self.continuous_order_book;
+        let book_state_sender = &self.book_state_sender;
+        self.product_books.entry(product_id).or_insert_with(|| {
+            let mut book = template.empty_like();
+            if let Some(sender) = book_state_sender {
+                book.publish_book_state(product_id, sender.clone());
+            }
+            book
+        })
+    }
+
+    /// Returns a receiver for the `BookState` transitions of every book, tagged with
+    /// the product. Books opened later publish to it as well.
+    pub fn subscribe_book_states(&mut self) -> Receiver<(u16, BookState)> {
+        let (sender, receiver) = mpsc::channel();
+        for (product_id, book) in self.books_mut() {
+            book.publish_book_state(product_id, sender.clone());
+        }
+        self.book_state_sender = Some(sender);
+        receiver
+    }
+
+    /// Every book with its product, the instance's own product first.
+    pub fn books(&self) -> impl Iterator<Item = (u16, &ContinuousOrderBook)> {
+        std::iter::once((self.product_id, &self.continuous_order_book))
+            .chain(self.product_books.iter().map(|(product_id, book)| (*product_id, book)))
+    }
+
+    /// Every book with its product, mutably.
+    pub fn books_mut(&mut self) -> impl Iterator<Item = (u16, &mut ContinuousOrderBook)> {
+        std::iter::once((self.product_id, &mut self.continuous_order_book))
+            .chain(self.product_books.iter_mut().map(|(product_id, book)| (*product_id, book)))
+    }
+
+    /// Captures the instance-wide status sent after the per-book status frames. The
+    /// queue high-water mark starts over with each capture.
+    pub fn build_status_extension(&self) -> StatusExtension {
+        StatusExtension {
+            instance_tag: self.instance_tag,
+            uptime_ns: self.started_at.elapsed().as_nanos() as u64,
+            engine_version: engine_version(),
+            queue_depth: self.counters.queued().min(u32::MAX as u64) as u32,
+            max_queue_depth: self.counters.take_max_queued().min(u32::MAX as u64) as u32,
+        }
+    }
+
+    /// Captures one status frame per book: the product's order counts, volumes and
+    /// counters, with the instance-wide error counters repeated in each.
+    pub fn build_stats(&self) -> Vec<BroadcastStats> {
+        self.books()
+            .map(|(product_id, book)| {
+                let view = book.snapshot_view(0);
+                BroadcastStats {
+                    instance_tag: self.instance_tag,
+                    product_id,
+                    bids_order_count: view.bid_order_count,
+                    ask_order_count: view.ask_order_count,
+                    matched_orders: book.matched_orders as u32,
+                    total_received_orders: book.received_orders as u32,
+                    start_time: self.start_time,
+                    total_bid_volumn: view.total_bid_volume,
+                    total_ask_volumn: view.total_ask_volume,
+                    clock_skew_count: self.clock_skew_count as u32,
+                    malformed_frames: self.malformed_frames as u32,
+                    checksum_failures: self.checksum_failures as u32,
+                }
+            })
+            .collect()
+    }
+
+    /// Market data frame of one book from a view of at least `MARKET_DATA_DEPTH`
+    /// levels, quantities in base units.
+    pub fn market_data_of(&self, product_id: u16, view: &BookView) -> MarketDataSnapshot {
+        let bids = &view.bids[..view.bids.len().min(MARKET_DATA_DEPTH)];
+        let asks = &view.asks[..view.asks.len().min(MARKET_DATA_DEPTH)];
+        let mut snapshot = MarketDataSnapshot {
+            product_id,
+            bid_count: bids.len() as u8,
+            ask_count: asks.len() as u8,
+            bids: [DepthLevel::default(); MARKET_DATA_DEPTH],
+            asks: [DepthLevel::default(); MARKET_DATA_DEPTH],
+        };
+        snapshot.bids[..bids.len()].copy_from_slice(bids);
+        snapshot.asks[..asks.len()].copy_from_slice(asks);
+        for level in snapshot.bids.iter_mut().chain(snapshot.asks.iter_mut()) {
+            level.quantity = level.quantity.saturating_mul(self.lot_size);
+        }
+        snapshot
+    }
+
+    /// Creates the one status broadcaster that should drive status frames for this engine.
+    pub fn new_status_broadcaster(&self) -> StatusBroadcaster {
+        StatusBroadcaster::new(STATUS_MIN_INTERVAL_NS, STATUS_MAX_INTERVAL_NS)
+    }
 
     pub  fn increase_match(&mut self) {
-        self.matched_orders  = self.matched_orders + 1;
+        self.counters.add_matched(1);
         
     }
 
+    /// Matches the order in its product's book. Orders for products this instance does
+    /// not accept are counted and ignored; the matcher rejects them before this point.
     pub  fn match_order(&mut self, new_order: Order) {
-        
-        self.continuous_order_book.match_order(new_order);
+        self.counters.add_received();
+        let product_id = new_order.product_id;
+        if let Some(book) = self.open_book(product_id) {
+            book.received_orders += 1;
+            book.match_order(new_order);
+            self.record_executions(product_id);
+        }
+    }
 
+    /// Atomically replaces a resting order; counted like a submission when it goes through.
+    pub fn replace_order(&mut self, old_order_id: u64, new_order: Order) -> Result<(), RejectReason> {
+        let product_id = new_order.product_id;
+        let book = self.open_book(product_id).ok_or(RejectReason::UnknownProduct)?;
+        book.cancel_replace(old_order_id, new_order)?;
+        book.received_orders += 1;
+        self.counters.add_received();
+        self.record_executions(product_id);
+        Ok(())
     }
 
-    pub  fn load_sample_test_book(&mut self, test_order_book_size:u32 ) {
-        
-        for i in 0..test_order_book_size {
-            let order = self.create_buy_order(i);
-            self.continuous_order_book.fuel_order(order);
+    /// Uncrosses the book of `product_id` in a call auction as it reopens for continuous
+    /// trading; the fills count like matches. `None` when the product has no book.
+    pub fn run_auction(&mut self, product_id: u16) -> Option<Result<(MatchResult, AuctionSummary), AuctionExtended>> {
+        let book = if product_id == self.product_id {
+            &mut self.continuous_order_book
+        } else {
+            self.product_books.get_mut(&product_id)?
+        };
+        let uncrossed = book.run_auction(&mut self.call_auction_pool, product_id);
+        if let Ok((result, _)) = &uncrossed {
+            let executions = result.real_count() as u64;
+            if executions > 0 {
+                book.matched_orders += executions;
+                self.counters.add_matched(executions);
+                self.stats_dirty = true;
+            }
         }
-        for i in 0..test_order_book_size {
-            let order = self.create_sell_order(i, test_order_book_size);
-            self.continuous_order_book.fuel_order(order);
+        Some(uncrossed)
+    }
+
+    /// Adds the real executions of the last match to the matched count. Mock fills
+    /// never traded, so they stay out of the status frame.
+    fn record_executions(&mut self, product_id: u16) {
+        let Some(book) = self.book_mut(product_id) else {
+            return;
+        };
+        let executions = book.match_result.real_count();
+        if executions > 0 {
+            book.matched_orders += executions as u64;
+            self.counters.add_matched(executions as u64);
+            self.stats_dirty = true;
         }
+    }
 
+    /// Loads a synthetic book. Non-crossing shapes are fuelled straight into the
+    /// book; crossing shapes go through the matcher so their fills are produced.
+    pub  fn load_sample_test_book(&mut self, scenario: TestBookScenario, test_order_book_size:u32 ) {
+        let orders = TestOrderBookBuilder::new(self.product_id).build(scenario, test_order_book_size);
+        for order in orders {
+            match scenario {
+                TestBookScenario::NonCrossing | TestBookScenario::OneSided => {
+                    self.continuous_order_book.fuel_order(order)
+                }
+                TestBookScenario::Crossing | TestBookScenario::Random(_) => self.match_order(order),
+            }
+        }
     }
 
+    /// Writes the engine to `path`: a header frame with the write-ahead log sequence and
+    /// the counters, then for each product its counters and session summary frames
+    /// followed by its resting orders as order frames, bids then asks in priority order,
+    /// then those of its mock book as mock orders, and last a trading state frame for
+    /// every product whose state was set. Returns how many orders were written.
+    ///
+    /// The file is written next to `path` and renamed over it, so a crash mid-write
+    /// leaves the previous snapshot intact.
+    pub fn snapshot_to(&self, path: &Path) -> io::Result<usize> {
+        let mut tmp_path = path.as_os_str().to_owned();
+        tmp_path.push(".tmp");
 
-    
-    pub fn create_buy_order(&self, index: u32) -> Order {
-        //let time_now = time::Instant::now().elapsed().as_nanos() as u64;
-        let time_now = SystemTime::now()
-            .duration_since(UNIX_EPOCH)
-            .expect("fail")
-            .as_nanos() as u64;
-        Order {
-            product_id: self.product_id,
-            order_id: (index + 1) as u64,
-            order_type: ORDER_TYPE_BUY,
-            price_type: ORDER_PRICE_TYPE_LIMIT,
-            price: (index + 1) as u64,
-            quantity: 2,
-            submit_time: time_now,
-            expire_time: time_now + 1000 * 1000 * 1000 * 1000 * 10,
+        let mut writer = BufWriter::new(File::create(&tmp_path)?);
+        writer.write_all(&message_codec::serialize_snapshot_header(&SnapshotHeader {
+            sequence: self.sequence,
+            matched_orders: self.counters.matched(),
+            total_received_orders: self.counters.received(),
+            cancelled_orders: self.cancelled_orders,
+            clock_skew_count: self.clock_skew_count,
+            malformed_frames: self.malformed_frames,
+            checksum_failures: self.checksum_failures,
+        }))?;
+        let mut written = 0;
+        for (product_id, book) in self.books() {
+            writer.write_all(&message_codec::serialize_snapshot_product(&SnapshotProduct {
+                product_id,
+                received_orders: book.received_orders,
+                matched_orders: book.matched_orders,
+            }))?;
+            let session = &book.session;
+            writer.write_all(&message_codec::serialize_session_summary(
+                product_id,
+                session.volume,
+                session.turnover,
+                session.ohlc,
+            ))?;
+            for order in book.iter_bids().chain(book.iter_asks()) {
+                writer.write_all(&message_codec::serialize_order(order))?;
+                written += 1;
+            }
+            // the mock book holds its orders as plain buys and sells
+            for order in book.mock_book.iter().flat_map(|mock_book| mock_book.iter_bids().chain(mock_book.iter_asks())) {
+                let mut order = order.clone();
+                order.order_type = if order.is_buy() { ORDER_TYPE_MOCK_BUY } else { ORDER_TYPE_MOCK_SELL };
+                writer.write_all(&message_codec::serialize_order(&order))?;
+                written += 1;
+            }
+        }
+        for (&product_id, &state) in &self.trading_states {
+            writer.write_all(&message_codec::serialize_trading_state(&TradingStateChange { product_id, state }))?;
         }
+        writer.into_inner().map_err(|e| e.into_error())?.sync_all()?;
+        fs::rename(&tmp_path, path)?;
+        Ok(written)
     }
 
-    pub fn create_sell_order(&self, index: u32, size: u32) -> Order {
-        let time_now = SystemTime::now()
-            .duration_since(UNIX_EPOCH)
-            .expect("fail")
-            .as_nanos() as u64;
+    /// Restores a snapshot written by `snapshot_to` and returns how many orders were
+    /// rested: the counters and sequence from its header, each product's counters, session totals
+    /// and trading state, and its orders and mock orders in their saved queue order, without matching. Book counters and
+    /// volumes are rebuilt from the orders themselves. A locked or crossed book is
+    /// restored as it was: resting AON and min-qty orders can legitimately leave it so.
+    /// Files without a header, from before it was written, restore orders only.
+    pub fn load_snapshot(&mut self, path: &Path) -> io::Result<usize> {
+        let bytes = read_frames(path, "snapshot")?;
+        let mut loaded = 0;
+        for frame in bytes.as_chunks::<MESSAGE_TOTAL_SIZE>().0 {
+            match message_codec::unpack_message_payload(frame) {
+                Ok((MSG_SNAPSHOT_HEADER, _, payload)) => match message_codec::deserialize_snapshot_header(payload) {
+                    Ok(header) => self.restore_counters(&header),
+                    Err(e) => log_warn!("Skipping snapshot header: {}", e),
+                },
+                Ok((MSG_SNAPSHOT_PRODUCT, _, payload)) => match message_codec::deserialize_snapshot_product(payload) {
+                    Ok(product) => {
+                        let book = self.register_product(product.product_id);
+                        book.received_orders = product.received_orders;
+                        book.matched_orders = product.matched_orders;
+                    }
+                    Err(e) => log_warn!("Skipping snapshot product counters: {}", e),
+                },
+                Ok((MSG_SESSION_SUMMARY, _, payload)) => match message_codec::deserialize_session_summary(payload) {
+                    // the product had a book when written, so it gets one back whatever the policy
+                    Ok((product_id, session)) => self.register_product(product_id).session = session,
+                    Err(e) => log_warn!("Skipping snapshot session summary: {}", e),
+                },
+                Ok((MSG_ORDER_SUBMIT, version, payload)) => {
+                    let order = message_codec::deserialize_order(payload, version);
+                    loaded += matches!(self.rest_loaded_order(order, "snapshot", false), LoadedOrder::Rested) as usize;
+                }
+                Ok((MSG_TRADING_STATE, _, payload)) => match message_codec::deserialize_trading_state(payload) {
+                    Ok(change) => {
+                        self.trading_states.insert(change.product_id, change.state);
+                    }
+                    Err(e) => log_warn!("Skipping snapshot trading state: {}", e),
+                },
+                Ok((message_type, _, _)) => log_warn!("Skipping snapshot frame: {}", CodecError::UnknownMessageType(message_type)),
+                Err(e) => log_warn!("Skipping snapshot frame: {}", e),
+            }
+        }
+        self.stats_dirty = true;
+        Ok(loaded)
+    }
 
-        Order {
-            product_id: self.product_id,
-            order_id: (size + index + 1) as u64,
-            order_type: ORDER_TYPE_SELL,
-            price_type: ORDER_PRICE_TYPE_LIMIT,
-            price: (size + 1 + index) as u64,
-            quantity: 2,
-            submit_time: time_now,
-            expire_time: time_now + 1000 * 1000 * 1000 * 1000 * 10,
+    fn restore_counters(&mut self, header: &SnapshotHeader) {
+        self.sequence = header.sequence;
+        self.counters.restore(header.matched_orders, header.total_received_orders);
+        self.cancelled_orders = header.cancelled_orders;
+        self.clock_skew_count = header.clock_skew_count;
+        self.malformed_frames = header.malformed_frames;
+        self.checksum_failures = header.checksum_failures;
+    }
+
+    /// Rests a hand-made list of order frames before the engine starts matching, to
+    /// stand up a replica or a test book. Orders crossing the opposite side are
+    /// skipped, since nothing matches them, and counted apart from the rested ones.
+    pub fn load_seed_book(&mut self, path: &Path) -> io::Result<SeedBookLoad> {
+        let bytes = read_frames(path, "seed")?;
+        let mut load = SeedBookLoad::default();
+        for frame in bytes.as_chunks::<MESSAGE_TOTAL_SIZE>().0 {
+            let order = match message_codec::unpack_message_payload(frame) {
+                Ok((MSG_ORDER_SUBMIT, version, payload)) => message_codec::deserialize_order(payload, version),
+                Ok((message_type, _, _)) => Err(CodecError::UnknownMessageType(message_type)),
+                Err(e) => Err(e),
+            };
+            match self.rest_loaded_order(order, "seed", true) {
+                LoadedOrder::Rested => load.rested += 1,
+                LoadedOrder::Crossing => load.crossing += 1,
+                LoadedOrder::Skipped => load.skipped += 1,
+            }
         }
+        self.stats_dirty = true;
+        Ok(load)
     }
-    
 
+    /// Rests one order read from `source` without matching.
+    ///
+    /// Frames that failed to decode are skipped, and so are orders that would be
+    /// rejected or could not rest: another shard's, an unaccepted product, a negative
+    /// price where those are not allowed, a price off the ladder, a duplicate order id,
+    /// or, with `skip_crossing`, a price crossing the opposite side.
+    fn rest_loaded_order(&mut self, order: Result<Order, CodecError>, source: &str, skip_crossing: bool) -> LoadedOrder {
+        let order = match order {
+            Ok(order) => order,
+            Err(e) => {
+                log_warn!("Skipping {} frame: {}", source, e);
+                return LoadedOrder::Skipped;
+            }
+        };
+        if !self.owns_order(order.order_id) {
+            log_warn!("Skipping {} order {}: another shard's", source, order.order_id);
+            return LoadedOrder::Skipped;
+        }
+        if order.price < 0 && !self.allow_negative_prices {
+            log_warn!("Skipping {} order {}: negative price {}", source, order.order_id, order.price);
+            return LoadedOrder::Skipped;
+        }
+        let Some(book) = self.open_book(order.product_id) else {
+            log_warn!("Skipping {} order {}: product {} not handled", source, order.order_id, order.product_id);
+            return LoadedOrder::Skipped;
+        };
+        // a mock order rests only where mock orders keep a book of their own
+        if !book.rests_residual(&order) {
+            log_warn!("Skipping {} order {}: not restable here", source, order.order_id);
+            return LoadedOrder::Skipped;
+        }
+        if !book.price_in_range(order.price) {
+            log_warn!("Skipping {} order {}: price {} off the ladder", source, order.order_id, order.price);
+            return LoadedOrder::Skipped;
+        }
+        let resting_book = book.resting_book(&order);
+        if resting_book.is_some_and(|book| book.get_order(order.order_id).is_some()) {
+            log_warn!("Skipping {} order {}: order id already rests", source, order.order_id);
+            return LoadedOrder::Skipped;
+        }
+        let crosses = skip_crossing
+            && resting_book.is_some_and(|book| {
+                if order.is_buy() {
+                    book.iter_asks().next().is_some_and(|ask| ask.price <= order.price)
+                } else {
+                    book.iter_bids().next().is_some_and(|bid| bid.price >= order.price)
+                }
+            });
+        if crosses {
+            log_warn!("Skipping {} order {}: price {} crosses the book", source, order.order_id, order.price);
+            return LoadedOrder::Crossing;
+        }
+        book.fuel_order(order);
+        LoadedOrder::Rested
+    }
+}
 
+/// Outcome of one seed book load.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct SeedBookLoad {
+    pub rested: usize,
+    /// Orders skipped for crossing the opposite side.
+    pub crossing: usize,
+    /// Frames skipped for anything else: undecodable, or an order that could not rest.
+    pub skipped: usize,
 }
 
+/// What became of one order read from a snapshot or seed file.
+enum LoadedOrder {
+    Rested,
+    Crossing,
+    Skipped,
+}
+
+/// Major, minor and patch of this build, each capped at 255.
+pub fn engine_version() -> [u8; 3] {
+    let part = |s: &str| s.parse::<u64>().map_or(0, |n| n.min(u8::MAX as u64) as u8);
+    [
+        part(env!("CARGO_PKG_VERSION_MAJOR")),
+        part(env!("CARGO_PKG_VERSION_MINOR")),
+        part(env!("CARGO_PKG_VERSION_PATCH")),
+    ]
+}
+
+/// Reads a file of whole frames for `source`, refusing one cut mid-frame.
+fn read_frames(path: &Path, source: &str) -> io::Result<Vec<u8>> {
+    let mut bytes = Vec::new();
+    File::open(path)?.read_to_end(&mut bytes)?;
+    if bytes.len() % MESSAGE_TOTAL_SIZE != 0 {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("{} file size {} is not a whole number of frames", source, bytes.len()),
+        ));
+    }
+    Ok(bytes)
+}
+
+/// Shortest gap between two status frames, used right after trading activity.
+pub const STATUS_MIN_INTERVAL_NS: u64 = 100 * 1_000_000;
+/// Longest gap between two status frames while the book is idle.
+pub const STATUS_MAX_INTERVAL_NS: u64 = 10 * 1_000_000_000;
+
 /// Handler responsible for periodically broadcasting the engine's current state/stats.
+///
+/// The interval adapts to activity: once the matcher marks the state dirty the next
+/// frame goes out as soon as `min_interval_ns` allows, and every idle broadcast
+/// doubles the interval until it reaches `max_interval_ns`.
+#[derive(Debug)]
 pub struct StatusBroadcaster {
-    state: Arc<EngineState>,
+    pub min_interval_ns: u64,
+    pub max_interval_ns: u64,
+    pub current_interval_ns: u64,
+    pub last_broadcast_ns: Option<u64>,
 }
 
 impl StatusBroadcaster {
+    pub fn new(min_interval_ns: u64, max_interval_ns: u64) -> Self {
+        Self {
+            min_interval_ns,
+            max_interval_ns: max_interval_ns.max(min_interval_ns),
+            current_interval_ns: min_interval_ns,
+            last_broadcast_ns: None,
+        }
+    }
+
+    /// Returns the status frames of every product if they are due at `now_ns`, or
+    /// none at all.
+    pub fn poll(&mut self, state: &mut EngineState, now_ns: u64) -> Vec<BroadcastStats> {
+        let elapsed = match self.last_broadcast_ns {
+            Some(last) => saturating_duration(last, now_ns),
+            None => u64::MAX,
+        };
+
+        if state.stats_dirty {
+            if elapsed < self.min_interval_ns {
+                return Vec::new();
+            }
+            self.current_interval_ns = self.min_interval_ns;
+        } else if elapsed < self.current_interval_ns {
+            return Vec::new();
+        } else if self.last_broadcast_ns.is_some() {
+            self.current_interval_ns = (self.current_interval_ns * 2).min(self.max_interval_ns);
+        }
+
+        state.stats_dirty = false;
+        self.last_broadcast_ns = Some(now_ns);
+        state.build_stats()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::parse_config;
+    use crate::data_types::{ORDER_PRICE_TYPE_LIMIT, ORDER_TYPE_BUY, ORDER_TYPE_SELL, TIF_GTC};
+
+    const PRODUCT: u16 = 7;
+
+    fn state() -> EngineState {
+        state_with(&[])
+    }
+
+    fn state_with(flags: &[&str]) -> EngineState {
+        let args: Vec<String> = ["engine", "--prodid", "7"].iter().chain(flags).map(|arg| arg.to_string()).collect();
+        EngineState::new(&parse_config(&args).unwrap())
+    }
+
+    /// What identifies each resting order, in queue order.
+    fn resting(book: &ContinuousOrderBook) -> Vec<(u64, u8, i64, u32, u64)> {
+        book.iter_bids()
+            .chain(book.iter_asks())
+            .map(|order| (order.order_id, order.order_type, order.price, order.quantity, order.submit_time))
+            .collect()
+    }
 
-    // pub async fn run_status_broadcast(&self) {
-
-
-    //     println!("Status broadcaster started.");
-
-    //     loop {
-    //         // Wait for the next tick
-
-    //         // 1. Lock necessary shared data
-    //         let continuous_order_book = self.state.continuous_order_book;
-    //         let matched_orders = self.state.matched_orders;
-    //         let total_received_orders = self.state.total_received_orders;
-
-    //         // 2. Construct the stats message
-    //         let stats = BroadcastStats {
-    //             instance_tag: self.state.instance_tag,
-    //             product_id: self.state.product_id,
-    //             bids_order_count: continuous_order_book.bids.len() as u32,
-    //             ask_order_count: continuous_order_book.asks.len() as u32,
-    //             matched_orders: matched_orders as u32,
-    //             total_received_orders: total_received_orders as u32,
-    //             start_time: self.state.start_time,
-    //             total_bid_volumn: continuous_order_book.total_bid_volumn,
-    //             total_ask_volumn: continuous_order_book.total_ask_volumn,
-    //         };
-    //         //println!("status info {:?}", stats);
-            
-    //         // 3. Serialize and send
-    //         let buf: [u8; MESSAGE_TOTAL_SIZE] = message_codec::serialize_stats_result(&stats);
-            
-    //         println!("{:?}",buf)
-    //     }
-    // }
+    fn limit(order_id: u64, order_type: u8, price: i64, quantity: u32) -> Order {
+        Order {
+            product_id: PRODUCT,
+            order_type,
+            price_type: ORDER_PRICE_TYPE_LIMIT,
+            quantity,
+            order_id,
+            price,
+            submit_time: order_id,
+            expire_time: 0,
+            time_in_force: TIF_GTC,
+            min_qty: 0,
+            aon: false,
+            trader_id: 0,
+            client_ref: [0; 8],
+            display_quantity: 0,
+        }
+    }
+
+    /// A file in the temp directory, named after the test, removed when dropped.
+    struct TempFile(std::path::PathBuf);
+
+    impl TempFile {
+        fn new(name: &str) -> Self {
+            TempFile(std::env::temp_dir().join(format!("{}-{}", name, std::process::id())))
+        }
+    }
+
+    impl Drop for TempFile {
+        fn drop(&mut self) {
+            let _ = fs::remove_file(&self.0);
+        }
+    }
+
+    #[test]
+    fn seed_book_rests_non_crossing_orders_and_counts_the_rest() {
+        let file = TempFile::new("seed-book");
+        let mut frames = Vec::new();
+        for order in [
+            limit(1, ORDER_TYPE_BUY, 100_001, 10),
+            limit(2, ORDER_TYPE_BUY, 200_001, 20),
+            limit(3, ORDER_TYPE_SELL, 400_001, 30),
+            limit(4, ORDER_TYPE_SELL, 300_001, 40),
+            // crosses the best ask of 300_001
+            limit(5, ORDER_TYPE_BUY, 300_001, 50),
+            // duplicate id
+            limit(2, ORDER_TYPE_SELL, 500_001, 60),
+        ] {
+            frames.extend_from_slice(&message_codec::serialize_order(&order));
+        }
+        fs::write(&file.0, &frames).unwrap();
+
+        let mut state = state();
+        let load = state.load_seed_book(&file.0).unwrap();
+        assert_eq!(load, SeedBookLoad { rested: 4, crossing: 1, skipped: 1 });
+
+        let book = state.book(PRODUCT).unwrap();
+        assert_eq!((book.bid_order_count, book.ask_order_count), (2, 2));
+        assert_eq!((book.total_bid_volumn, book.total_ask_volumn), (30, 70));
+        assert_eq!(book.best_prices(), (Some(200_001), Some(300_001)));
+    }
+
+    #[test]
+    fn snapshot_restores_books_mock_books_and_trading_states() {
+        let file = TempFile::new("snapshot");
+        let mut saved = state_with(&["--products", "8", "--mock-policy", "mock-only"]);
+        for order in [
+            limit(1, ORDER_TYPE_BUY, 100_001, 10),
+            limit(2, ORDER_TYPE_BUY, 100_001, 20),
+            limit(3, ORDER_TYPE_SELL, 300_001, 30),
+            // partly fills order 3
+            limit(4, ORDER_TYPE_BUY, 300_001, 5),
+            limit(5, ORDER_TYPE_MOCK_BUY, 200_001, 40),
+            limit(6, ORDER_TYPE_MOCK_SELL, 400_001, 50),
+            Order { product_id: 8, ..limit(7, ORDER_TYPE_SELL, 500_001, 60) },
+        ] {
+            saved.match_order(order);
+        }
+        saved.trading_states.insert(PRODUCT, TradingState::Halted);
+        saved.trading_states.insert(8, TradingState::PreOpen);
+        let written = saved.snapshot_to(&file.0).unwrap();
+        assert_eq!(written, 6);
+
+        let mut loaded = state_with(&["--products", "8", "--mock-policy", "mock-only"]);
+        assert_eq!(loaded.load_snapshot(&file.0).unwrap(), 6);
+
+        assert_eq!(loaded.counters.matched(), saved.counters.matched());
+        assert_eq!(loaded.counters.received(), saved.counters.received());
+        assert_eq!(loaded.trading_state(PRODUCT), TradingState::Halted);
+        assert_eq!(loaded.trading_state(8), TradingState::PreOpen);
+        for product_id in [PRODUCT, 8] {
+            let (before, after) = (saved.book(product_id).unwrap(), loaded.book(product_id).unwrap());
+            assert_eq!(resting(after), resting(before));
+            assert_eq!(after.session, before.session);
+            assert_eq!((after.received_orders, after.matched_orders), (before.received_orders, before.matched_orders));
+            assert_eq!(after.mock_book.as_deref().map(resting), before.mock_book.as_deref().map(resting));
+        }
+        assert_eq!(resting(loaded.book(PRODUCT).unwrap().mock_book.as_deref().unwrap()).len(), 2);
+    }
+
+    #[test]
+    fn status_extension_carries_version_and_uptime() {
+        let state = state();
+        std::thread::sleep(std::time::Duration::from_millis(2));
+        let frame = message_codec::serialize_status_extension(&state.build_status_extension());
+
+        let (message_type, _, payload) = message_codec::unpack_message_payload(&frame).unwrap();
+        assert_eq!(message_type, crate::data_types::MSG_STATUS_EXTENSION);
+        let extension = message_codec::deserialize_status_extension(payload).unwrap();
+        assert_eq!(extension.instance_tag, state.instance_tag);
+        let version: Vec<u8> = env!("CARGO_PKG_VERSION").split('.').map(|part| part.parse().unwrap()).collect();
+        assert_eq!(extension.engine_version.as_slice(), version);
+        assert!(extension.uptime_ns >= 2_000_000);
+    }
+
+    #[test]
+    fn counters_stay_exact_under_concurrent_increments() {
+        let counters = Arc::new(EngineCounters::default());
+        let threads: Vec<_> = (0..8)
+            .map(|_| {
+                let counters = Arc::clone(&counters);
+                std::thread::spawn(move || {
+                    for _ in 0..10_000 {
+                        counters.add_received();
+                        counters.add_matched(2);
+                    }
+                })
+            })
+            .collect();
+        for thread in threads {
+            thread.join().unwrap();
+        }
+        assert_eq!((counters.received(), counters.matched()), (80_000, 160_000));
+    }
+
+    #[test]
+    fn counters_are_read_from_another_thread_than_the_matcher() {
+        let mut state = state();
+        let counters = Arc::clone(&state.counters);
+        let matcher = std::thread::spawn(move || {
+            for i in 0..100 {
+                state.match_order(limit(2 * i + 1, ORDER_TYPE_SELL, 100_001, 10));
+                state.match_order(limit(2 * i + 2, ORDER_TYPE_BUY, 100_001, 10));
+            }
+        });
+        let mut seen = 0;
+        while !matcher.is_finished() {
+            let received = counters.received();
+            assert!(received >= seen);
+            seen = received;
+        }
+        matcher.join().unwrap();
+        assert_eq!((counters.received(), counters.matched()), (200, 100));
+    }
 }
@@ -8,6 +8,10 @@ use windows_sys::Win32::System::Performance::{
 };
 
 use std::sync::OnceLock;
+use std::sync::atomic::{AtomicU8, Ordering};
+use std::time::Instant;
+
+use crate::date_time_tool::saturating_duration;
 
 /// ------------------------------------------------------------
 /// High-Resolution Timer (Cross-Platform)
@@ -16,11 +20,49 @@ use std::sync::OnceLock;
 /// • x86_64 (Linux/macOS): rdtsc + startup calibration
 /// • ARM64 (Linux/macOS): cntvct_el0 + cntfrq_el0
 /// ------------------------------------------------------------
+///
+/// On x86 the frequency is calibrated once, so readings are only exact on CPUs
+/// with an invariant TSC (`constant_tsc` and `nonstop_tsc` in /proc/cpuinfo),
+/// whose counter ticks at one rate on every core and in every power state. The
+/// lfence on each side of rdtsc keeps it from being reordered around the code
+/// being timed. Where the counter still misbehaves (a thread migrated to a core
+/// whose TSC lags, or jumps far ahead) the measurement falls back to `Instant`.
 #[derive(Debug)]
 pub struct HighResolutionTimer {
-    start_cycles: u64
+    source: TimerSource,
+    start_cycles: u64,
+    start_instant: Instant,
+}
+
+/// Where a timer reads time from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[repr(u8)]
+pub enum TimerSource {
+    /// The hardware tick counter above (rdtsc, cntvct_el0 or QPC).
+    #[default]
+    Tsc = 0,
+    /// `Instant` only, for virtualized hosts whose TSC cannot be trusted.
+    Monotonic = 1,
+}
+
+static TIMER_SOURCE: AtomicU8 = AtomicU8::new(TimerSource::Tsc as u8);
+
+/// Chooses the source of every timer started from now on.
+pub fn set_timer_source(source: TimerSource) {
+    TIMER_SOURCE.store(source as u8, Ordering::Relaxed);
+}
+
+fn timer_source() -> TimerSource {
+    match TIMER_SOURCE.load(Ordering::Relaxed) {
+        1 => TimerSource::Monotonic,
+        _ => TimerSource::Tsc,
+    }
 }
 
+/// Longest interval a tick delta is trusted for; beyond it the reading is taken
+/// for a counter glitch and `Instant` measures it instead.
+const MAX_PLAUSIBLE_NS: u128 = 24 * 3600 * 1_000_000_000;
+
 // ==========================
 // Global tick frequency (Hz)
 // ==========================
@@ -33,22 +75,34 @@ fn global_tick_hz() -> u64 {
 }
 
 impl HighResolutionTimer {
-    /// Start the timer.
+    /// Start the timer on the source chosen with `set_timer_source`.
     ///
     /// 
     /// - tick_hz not belongs to instance
     /// - calibrate on start
     pub fn start() -> Self {
-        // 触发一次校准（如果还没做）
-        let _ = global_tick_hz();
+        Self::start_with(timer_source())
+    }
 
-        let start_cycles = Self::get_ticks();
+    /// Start the timer on `source`. A monotonic timer never calibrates or reads ticks.
+    pub fn start_with(source: TimerSource) -> Self {
+        let start_cycles = match source {
+            TimerSource::Tsc => {
+                // 触发一次校准（如果还没做）
+                let _ = global_tick_hz();
+                Self::get_ticks()
+            }
+            TimerSource::Monotonic => 0,
+        };
 
         Self {
-            start_cycles
+            source,
+            start_cycles,
+            start_instant: Instant::now(),
         }
     }
 
+
     /// Read hardware ticks
     #[inline(always)]
     fn get_ticks() -> u64 {
@@ -104,10 +158,24 @@ impl HighResolutionTimer {
 
     /// Return elapsed time in **nanoseconds** (integer)
     pub fn ns(&self) -> u128 {
+        if self.source == TimerSource::Monotonic {
+            return self.start_instant.elapsed().as_nanos();
+        }
         let end_ticks = Self::get_ticks();
-        let delta = end_ticks.wrapping_sub(self.start_cycles) as u128;
+        Self::ticks_to_ns(self.start_cycles, end_ticks, global_tick_hz())
+            .unwrap_or_else(|| self.start_instant.elapsed().as_nanos())
+    }
 
-        (delta * 1_000_000_000u128) / global_tick_hz() as u128
+    /// Converts a tick delta to nanoseconds, or `None` when the counter went
+    /// backwards or the result is implausibly large.
+    #[inline(always)]
+    fn ticks_to_ns(start_ticks: u64, end_ticks: u64, tick_hz: u64) -> Option<u128> {
+        if end_ticks < start_ticks || tick_hz == 0 {
+            return None;
+        }
+        let delta = saturating_duration(start_ticks, end_ticks) as u128;
+        let ns = (delta * 1_000_000_000u128) / tick_hz as u128;
+        (ns <= MAX_PLAUSIBLE_NS).then_some(ns)
     }
 
     pub fn us(&self) -> u64 {
@@ -183,10 +251,11 @@ fn calibrate_tsc_with_monotonic() -> u64 {
         let tsc_end = _rdtsc();
         _mm_lfence();
 
-        let delta_tsc = tsc_end - tsc_start;
-        let delta_ns =
-            (ts_end.tv_sec - ts_start.tv_sec) as u128 * 1_000_000_000u128 +
-            (ts_end.tv_nsec - ts_start.tv_nsec) as u128;
+        let delta_tsc = saturating_duration(tsc_start, tsc_end);
+        // tv_nsec can be smaller at the end than at the start, so combine signed
+        let delta_ns = ((ts_end.tv_sec - ts_start.tv_sec) as i128 * 1_000_000_000i128
+            + (ts_end.tv_nsec - ts_start.tv_nsec) as i128)
+            .max(1) as u128;
 
         (delta_tsc as u128 * 1_000_000_000u128 / delta_ns) as u64
     }
@@ -0,0 +1,114 @@
+use std::sync::atomic::{AtomicU8, Ordering};
+
+/// Log verbosity, most severe first. A message is printed when its level is at or
+/// above the configured one.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[repr(u8)]
+pub enum LogLevel {
+    Error = 1,
+    Warn = 2,
+    Info = 3,
+    Debug = 4,
+}
+
+static LOG_LEVEL: AtomicU8 = AtomicU8::new(LogLevel::Info as u8);
+
+pub fn set_log_level(level: LogLevel) {
+    LOG_LEVEL.store(level as u8, Ordering::Relaxed);
+}
+
+/// Whether a message at `level` would be printed.
+///
+/// Debug output is compiled out of release builds entirely, so debug logging on the
+/// matching path costs nothing there.
+#[inline(always)]
+pub fn log_enabled(level: LogLevel) -> bool {
+    if level == LogLevel::Debug && !cfg!(debug_assertions) {
+        return false;
+    }
+    level as u8 <= LOG_LEVEL.load(Ordering::Relaxed)
+}
+
+/// Parses `error`, `warn`, `info` or `debug`.
+pub fn parse_log_level(s: &str) -> Result<LogLevel, String> {
+    match s.to_lowercase().as_str() {
+        "error" => Ok(LogLevel::Error),
+        "warn" => Ok(LogLevel::Warn),
+        "info" => Ok(LogLevel::Info),
+        "debug" => Ok(LogLevel::Debug),
+        _ => Err(format!(
+            "Invalid log level: '{}'. Expected error, warn, info or debug.",
+            s
+        )),
+    }
+}
+
+/// Lets one message through per interval and counts the ones it held back, for
+/// conditions that can repeat at packet rate.
+#[derive(Debug)]
+pub struct LogThrottle {
+    interval_ns: u64,
+    last_ns: Option<u64>,
+    suppressed: u64,
+}
+
+impl LogThrottle {
+    pub fn new(interval_ns: u64) -> Self {
+        Self {
+            interval_ns,
+            last_ns: None,
+            suppressed: 0,
+        }
+    }
+
+    /// Returns `Some(suppressed)` when a message may be logged now, where `suppressed`
+    /// is how many were held back since the last one; `None` when it should be dropped.
+    pub fn allow(&mut self, now_ns: u64) -> Option<u64> {
+        match self.last_ns {
+            Some(last) if now_ns.saturating_sub(last) < self.interval_ns => {
+                self.suppressed += 1;
+                None
+            }
+            _ => {
+                self.last_ns = Some(now_ns);
+                Some(std::mem::take(&mut self.suppressed))
+            }
+        }
+    }
+}
+
+#[macro_export]
+macro_rules! log_error {
+    ($($arg:tt)*) => {
+        if $crate::log_tool::log_enabled($crate::log_tool::LogLevel::Error) {
+            eprintln!("[ERROR] {}", format_args!($($arg)*));
+        }
+    };
+}
+
+#[macro_export]
+macro_rules! log_warn {
+    ($($arg:tt)*) => {
+        if $crate::log_tool::log_enabled($crate::log_tool::LogLevel::Warn) {
+            eprintln!("[WARN] {}", format_args!($($arg)*));
+        }
+    };
+}
+
+#[macro_export]
+macro_rules! log_info {
+    ($($arg:tt)*) => {
+        if $crate::log_tool::log_enabled($crate::log_tool::LogLevel::Info) {
+            println!("[INFO] {}", format_args!($($arg)*));
+        }
+    };
+}
+
+#[macro_export]
+macro_rules! log_debug {
+    ($($arg:tt)*) => {
+        if $crate::log_tool::log_enabled($crate::log_tool::LogLevel::Debug) {
+            println!("[DEBUG] {}", format_args!($($arg)*));
+        }
+    };
+}
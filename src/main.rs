@@ -12,31 +12,33 @@ mod text_output_tool;
 mod cpu_affinity;
 mod config;
 mod perf_stats;
+mod network_handler;
+mod order_matcher;
+mod matcher_actor;
+mod test_order_book_builder;
+mod bench;
+mod log_tool;
+mod audit_log;
+mod write_ahead_log;
 
 
 use data_types::{EngineState,ORDER_TYPE_BUY, 
     ORDER_TYPE_SELL,
-    ORDER_PRICE_TYPE_LIMIT};
+    ORDER_PRICE_TYPE_LIMIT, TIF_GTC};
 
-use text_output_tool::{print_centered_line,print_separator,show_result};
+use text_output_tool::{print_centered_line,print_separator,print_status,show_result};
 
-use cpu_affinity::set_core;
+use cpu_affinity::{set_core, unpin_core};
+use date_time_tool::saturating_duration;
 
 use config::get_config;
 use perf_stats::calculate_perf;
 use perf_stats::print_stats_table;
 
-use crate::{data_types::Order, high_resolution_timer::HighResolutionTimer};
-
-
+use network_handler::NetworkHandler;
+use order_matcher::OrderMatcher;
 
-fn tag_to_u16_array(tag: &str) -> [u8; 16] {
-    let mut tag_array = [0u8; 16];
-    let bytes = tag.as_bytes();
-    let len = std::cmp::min(bytes.len(), 16);
-    tag_array[..len].copy_from_slice(&bytes[..len]);
-    tag_array
-}
+use crate::{data_types::Order, high_resolution_timer::HighResolutionTimer};
 
 
 
@@ -44,34 +46,145 @@ fn tag_to_u16_array(tag: &str) -> [u8; 16] {
     println!("Starting Lighting Match Engine Core...");
 
     // 1. Get configuration
-    let (tag_string, prod_id, test_order_book_size) = match get_config() {
+    let config = match get_config() {
         Ok(config) => config,
         Err(e) => {
             eprintln!("Configuration Error: {}", e);
             eprintln!(
-                "Usage: --name <tag_16_chars_max> --prodid <u16> [--test-order-book-size 10k]"
+                "Usage: [--tag <16_chars_max>] [--name <label>] --prodid <u16> [--products 100-120|100,105,110] [--unknown-product reject|auto-create] [--test-order-book-size 10k] [--test-book-scenario crossing] [--multicast-addr 239.0.0.1:5000 [--broadcast-addr 239.0.0.2:5000] [--mock-trade-addr 239.0.0.3:5000] [--interfaces 10.0.0.5,10.1.0.5] [--multicast-ttl 1] [--multicast-loopback on|off]] [--bench 1M [--bench-warmup 10k] [--bench-compare-layouts]] [--core 1] [--receive-core 0] [--allow-negative-prices] [--lot-size 1] [--priority-mode price-time|time-price] [--bucket-layout aos|soa] [--max-slippage-ticks 10] [--price-band-ticks 50] [--trade-price resting|midpoint|aggressor|reference [--reference-price 0]] [--tape-aggregation off|by-price] [--price-rounding half-up|half-even] [--auction-tie-break volume,imbalance,pressure,reference] [--auction-reference-price 0] [--auction-max-imbalance 50] [--max-orders-per-side 1M --book-full-policy reject|evict-worst] [--mock-policy against-real|mock-only] [--wash-trade-policy off|warn|reject] [--timestamp-source client|engine] [--max-clock-skew-ms 5] [--shard-index 0 --shard-count 1] [--log-level info] [--timer-source tsc|monotonic] [--audit-log rejects.log] [--snapshot book.snap [--snapshot-interval 60]] [--wal book.wal] [--seed-book seed.bin] [--compact-interval 60] [--compact-floor 1k] [--prefault] [--checksum-policy count|alert] [--checksum-alert-threshold 100]"
             );
             return Err(e.into());
         }
     };
 
 
+    log_tool::set_log_level(config.log_level);
+    high_resolution_timer::set_timer_source(config.timer_source);
+
     println!("Configuration Loaded:");
-    println!("  Instance Tag: {}", tag_string);
-    println!("  Product ID: {}", prod_id);
-    println!("  Test order book size: {} bids and {}  asks pectively", test_order_book_size, test_order_book_size);
+    println!("  Instance Tag: {}", config.instance_tag);
+    if let Some(name) = &config.instance_name {
+        println!("  Instance Name: {}", name);
+    }
+    println!("  Product ID: {}", config.product_id);
+    if config.products != data_types::ProductSet::None {
+        println!("  Further Products: {:?}", config.products);
+    }
+    if config.unknown_product_policy != data_types::UnknownProductPolicy::Reject {
+        println!("  Unknown Products: {:?}", config.unknown_product_policy);
+    }
+    println!("  Test order book size: {} bids and {}  asks pectively", config.test_order_book_size, config.test_order_book_size);
+    if let Some(addr) = config.multicast_addr {
+        println!("  Multicast Address: {}", addr);
+    }
+    if let Some(addr) = config.broadcast_addr {
+        println!("  Broadcast Address: {}", addr);
+    }
+    if let Some(addr) = config.mock_trade_addr {
+        println!("  Mock Trade Address: {}", addr);
+    }
+    if config.multicast_addr.is_some() {
+        match config.receive_core {
+            Some(core) => println!("  Threads: receive on core {}, matcher on core {}", core, config.cpu_core),
+            None => println!("  Threads: receive unpinned, matcher on core {}", config.cpu_core),
+        }
+    }
+    if config.timer_source != high_resolution_timer::TimerSource::Tsc {
+        println!("  Timer Source: {:?}", config.timer_source);
+    }
     
     
     print_separator(100);
     
 
-    set_core(1);
+    // the books are built on the matcher's core; serving moves this thread off it
+    set_core(config.cpu_core);
 
-    let instance_tag_bytes = tag_to_u16_array(&tag_string);
+    if let Some(bench_orders) = config.bench_orders {
+        if config.bench_compare_layouts {
+            let reports = bench::compare_layouts(bench_orders as usize, config.product_id, config.bench_warmup as usize);
+            bench::print_layout_comparison(&reports);
+            return Ok(());
+        }
+        let report = bench::run_bench(
+            bench_orders as usize,
+            config.product_id,
+            config.bench_warmup as usize,
+            config.bucket_layout,
+        );
+        bench::print_bench_report(&report);
+        return Ok(());
+    }
 
     // 3. Initialize Engine State
-    let mut engine_state = EngineState::new(instance_tag_bytes, prod_id);
-    engine_state.load_sample_test_book(test_order_book_size);
+    let mut engine_state = EngineState::new(&config);
+    let mut status_broadcaster = engine_state.new_status_broadcaster();
+    if config.prefault {
+        for (product_id, book) in engine_state.books_mut() {
+            println!("Prefaulted room for {} orders in the book of product {}", book.prefault(), product_id);
+        }
+    }
+    engine_state.load_sample_test_book(config.test_book_scenario, config.test_order_book_size);
+    let mut recovered = false;
+    if let Some(path) = &config.snapshot_path
+        && path.exists()
+    {
+        let loaded = engine_state.load_snapshot(path)?;
+        println!("Recovered {} resting orders from snapshot {}", loaded, path.display());
+        recovered = true;
+    }
+    // a recovered book already holds whatever of the seed was not filled or cancelled
+    match &config.seed_book_path {
+        Some(path) if recovered => println!("Seed book {} not loaded over the recovered snapshot", path.display()),
+        Some(path) => {
+            let load = engine_state.load_seed_book(path)?;
+            println!(
+                "Seeded {} resting orders from {} ({} crossing and {} other frames skipped)",
+                load.rested,
+                path.display(),
+                load.crossing,
+                load.skipped
+            );
+        }
+        None => {}
+    }
+
+    // 4. Serve over the network when a multicast group is configured
+    if let Some(multicast_addr) = config.multicast_addr {
+        let network_handler = NetworkHandler::bind(
+            multicast_addr,
+            config.broadcast_addr.unwrap_or(multicast_addr),
+            &config.multicast_interfaces,
+        )?
+            .with_multicast_options(config.multicast_ttl, config.multicast_loopback)?
+            .with_checksum_policy(config.checksum_policy, config.checksum_alert_threshold)
+            .with_tape_aggregation(config.tape_aggregation)
+            .with_mock_trade_addr(config.mock_trade_addr);
+        let result_sender = network_handler.result_sender()?;
+        let mut order_matcher = OrderMatcher::new(engine_state, Box::new(result_sender));
+        if let Some(path) = &config.audit_log_path {
+            order_matcher = order_matcher.with_audit_log(audit_log::AuditLog::open(path)?);
+        }
+        if let Some(path) = &config.snapshot_path {
+            order_matcher = order_matcher.with_snapshot(path.clone(), config.snapshot_interval_secs * 1_000_000_000);
+        }
+        if let Some(path) = &config.wal_path {
+            let replayed = order_matcher.replay_write_ahead_log(path)?;
+            println!("Replayed {} journaled frames from {}", replayed, path.display());
+            order_matcher = order_matcher.with_write_ahead_log(write_ahead_log::WriteAheadLog::open(path)?);
+        }
+        if let Some(secs) = config.compact_interval_secs {
+            order_matcher = order_matcher.with_compaction(secs * 1_000_000_000);
+        }
+        match config.receive_core {
+            Some(core) => set_core(core),
+            None => unpin_core(),
+        };
+        network_handler::stop_on_signals();
+        network_handler.run(order_matcher, status_broadcaster, Some(config.cpu_core))?;
+        println!("Lighting Match Engine Core stopped");
+        return Ok(());
+    }
 
     let count = 10000u64;
     let timer = HighResolutionTimer::start();
@@ -91,13 +204,18 @@ fn tag_to_u16_array(tag: &str) -> [u8; 16] {
             order_id: 1_000_000_000 + i,
             submit_time:100,
             expire_time:0,
-
+            time_in_force: TIF_GTC,
+            min_qty: 0,
+            aon: false,
+            trader_id: 0,
+            client_ref: [0; 8],
+            display_quantity: 0,
         };
         
 
         engine_state.match_order(new_order_buy);
         if i > 1000 {
-            perf_data.push(engine_state.continuous_order_book.match_result.time_per_trade() as u32);
+            perf_data.extend(engine_state.continuous_order_book.match_result.order_execution_list.iter().map(|e| e.internal_match_time));
         }
         
         let new_order_sell = Order{
@@ -109,30 +227,40 @@ fn tag_to_u16_array(tag: &str) -> [u8; 16] {
             order_id: 2_000_000_000+i+1,
             submit_time:2_000_000_000+i+1,
             expire_time:0,
-
+            time_in_force: TIF_GTC,
+            min_qty: 0,
+            aon: false,
+            trader_id: 0,
+            client_ref: [0; 8],
+            display_quantity: 0,
         };
         engine_state.match_order(new_order_sell);
         if i > 1000 {
-            perf_data.push(engine_state.continuous_order_book.match_result.time_per_trade() as u32);
+            perf_data.extend(engine_state.continuous_order_book.match_result.order_execution_list.iter().map(|e| e.internal_match_time));
         }
 
     }
     let end = timer.ns() as u64;
-    println!("Time consumed {}ns for {} match requests.", (end-start),2*count);
-    println!("Speed: {} match results per second.\n", ( (1_000_000_000)*(2*count ) ) /(end-start));
+    let elapsed = saturating_duration(start, end).max(1);
+    println!("Time consumed {}ns for {} match requests.", elapsed,2*count);
+    println!("Speed: {} match results per second.\n", ( (1_000_000_000)*(2*count ) ) /elapsed);
+    let status = status_broadcaster.poll(&mut engine_state, end);
     let last_result = engine_state.continuous_order_book.match_result;
     //println!("result {:?}", engine_state.continuous_order_book.match_result);
     
     print_centered_line("Last match result",'-',80);
     if last_result.total_count()>0 {
-            println!("\nTotal time: {}ns for {} order executions, avarage {}ns per order execution\n", 
+            println!("\nTotal time: {}ns for {} order executions, avarage {:.3}ns per order execution\n", 
         last_result.total_time(), 
         last_result.total_count(),
-        last_result.total_time() / last_result.total_count() as u64);
+        last_result.time_per_trade());
     }
 
 
     show_result(last_result);
+    for stats in &status {
+        print_status(stats);
+    }
     
     if let Some(stats) = perf_stats::calculate_perf(perf_data) {
         perf_stats::print_stats_table(&stats);
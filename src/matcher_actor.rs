@@ -0,0 +1,363 @@
+use std::io;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, Sender};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+use crate::cpu_affinity::set_core;
+#[cfg(test)]
+use crate::data_types::DepthLevel;
+use crate::data_types::{EngineCounters, IncomingMessage, MESSAGE_TOTAL_SIZE};
+use crate::{log_error, log_warn};
+use crate::message_codec::CodecError;
+use crate::order_matcher::OrderMatcher;
+
+/// The matcher thread wakes up at least this often for its housekeeping while no
+/// commands arrive.
+const TICK_INTERVAL: Duration = Duration::from_millis(50);
+
+/// Resting depth of one product as `(bids, asks)`, best first.
+#[cfg(test)]
+pub type Depth = (Vec<DepthLevel>, Vec<DepthLevel>);
+
+/// One request to the matcher thread. Commands are applied in the order they were sent.
+pub enum MatcherCommand {
+    /// The decoded frames of one datagram, each with the frame it came from. All of
+    /// them are journaled before any is applied.
+    Frames {
+        frames: Vec<([u8; MESSAGE_TOTAL_SIZE], IncomingMessage)>,
+        received_time: u64,
+    },
+    /// Depth of `product_id` to `levels` levels per side, answered on `reply`; `None`
+    /// when the product has no book. Only the tests look at the books this way yet.
+    #[cfg(test)]
+    Depth {
+        product_id: u16,
+        levels: usize,
+        reply: Sender<Option<Depth>>,
+    },
+    /// A frame the receiving thread dropped undecoded, for the matcher's counters.
+    Dropped(DroppedFrame),
+}
+
+/// Why the receiving thread dropped a frame.
+pub enum DroppedFrame {
+    BadChecksum(CodecError),
+    Malformed { size: usize, src: SocketAddr },
+}
+
+/// Work the matcher thread does besides applying commands. The network side
+/// broadcasts status and market data from here.
+pub trait Housekeeping: Send + 'static {
+    /// Runs after every command, and once per `TICK_INTERVAL` while none arrive.
+    fn tick(&mut self, matcher: &mut OrderMatcher);
+
+    /// Counts and reports a frame the receiving thread dropped.
+    fn frame_dropped(&mut self, matcher: &mut OrderMatcher, dropped: DroppedFrame);
+}
+
+/// Housekeeping of a matcher nobody broadcasts for.
+impl Housekeeping for () {
+    fn tick(&mut self, _matcher: &mut OrderMatcher) {}
+
+    fn frame_dropped(&mut self, _matcher: &mut OrderMatcher, _dropped: DroppedFrame) {}
+}
+
+/// A thread of its own owning the `OrderMatcher`, and with it every book.
+///
+/// Other threads reach the books only through commands, so matching is
+/// single-threaded by construction, and a slow receive or broadcast never runs in
+/// the middle of a match.
+pub struct MatcherActor {
+    commands: Sender<MatcherCommand>,
+    counters: Arc<EngineCounters>, // the matcher's, for the depth of its queue
+    drained: Receiver<()>, // signalled once the last queued command has been applied
+    thread: JoinHandle<OrderMatcher>,
+}
+
+impl MatcherActor {
+    /// Starts the matcher thread, pinned to `core` if one is given.
+    pub fn spawn(mut matcher: OrderMatcher, mut housekeeping: impl Housekeeping, core: Option<usize>) -> io::Result<Self> {
+        let (commands, inbox) = mpsc::channel();
+        let (drained_sender, drained) = mpsc::channel();
+        let counters = Arc::clone(&matcher.state.counters);
+        let thread = thread::Builder::new().name("matcher".to_string()).spawn(move || {
+            if let Some(core) = core
+                && !set_core(core)
+            {
+                log_warn!("Failed to pin the matcher thread to core {}", core);
+            }
+            run(&mut matcher, &mut housekeeping, &inbox);
+            let _ = drained_sender.send(());
+            matcher
+        })?;
+        Ok(Self {
+            commands,
+            counters,
+            drained,
+            thread,
+        })
+    }
+
+    /// Queues `command`. False once the matcher thread is gone.
+    pub fn send(&self, command: MatcherCommand) -> bool {
+        self.counters.command_queued();
+        if self.commands.send(command).is_err() {
+            self.counters.command_taken();
+            return false;
+        }
+        true
+    }
+
+    /// Depth of `product_id` once every command sent before has been applied. `None`
+    /// when the product has no book or the matcher thread is gone.
+    #[cfg(test)]
+    pub fn depth(&self, product_id: u16, levels: usize) -> Option<Depth> {
+        let (reply, answer) = mpsc::channel();
+        if !self.send(MatcherCommand::Depth { product_id, levels, reply }) {
+            return None;
+        }
+        answer.recv().ok().flatten()
+    }
+
+    /// Stops taking commands and waits up to `timeout` for the matcher thread to apply
+    /// those already queued, and so send their results, then hands the matcher back.
+    /// `None` when the drain took longer or the matcher thread panicked.
+    pub fn shutdown(self, timeout: Duration) -> Option<OrderMatcher> {
+        drop(self.commands);
+        match self.drained.recv_timeout(timeout) {
+            Ok(()) => self.thread.join().ok(),
+            Err(RecvTimeoutError::Timeout) => {
+                log_error!("The matcher thread did not drain its queue within {:?}", timeout);
+                None
+            }
+            Err(RecvTimeoutError::Disconnected) => {
+                log_error!("The matcher thread panicked");
+                None
+            }
+        }
+    }
+}
+
+/// Applies commands until every sender is gone, ticking the housekeeping in between.
+fn run(matcher: &mut OrderMatcher, housekeeping: &mut impl Housekeeping, inbox: &Receiver<MatcherCommand>) {
+    loop {
+        match inbox.recv_timeout(TICK_INTERVAL) {
+            Ok(command) => {
+                matcher.state.counters.command_taken();
+                apply(matcher, housekeeping, command);
+            }
+            Err(RecvTimeoutError::Timeout) => {}
+            // queued commands are all received before the disconnect is seen, and
+            // the last tick already followed the last of them
+            Err(RecvTimeoutError::Disconnected) => return,
+        }
+        housekeeping.tick(matcher);
+    }
+}
+
+fn apply(matcher: &mut OrderMatcher, housekeeping: &mut impl Housekeeping, command: MatcherCommand) {
+    match command {
+        MatcherCommand::Frames { frames, received_time } => {
+            for (frame, _) in &frames {
+                matcher.journal(frame, received_time);
+            }
+            // journaled as a whole before anything is applied or answered
+            matcher.flush_journal();
+            for (_, msg) in frames {
+                matcher.handle_message(msg, received_time);
+            }
+        }
+        #[cfg(test)]
+        MatcherCommand::Depth { product_id, levels, reply } => {
+            // an asker that gave up waiting is no concern of the matcher
+            let _ = reply.send(matcher.state.book(product_id).map(|book| book.depth(levels, false)));
+        }
+        MatcherCommand::Dropped(dropped) => housekeeping.frame_dropped(matcher, dropped),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::parse_config;
+    use crate::data_types::{
+        CancelOrder, EngineState, MatchResult, ORDER_PRICE_TYPE_LIMIT, ORDER_TYPE_BUY, ORDER_TYPE_SELL, Order, OrderAck,
+        ResultSender, TIF_GTC,
+    };
+    use crate::message_codec;
+    use std::sync::{Arc, Mutex};
+
+    const PRODUCT: u16 = 7;
+
+    /// Ids of the orders acked so far, in ack order.
+    #[derive(Clone, Default)]
+    struct Acks(Arc<Mutex<Vec<u64>>>);
+
+    impl ResultSender for Acks {
+        fn send_result(&self, _result: &MatchResult) {}
+
+        fn send_ack(&self, ack: &OrderAck) {
+            self.0.lock().unwrap().push(ack.order_id);
+        }
+    }
+
+    /// Housekeeping that takes its time, so commands queue up behind it.
+    struct Slow(Duration);
+
+    impl Housekeeping for Slow {
+        fn tick(&mut self, _matcher: &mut OrderMatcher) {
+            thread::sleep(self.0);
+        }
+
+        fn frame_dropped(&mut self, _matcher: &mut OrderMatcher, _dropped: DroppedFrame) {}
+    }
+
+    fn actor_with(housekeeping: impl Housekeeping) -> (MatcherActor, Acks) {
+        let args: Vec<String> = ["engine", "--prodid", "7"].iter().map(|arg| arg.to_string()).collect();
+        let acks = Acks::default();
+        let matcher = OrderMatcher::new(EngineState::new(&parse_config(&args).unwrap()), Box::new(acks.clone()));
+        (MatcherActor::spawn(matcher, housekeeping, None).unwrap(), acks)
+    }
+
+    fn actor() -> MatcherActor {
+        actor_with(()).0
+    }
+
+    fn limit(order_id: u64, order_type: u8, price: i64, quantity: u32) -> Order {
+        Order {
+            product_id: PRODUCT,
+            order_type,
+            price_type: ORDER_PRICE_TYPE_LIMIT,
+            quantity,
+            order_id,
+            price,
+            submit_time: 0,
+            expire_time: 0,
+            time_in_force: TIF_GTC,
+            min_qty: 0,
+            aon: false,
+            trader_id: 0,
+            client_ref: [0; 8],
+            display_quantity: 0,
+        }
+    }
+
+    /// One datagram of `frames`, decoded as the receiving thread would.
+    fn datagram(frames: &[[u8; MESSAGE_TOTAL_SIZE]]) -> MatcherCommand {
+        let frames = frames
+            .iter()
+            .map(|frame| {
+                let (message_type, version, payload) = message_codec::unpack_message_payload(frame).unwrap();
+                (*frame, message_codec::deserialize_message(message_type, version, payload).unwrap())
+            })
+            .collect();
+        MatcherCommand::Frames { frames, received_time: 1 }
+    }
+
+    fn level(price: i64, quantity: u32) -> DepthLevel {
+        DepthLevel { price, quantity }
+    }
+
+    #[test]
+    fn commands_apply_in_the_order_they_were_sent() {
+        let actor = actor();
+        let mut answers = Vec::new();
+        let mut ask_depth = || {
+            let (reply, answer) = mpsc::channel();
+            answers.push(answer);
+            MatcherCommand::Depth { product_id: PRODUCT, levels: 5, reply }
+        };
+        // nothing waits for an answer until every command is queued
+        let commands = [
+            datagram(&[message_codec::serialize_order(&limit(1, ORDER_TYPE_SELL, 300_001, 10))]),
+            ask_depth(),
+            datagram(&[message_codec::serialize_order(&limit(2, ORDER_TYPE_BUY, 300_001, 4))]),
+            ask_depth(),
+            datagram(&[
+                message_codec::serialize_order(&limit(3, ORDER_TYPE_SELL, 200_001, 5)),
+                message_codec::serialize_cancel_order(&CancelOrder { product_id: PRODUCT, order_id: 1 }),
+            ]),
+            ask_depth(),
+        ];
+        for command in commands {
+            assert!(actor.send(command));
+        }
+
+        let asks: Vec<Vec<DepthLevel>> = answers.iter().map(|answer| answer.recv().unwrap().unwrap().1).collect();
+        assert_eq!(asks, [vec![level(300_001, 10)], vec![level(300_001, 6)], vec![level(200_001, 5)]]);
+        assert_eq!(actor.depth(PRODUCT + 1, 5), None);
+
+        let matcher = actor.shutdown(Duration::from_secs(5)).unwrap();
+        assert_eq!(matcher.state.counters.received(), 3);
+    }
+
+    #[test]
+    fn shutdown_applies_every_queued_command_first() {
+        let (actor, acks) = actor_with(Slow(Duration::from_millis(20)));
+        for order_id in 1..=10 {
+            let order = limit(order_id, ORDER_TYPE_BUY, 100_001 * order_id as i64, 1);
+            assert!(actor.send(datagram(&[message_codec::serialize_order(&order)])));
+        }
+
+        let matcher = actor.shutdown(Duration::from_secs(5)).unwrap();
+        assert_eq!(*acks.0.lock().unwrap(), (1..=10).collect::<Vec<u64>>());
+        assert_eq!(matcher.state.book(PRODUCT).unwrap().best_prices().0, Some(1_000_010));
+    }
+
+    #[test]
+    fn queue_depth_rises_with_a_backlog() {
+        let (actor, _) = actor_with(Slow(Duration::from_millis(20)));
+        for order_id in 1..=10 {
+            assert!(actor.send(datagram(&[message_codec::serialize_order(&limit(order_id, ORDER_TYPE_BUY, 100_001, 1))])));
+        }
+        // the first command holds the matcher in its tick while the other nine wait
+        let counters = Arc::clone(&actor.counters);
+        assert!(counters.queued() >= 8);
+
+        let matcher = actor.shutdown(Duration::from_secs(5)).unwrap();
+        let extension = matcher.state.build_status_extension();
+        assert_eq!(extension.queue_depth, 0);
+        assert!(extension.max_queue_depth >= 9, "max depth {}", extension.max_queue_depth);
+        // the high-water mark restarts with each status frame
+        assert_eq!(matcher.state.build_status_extension().max_queue_depth, 0);
+    }
+
+    /// Where the housekeeping of the matcher thread ran: thread name and CPU.
+    #[cfg(target_os = "linux")]
+    struct WhereAmI(Sender<(Option<String>, i32)>);
+
+    #[cfg(target_os = "linux")]
+    impl Housekeeping for WhereAmI {
+        fn tick(&mut self, _matcher: &mut OrderMatcher) {
+            let name = thread::current().name().map(str::to_string);
+            let _ = self.0.send((name, unsafe { libc::sched_getcpu() }));
+        }
+
+        fn frame_dropped(&mut self, _matcher: &mut OrderMatcher, _dropped: DroppedFrame) {}
+    }
+
+    #[test]
+    #[cfg(target_os = "linux")]
+    fn matcher_thread_runs_named_on_its_core() {
+        let args: Vec<String> = ["engine", "--prodid", "7"].iter().map(|arg| arg.to_string()).collect();
+        let matcher = OrderMatcher::new(EngineState::new(&parse_config(&args).unwrap()), Box::new(Acks::default()));
+        let (sender, ticks) = mpsc::channel();
+        let actor = MatcherActor::spawn(matcher, WhereAmI(sender), Some(0)).unwrap();
+
+        for _ in 0..3 {
+            assert_eq!(ticks.recv().unwrap(), (Some("matcher".to_string()), 0));
+        }
+        assert!(actor.shutdown(Duration::from_secs(5)).is_some());
+    }
+
+    #[test]
+    fn shutdown_gives_up_on_a_drain_past_the_timeout() {
+        let (actor, _) = actor_with(Slow(Duration::from_millis(300)));
+        for order_id in 1..=3 {
+            assert!(actor.send(datagram(&[message_codec::serialize_order(&limit(order_id, ORDER_TYPE_BUY, 100_001, 1))])));
+        }
+        assert!(actor.shutdown(Duration::from_millis(10)).is_none());
+    }
+}
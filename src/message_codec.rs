@@ -1,8 +1,102 @@
+use std::fmt;
+
 use crate::data_types::{
-    BroadcastStats, CancelOrder, MESSAGE_TOTAL_SIZE, MSG_ORDER_CANCEL, MSG_ORDER_SUBMIT,
-    MSG_STATUS_BROADCAST, MSG_TRADE_BROADCAST, MatchResult, Order, OrderExecution,
+    BroadcastStats, CancelAck, CancelAtPrice, CancelOrder, CancelOrderBatch, CancelReject, MAX_IDS_PER_CHUNK, MSG_ORDER_CANCEL_BATCH, DepthLevel, IncomingMessage, MARKET_DATA_DEPTH,
+    MESSAGE_TOTAL_SIZE, MSG_CANCEL_ACK, MSG_CANCEL_AT_PRICE, MSG_CANCEL_REJECT, MSG_MARKETDATA, MSG_ORDER_ACK, MSG_ORDER_CANCEL, MSG_ORDER_FILLED, MSG_ORDER_QUERY,
+    MSG_ORDER_QUERY_RESPONSE, MSG_ORDER_REPLACE, MSG_ORDER_SUBMIT, MSG_SESSION_SUMMARY, MSG_STATUS_BROADCAST,
+    MSG_TRADE_BROADCAST, MSG_TYPE_MASK, MSG_UNFILLED_MARKET, MSG_VERSION_SHIFT, MarketDataSnapshot, MatchResult,
+    ORDER_LAYOUT_CURRENT, ORDER_LAYOUT_EXTENDED, ORDER_LAYOUT_LEGACY, ORDER_LAYOUT_UNVERSIONED, ORDER_TYPE_BUY,
+    ORDER_TYPE_MOCK_SELL, ORDER_PRICE_TYPE_LIMIT, ORDER_PRICE_TYPE_MARKET, ORDER_TYPE_SELL, Order, OrderAck, OrderExecution, OrderQuery, OrderQueryResponse,
+    Ohlc, OrderSummary, ReplaceOrder, TIF_GTC, TIF_GTD, TapeAggregation, UnfilledMarket,
+    BOOK_ADMIN_REGISTER, BOOK_ADMIN_REPORT_MEMORY, BOOK_ADMIN_SHRINK, BookAdmin, BookMemoryReport, MSG_BOOK_ADMIN, MSG_BOOK_MEMORY,
+    MSG_SET_TRADING_STATE, MSG_TRADING_STATE, TradingState, TradingStateChange, BookDigest, MSG_BOOK_DIGEST,
+    MSG_STATUS_EXTENSION, StatusExtension, MSG_SEQUENCE, SequenceHeader, MSG_WHATIF, MSG_WHATIF_RESPONSE, TRADE_TYPE_MOCK, TRADE_TYPE_REAL, WhatIfResponse,
+    MSG_SNAPSHOT_HEADER, MSG_SNAPSHOT_PRODUCT, SessionStats, SnapshotHeader, SnapshotProduct,
 };
 
+/// Why a frame or payload could not be decoded.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CodecError {
+    /// The payload ends before the field being read; `need` bytes were required.
+    BufferTooShort { need: usize, got: usize },
+    /// The checksum byte does not match the frame; `expected` is what the frame sums to.
+    ChecksumMismatch { expected: u8, got: u8 },
+    UnknownMessageType(u8),
+    UnknownTimeInForce(u8),
+    /// An order side other than buy, sell and their mock variants.
+    UnknownOrderType(u8),
+    UnknownPriceType(u8),
+    /// An order layout version this engine does not know.
+    UnsupportedVersion(u8),
+    /// Order ID 0 is reserved and never names an order.
+    ReservedOrderIdZero,
+    /// A book admin command this engine does not know.
+    UnknownAdminCommand(u8),
+    UnknownTradingState(u8),
+    /// A cancel batch of more order IDs than 255 chunks carry, or a chunk of more than
+    /// `MAX_IDS_PER_CHUNK`.
+    TooManyIds(usize),
+    /// A cancel batch chunk whose index is not below its count, or that is not at its
+    /// place among the chunks of one batch.
+    ChunkOutOfSequence { index: u8, count: u8 },
+}
+
+impl fmt::Display for CodecError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CodecError::BufferTooShort { need, got } => {
+                write!(f, "buffer too short: need {} bytes, got {}", need, got)
+            }
+            CodecError::ChecksumMismatch { expected, got } => {
+                write!(f, "checksum mismatch: expected {:#04x}, got {:#04x}", expected, got)
+            }
+            CodecError::UnknownMessageType(message_type) => write!(f, "unknown message type {}", message_type),
+            CodecError::UnknownTimeInForce(tif) => write!(f, "unknown time in force {}", tif),
+            CodecError::UnknownOrderType(order_type) => write!(f, "unknown order type {}", order_type),
+            CodecError::UnknownPriceType(price_type) => write!(f, "unknown price type {}", price_type),
+            CodecError::UnsupportedVersion(version) => write!(f, "unsupported order layout version {}", version),
+            CodecError::ReservedOrderIdZero => write!(f, "order id 0 is reserved"),
+            CodecError::UnknownAdminCommand(command) => write!(f, "unknown book admin command {}", command),
+            CodecError::UnknownTradingState(state) => write!(f, "unknown trading state {}", state),
+            CodecError::TooManyIds(count) => write!(f, "too many order ids in one cancel: {}", count),
+            CodecError::ChunkOutOfSequence { index, count } => {
+                write!(f, "cancel chunk {} of {} out of sequence", index, count)
+            }
+        }
+    }
+}
+
+impl std::error::Error for CodecError {}
+
+/// Fails with `BufferTooShort` unless `payload` holds at least `need` bytes.
+#[inline(always)]
+fn require_len(payload: &[u8], need: usize) -> Result<(), CodecError> {
+    if payload.len() < need {
+        return Err(CodecError::BufferTooShort { need, got: payload.len() });
+    }
+    Ok(())
+}
+
+/// The `N` bytes at `at`, or `BufferTooShort` if the payload ends first. Every field
+/// is read through here, so a truncated payload is an error rather than a panic.
+#[inline(always)]
+fn field<const N: usize>(payload: &[u8], at: usize) -> Result<[u8; N], CodecError> {
+    payload
+        .get(at..)
+        .and_then(|rest| rest.first_chunk::<N>())
+        .copied()
+        .ok_or(CodecError::BufferTooShort { need: at + N, got: payload.len() })
+}
+
+/// Fails with `ReservedOrderIdZero` for order ID 0.
+#[inline(always)]
+fn require_order_id(order_id: u64) -> Result<u64, CodecError> {
+    if order_id == 0 {
+        return Err(CodecError::ReservedOrderIdZero);
+    }
+    Ok(order_id)
+}
+
 /// Calculates a simple XOR checksum for the payload starting after the type byte (index 2).
 /// The buffer must be at least 2 bytes long.
 fn calculate_checksum(buf: &[u8]) -> u8 {
@@ -10,12 +104,18 @@ fn calculate_checksum(buf: &[u8]) -> u8 {
     buf[1..].iter().fold(0, |acc, &x| acc ^ x)
 }
 
-/// Serializes an Order struct into a 50-byte network buffer.
+/// Type byte carrying `message_type` and an order layout `version`.
+#[inline(always)]
+fn versioned_type(message_type: u8, version: u8) -> u8 {
+    message_type | (version << MSG_VERSION_SHIFT)
+}
+
+/// Serializes an Order struct into a 50-byte network buffer, in the current layout.
 pub fn serialize_order(order: &Order) -> [u8; MESSAGE_TOTAL_SIZE] {
     let mut buf = [0u8; MESSAGE_TOTAL_SIZE];
     let payload_start = 2; // Checksum (0) + Type (1) = Start at index 2
 
-    buf[1] = MSG_ORDER_SUBMIT;
+    buf[1] = versioned_type(MSG_ORDER_SUBMIT, ORDER_LAYOUT_CURRENT);
 
     // Product ID (u16)
     buf[payload_start..payload_start + 2].copy_from_slice(&order.product_id.to_be_bytes());
@@ -33,6 +133,18 @@ pub fn serialize_order(order: &Order) -> [u8; MESSAGE_TOTAL_SIZE] {
     buf[payload_start + 24..payload_start + 32].copy_from_slice(&order.submit_time.to_be_bytes());
     // Expire Time (u64)
     buf[payload_start + 32..payload_start + 40].copy_from_slice(&order.expire_time.to_be_bytes());
+    // Time In Force (u8)
+    buf[payload_start + 40] = order.time_in_force;
+    // Client Reference ([u8; 8]), overwritten by the old order ID in a replace
+    buf[payload_start + 41..payload_start + 49].copy_from_slice(&order.client_ref);
+    // Minimum Quantity (u32), after the bytes a replace uses for the old order ID
+    buf[payload_start + 49..payload_start + 53].copy_from_slice(&order.min_qty.to_be_bytes());
+    // All Or None (u8)
+    buf[payload_start + 53] = order.aon as u8;
+    // Trader ID (u32)
+    buf[payload_start + 54..payload_start + 58].copy_from_slice(&order.trader_id.to_be_bytes());
+    // Display Quantity (u32)
+    buf[payload_start + 58..payload_start + 62].copy_from_slice(&order.display_quantity.to_be_bytes());
 
     // Checksum calculation and placement
     buf[0] = calculate_checksum(&buf);
@@ -40,7 +152,24 @@ pub fn serialize_order(order: &Order) -> [u8; MESSAGE_TOTAL_SIZE] {
     buf
 }
 
+/// Serializes a ReplaceOrder struct: the order frame layout followed by the old order ID.
+/// Clients encode their own frames; the engine only needs this in the codec tests.
+#[cfg(test)]
+pub fn serialize_replace_order(replace: &ReplaceOrder) -> [u8; MESSAGE_TOTAL_SIZE] {
+    let mut buf = serialize_order(&replace.order);
+    let payload_start = 2;
+
+    buf[1] = versioned_type(MSG_ORDER_REPLACE, ORDER_LAYOUT_CURRENT);
+    // Old Order ID (u64)
+    buf[payload_start + 41..payload_start + 49].copy_from_slice(&replace.old_order_id.to_be_bytes());
+
+    buf[0] = calculate_checksum(&buf);
+
+    buf
+}
+
 /// Serializes a CancelOrder struct into a 50-byte network buffer.
+#[cfg(test)]
 pub fn serialize_cancel_order(cancel: &CancelOrder) -> [u8; MESSAGE_TOTAL_SIZE] {
     let mut buf = [0u8; MESSAGE_TOTAL_SIZE];
     let payload_start = 2;
@@ -58,6 +187,50 @@ pub fn serialize_cancel_order(cancel: &CancelOrder) -> [u8; MESSAGE_TOTAL_SIZE]
     buf
 }
 
+/// Splits a CancelOrderBatch into one frame per `MAX_IDS_PER_CHUNK` order IDs, in
+/// order. Fails on a zero order ID or more IDs than 255 chunks carry; an empty batch
+/// has no frames. Clients encode their own frames; the engine only needs this in the
+/// codec tests.
+#[cfg(test)]
+pub fn serialize_cancel_order_batch(batch: &CancelOrderBatch) -> Result<Vec<[u8; MESSAGE_TOTAL_SIZE]>, CodecError> {
+    for &order_id in &batch.order_ids {
+        require_order_id(order_id)?;
+    }
+    let chunk_count = u8::try_from(batch.order_ids.len().div_ceil(MAX_IDS_PER_CHUNK))
+        .map_err(|_| CodecError::TooManyIds(batch.order_ids.len()))?;
+    let payload_start = 2;
+
+    let frames = batch
+        .order_ids
+        .chunks(MAX_IDS_PER_CHUNK)
+        .enumerate()
+        .map(|(chunk_index, order_ids)| {
+            let mut buf = [0u8; MESSAGE_TOTAL_SIZE];
+            buf[1] = MSG_ORDER_CANCEL_BATCH;
+            // Product ID (u16)
+            buf[payload_start..payload_start + 2].copy_from_slice(&batch.product_id.to_be_bytes());
+            // Chunk Index, Chunk Count and ID Count (u8 each)
+            buf[payload_start + 2] = chunk_index as u8;
+            buf[payload_start + 3] = chunk_count;
+            buf[payload_start + 4] = order_ids.len() as u8;
+            // Order IDs (u64 each)
+            for (i, order_id) in order_ids.iter().enumerate() {
+                let at = payload_start + 5 + i * 8;
+                buf[at..at + 8].copy_from_slice(&order_id.to_be_bytes());
+            }
+            buf[0] = calculate_checksum(&buf);
+            buf
+        })
+        .collect();
+    Ok(frames)
+}
+
+/// TRADE_TYPE_MOCK for a fill of a mock order, which never touched the book.
+#[inline(always)]
+fn trade_type(execution: &OrderExecution) -> u8 {
+    if execution.is_mocked_result { TRADE_TYPE_MOCK } else { TRADE_TYPE_REAL }
+}
+
 /// Serializes a OrderExecution struct into a 50-byte network buffer.
 pub fn serialize_order_execution(result: &OrderExecution) -> [u8; MESSAGE_TOTAL_SIZE] {
     let mut buf = [0u8; MESSAGE_TOTAL_SIZE];
@@ -83,6 +256,10 @@ pub fn serialize_order_execution(result: &OrderExecution) -> [u8; MESSAGE_TOTAL_
         .copy_from_slice(&result.trade_time_network.to_be_bytes());
     buf[payload_start + 42..payload_start + 46]
         .copy_from_slice(&result.internal_match_time.to_be_bytes());
+    // Aggressor Side (u8)
+    buf[payload_start + 46] = result.aggressor_side;
+    // Trade Type (u8)
+    buf[payload_start + 47] = trade_type(result);
     // Padding to 50 bytes is implicit by the array size (index 48 is the last element used)
 
     // Checksum calculation and placement
@@ -94,6 +271,7 @@ pub fn serialize_order_execution(result: &OrderExecution) -> [u8; MESSAGE_TOTAL_
 pub fn serialize_order_execution_share_time(
     result: &OrderExecution,
     time_per_trade: u32,
+    receive_to_match_time: u32,
 ) -> [u8; MESSAGE_TOTAL_SIZE] {
     let mut buf = [0u8; MESSAGE_TOTAL_SIZE];
     let payload_start = 2;
@@ -118,23 +296,67 @@ pub fn serialize_order_execution_share_time(
         .copy_from_slice(&result.trade_time_network.to_be_bytes());
     buf[payload_start + 50..payload_start + 54]
         .copy_from_slice(&time_per_trade.to_be_bytes());
+    // Aggressor Side (u8)
+    buf[payload_start + 54] = result.aggressor_side;
+    // Clock Skew Flag (u8)
+    buf[payload_start + 55] = result.clock_skew_detected as u8;
+    // Engine Receive To Match Time (u32)
+    buf[payload_start + 56..payload_start + 60]
+        .copy_from_slice(&receive_to_match_time.to_be_bytes());
+    // Trade Type (u8)
+    buf[payload_start + 60] = trade_type(result);
 
     buf[0] = calculate_checksum(&buf);
 
     buf
 }
 
-pub fn serialize_match_result(result: &MatchResult) -> Vec<Vec<u8>> {
+/// Coalesces consecutive executions at the same price into one print carrying their
+/// summed quantity. A print covering several resting orders has a resting-side order
+/// ID of 0; the aggressor's ID is kept.
+pub fn aggregate_by_price(executions: &[OrderExecution]) -> Vec<OrderExecution> {
+    let mut prints: Vec<OrderExecution> = Vec::with_capacity(executions.len());
+    for execution in executions {
+        match prints.last_mut() {
+            Some(print) if print.price == execution.price && print.product_id == execution.product_id => {
+                print.quantity = print.quantity.saturating_add(execution.quantity);
+                if print.aggressor_side == ORDER_TYPE_BUY {
+                    print.sell_order_id = 0;
+                } else {
+                    print.buy_order_id = 0;
+                }
+            }
+            _ => prints.push(execution.clone()),
+        }
+    }
+    prints
+}
+
+pub fn serialize_match_result(result: &MatchResult, aggregation: TapeAggregation) -> Vec<Vec<u8>> {
     const BATCH_SIZE: usize = 20;
 
     let mut batches = Vec::new();
 
-    let time_per_trade = result.time_per_trade();
-    for chunk in result.order_execution_list.chunks(BATCH_SIZE) {
+    let aggregated;
+    let prints = match aggregation {
+        TapeAggregation::Off => &result.order_execution_list,
+        TapeAggregation::ByPrice => {
+            aggregated = aggregate_by_price(&result.order_execution_list);
+            &aggregated
+        }
+    };
+
+    // The wire field is whole nanoseconds
+    let time_per_trade = result.time_per_trade().round() as u32;
+    for chunk in prints.chunks(BATCH_SIZE) {
         let mut buf = Vec::with_capacity(MESSAGE_TOTAL_SIZE * chunk.len());
 
         for trade in chunk {
-            let single = serialize_order_execution_share_time(trade, time_per_trade);
+            let single = serialize_order_execution_share_time(
+                trade,
+                time_per_trade,
+                result.receive_to_match_time,
+            );
             buf.extend_from_slice(&single);
         }
 
@@ -144,6 +366,318 @@ pub fn serialize_match_result(result: &MatchResult) -> Vec<Vec<u8>> {
     batches
 }
 
+/// Serializes an OrderAck struct into a network buffer.
+pub fn serialize_order_ack(ack: &OrderAck) -> [u8; MESSAGE_TOTAL_SIZE] {
+    let mut buf = [0u8; MESSAGE_TOTAL_SIZE];
+    let payload_start = 2;
+
+    buf[1] = MSG_ORDER_ACK;
+
+    // Product ID (u16)
+    buf[payload_start..payload_start + 2].copy_from_slice(&ack.product_id.to_be_bytes());
+    // Order ID (u64)
+    buf[payload_start + 2..payload_start + 10].copy_from_slice(&ack.order_id.to_be_bytes());
+    // Filled Quantity (u32)
+    buf[payload_start + 10..payload_start + 14].copy_from_slice(&ack.filled_quantity.to_be_bytes());
+    // Resting Quantity (u32)
+    buf[payload_start + 14..payload_start + 18].copy_from_slice(&ack.resting_quantity.to_be_bytes());
+    // Status (u8)
+    buf[payload_start + 18] = ack.status;
+    // Reject Reason (u8)
+    buf[payload_start + 19] = ack.reject_reason;
+    // Client Reference ([u8; 8])
+    buf[payload_start + 20..payload_start + 28].copy_from_slice(&ack.client_ref);
+
+    buf[0] = calculate_checksum(&buf);
+
+    buf
+}
+
+/// Serializes an OrderSummary struct into a network buffer.
+pub fn serialize_order_summary(summary: &OrderSummary) -> [u8; MESSAGE_TOTAL_SIZE] {
+    let mut buf = [0u8; MESSAGE_TOTAL_SIZE];
+    let payload_start = 2;
+
+    buf[1] = MSG_ORDER_FILLED;
+
+    // Product ID (u16)
+    buf[payload_start..payload_start + 2].copy_from_slice(&summary.product_id.to_be_bytes());
+    // Order ID (u64)
+    buf[payload_start + 2..payload_start + 10].copy_from_slice(&summary.order_id.to_be_bytes());
+    // Filled Quantity (u64)
+    buf[payload_start + 10..payload_start + 18]
+        .copy_from_slice(&summary.filled_quantity.to_be_bytes());
+    // Average Price (i64)
+    buf[payload_start + 18..payload_start + 26].copy_from_slice(&summary.average_price.to_be_bytes());
+    // Counterparties (u32)
+    buf[payload_start + 26..payload_start + 30].copy_from_slice(&summary.counterparties.to_be_bytes());
+    // Client Reference ([u8; 8])
+    buf[payload_start + 30..payload_start + 38].copy_from_slice(&summary.client_ref);
+
+    buf[0] = calculate_checksum(&buf);
+
+    buf
+}
+
+/// Serializes an UnfilledMarket struct into a network buffer.
+pub fn serialize_unfilled_market(unfilled: &UnfilledMarket) -> [u8; MESSAGE_TOTAL_SIZE] {
+    let mut buf = [0u8; MESSAGE_TOTAL_SIZE];
+    let payload_start = 2;
+
+    buf[1] = MSG_UNFILLED_MARKET;
+
+    // Product ID (u16)
+    buf[payload_start..payload_start + 2].copy_from_slice(&unfilled.product_id.to_be_bytes());
+    // Order ID (u64)
+    buf[payload_start + 2..payload_start + 10].copy_from_slice(&unfilled.order_id.to_be_bytes());
+    // Unfilled Quantity (u32)
+    buf[payload_start + 10..payload_start + 14]
+        .copy_from_slice(&unfilled.unfilled_quantity.to_be_bytes());
+
+    buf[0] = calculate_checksum(&buf);
+
+    buf
+}
+
+/// Serializes a CancelAck struct into a network buffer.
+pub fn serialize_cancel_ack(ack: &CancelAck) -> [u8; MESSAGE_TOTAL_SIZE] {
+    let mut buf = [0u8; MESSAGE_TOTAL_SIZE];
+    let payload_start = 2;
+
+    buf[1] = MSG_CANCEL_ACK;
+
+    // Product ID (u16)
+    buf[payload_start..payload_start + 2].copy_from_slice(&ack.product_id.to_be_bytes());
+    // Order ID (u64)
+    buf[payload_start + 2..payload_start + 10].copy_from_slice(&ack.order_id.to_be_bytes());
+    // Cancelled Quantity (u32)
+    buf[payload_start + 10..payload_start + 14].copy_from_slice(&ack.cancelled_quantity.to_be_bytes());
+
+    buf[0] = calculate_checksum(&buf);
+
+    buf
+}
+
+/// Serializes a CancelReject struct into a network buffer.
+pub fn serialize_cancel_reject(reject: &CancelReject) -> [u8; MESSAGE_TOTAL_SIZE] {
+    let mut buf = [0u8; MESSAGE_TOTAL_SIZE];
+    let payload_start = 2;
+
+    buf[1] = MSG_CANCEL_REJECT;
+
+    // Product ID (u16)
+    buf[payload_start..payload_start + 2].copy_from_slice(&reject.product_id.to_be_bytes());
+    // Order ID (u64)
+    buf[payload_start + 2..payload_start + 10].copy_from_slice(&reject.order_id.to_be_bytes());
+    // Reason (u8)
+    buf[payload_start + 10] = reject.reason as u8;
+
+    buf[0] = calculate_checksum(&buf);
+
+    buf
+}
+
+/// Serializes a WhatIfResponse struct into a network buffer.
+pub fn serialize_whatif_response(response: &WhatIfResponse) -> [u8; MESSAGE_TOTAL_SIZE] {
+    let mut buf = [0u8; MESSAGE_TOTAL_SIZE];
+    let payload_start = 2;
+
+    buf[1] = MSG_WHATIF_RESPONSE;
+
+    // Product ID (u16)
+    buf[payload_start..payload_start + 2].copy_from_slice(&response.product_id.to_be_bytes());
+    // Order ID (u64)
+    buf[payload_start + 2..payload_start + 10].copy_from_slice(&response.order_id.to_be_bytes());
+    // Filled Quantity (u32)
+    buf[payload_start + 10..payload_start + 14].copy_from_slice(&response.filled_quantity.to_be_bytes());
+    // Residual Quantity (u32)
+    buf[payload_start + 14..payload_start + 18].copy_from_slice(&response.residual_quantity.to_be_bytes());
+    // Average Price (i64)
+    buf[payload_start + 18..payload_start + 26].copy_from_slice(&response.average_price.to_be_bytes());
+    // Worst Price (i64)
+    buf[payload_start + 26..payload_start + 34].copy_from_slice(&response.worst_price.to_be_bytes());
+    // Fills (u32)
+    buf[payload_start + 34..payload_start + 38].copy_from_slice(&response.fills.to_be_bytes());
+
+    buf[0] = calculate_checksum(&buf);
+
+    buf
+}
+
+/// Serializes a BookDigest struct into a network buffer.
+pub fn serialize_book_digest(digest: &BookDigest) -> [u8; MESSAGE_TOTAL_SIZE] {
+    let mut buf = [0u8; MESSAGE_TOTAL_SIZE];
+    let payload_start = 2;
+
+    buf[1] = MSG_BOOK_DIGEST;
+
+    // Instance Tag ([u8; 16])
+    buf[payload_start..payload_start + 16].copy_from_slice(&digest.instance_tag);
+    // Product ID (u16)
+    buf[payload_start + 16..payload_start + 18].copy_from_slice(&digest.product_id.to_be_bytes());
+    // Depth (u8)
+    buf[payload_start + 18] = digest.depth;
+    // Digest (u64)
+    buf[payload_start + 19..payload_start + 27].copy_from_slice(&digest.digest.to_be_bytes());
+
+    buf[0] = calculate_checksum(&buf);
+
+    buf
+}
+
+/// Serializes a BookMemoryReport struct into a network buffer.
+pub fn serialize_book_memory(report: &BookMemoryReport) -> [u8; MESSAGE_TOTAL_SIZE] {
+    let mut buf = [0u8; MESSAGE_TOTAL_SIZE];
+    let payload_start = 2;
+
+    buf[1] = MSG_BOOK_MEMORY;
+
+    // Instance Tag ([u8; 16])
+    buf[payload_start..payload_start + 16].copy_from_slice(&report.instance_tag);
+    // Product ID (u16)
+    buf[payload_start + 16..payload_start + 18].copy_from_slice(&report.product_id.to_be_bytes());
+    // Footprint Bytes (u64)
+    buf[payload_start + 18..payload_start + 26].copy_from_slice(&report.footprint_bytes.to_be_bytes());
+    // Resting Orders (u32)
+    buf[payload_start + 26..payload_start + 30].copy_from_slice(&report.resting_orders.to_be_bytes());
+
+    buf[0] = calculate_checksum(&buf);
+
+    buf
+}
+
+/// Serializes a TradingStateChange struct into a MSG_TRADING_STATE network buffer.
+pub fn serialize_trading_state(change: &TradingStateChange) -> [u8; MESSAGE_TOTAL_SIZE] {
+    let mut buf = [0u8; MESSAGE_TOTAL_SIZE];
+    let payload_start = 2;
+
+    buf[1] = MSG_TRADING_STATE;
+
+    // Product ID (u16)
+    buf[payload_start..payload_start + 2].copy_from_slice(&change.product_id.to_be_bytes());
+    // State (u8)
+    buf[payload_start + 2] = change.state as u8;
+
+    buf[0] = calculate_checksum(&buf);
+
+    buf
+}
+
+/// Serializes an OrderQueryResponse struct into a network buffer. The view fields are
+/// zero when the found flag is 0.
+pub fn serialize_order_query_response(response: &OrderQueryResponse) -> [u8; MESSAGE_TOTAL_SIZE] {
+    let mut buf = [0u8; MESSAGE_TOTAL_SIZE];
+    let payload_start = 2;
+
+    buf[1] = MSG_ORDER_QUERY_RESPONSE;
+
+    // Product ID (u16)
+    buf[payload_start..payload_start + 2].copy_from_slice(&response.product_id.to_be_bytes());
+    // Order ID (u64)
+    buf[payload_start + 2..payload_start + 10].copy_from_slice(&response.order_id.to_be_bytes());
+
+    if let Some(view) = &response.view {
+        // Found (u8)
+        buf[payload_start + 10] = 1;
+        // Order Type (u8)
+        buf[payload_start + 11] = view.order_type;
+        // Price (i64)
+        buf[payload_start + 12..payload_start + 20].copy_from_slice(&view.price.to_be_bytes());
+        // Remaining Quantity (u32)
+        buf[payload_start + 20..payload_start + 24]
+            .copy_from_slice(&view.remaining_quantity.to_be_bytes());
+        // Submit Time (u64)
+        buf[payload_start + 24..payload_start + 32].copy_from_slice(&view.submit_time.to_be_bytes());
+        // Client Reference ([u8; 8])
+        buf[payload_start + 32..payload_start + 40].copy_from_slice(&view.client_ref);
+    }
+
+    buf[0] = calculate_checksum(&buf);
+
+    buf
+}
+
+/// Serializes the session totals of one product into a network buffer. The OHLC
+/// prices are zero when the flag is 0.
+pub fn serialize_session_summary(
+    product_id: u16,
+    volume: u64,
+    turnover: i128,
+    ohlc: Option<Ohlc>,
+) -> [u8; MESSAGE_TOTAL_SIZE] {
+    let mut buf = [0u8; MESSAGE_TOTAL_SIZE];
+    let payload_start = 2;
+
+    buf[1] = MSG_SESSION_SUMMARY;
+
+    // Product ID (u16)
+    buf[payload_start..payload_start + 2].copy_from_slice(&product_id.to_be_bytes());
+    // Volume (u64)
+    buf[payload_start + 2..payload_start + 10].copy_from_slice(&volume.to_be_bytes());
+    // Turnover (i128)
+    buf[payload_start + 10..payload_start + 26].copy_from_slice(&turnover.to_be_bytes());
+
+    if let Some(ohlc) = ohlc {
+        // Has OHLC (u8)
+        buf[payload_start + 26] = 1;
+        // Open, High, Low, Close (4 x i64)
+        buf[payload_start + 27..payload_start + 35].copy_from_slice(&ohlc.open.to_be_bytes());
+        buf[payload_start + 35..payload_start + 43].copy_from_slice(&ohlc.high.to_be_bytes());
+        buf[payload_start + 43..payload_start + 51].copy_from_slice(&ohlc.low.to_be_bytes());
+        buf[payload_start + 51..payload_start + 59].copy_from_slice(&ohlc.close.to_be_bytes());
+    }
+
+    buf[0] = calculate_checksum(&buf);
+
+    buf
+}
+
+/// Serializes a SnapshotHeader struct into the first frame of a snapshot file.
+pub fn serialize_snapshot_header(header: &SnapshotHeader) -> [u8; MESSAGE_TOTAL_SIZE] {
+    let mut buf = [0u8; MESSAGE_TOTAL_SIZE];
+    let payload_start = 2;
+
+    buf[1] = MSG_SNAPSHOT_HEADER;
+
+    // Sequence, then the six counters (7 x u64)
+    let fields = [
+        header.sequence,
+        header.matched_orders,
+        header.total_received_orders,
+        header.cancelled_orders,
+        header.clock_skew_count,
+        header.malformed_frames,
+        header.checksum_failures,
+    ];
+    for (i, value) in fields.iter().enumerate() {
+        let start = payload_start + i * 8;
+        buf[start..start + 8].copy_from_slice(&value.to_be_bytes());
+    }
+
+    buf[0] = calculate_checksum(&buf);
+
+    buf
+}
+
+/// Serializes a SnapshotProduct struct into a snapshot file frame.
+pub fn serialize_snapshot_product(product: &SnapshotProduct) -> [u8; MESSAGE_TOTAL_SIZE] {
+    let mut buf = [0u8; MESSAGE_TOTAL_SIZE];
+    let payload_start = 2;
+
+    buf[1] = MSG_SNAPSHOT_PRODUCT;
+
+    // Product ID (u16)
+    buf[payload_start..payload_start + 2].copy_from_slice(&product.product_id.to_be_bytes());
+    // Received Orders (u64)
+    buf[payload_start + 2..payload_start + 10].copy_from_slice(&product.received_orders.to_be_bytes());
+    // Matched Orders (u64)
+    buf[payload_start + 10..payload_start + 18].copy_from_slice(&product.matched_orders.to_be_bytes());
+
+    buf[0] = calculate_checksum(&buf);
+
+    buf
+}
+
 /// Serializes a BroadcastStats struct into a 50-byte network buffer.
 pub fn serialize_stats_result(stats: &BroadcastStats) -> [u8; MESSAGE_TOTAL_SIZE] {
     let mut buf = [0u8; MESSAGE_TOTAL_SIZE];
@@ -194,8 +728,16 @@ pub fn serialize_stats_result(stats: &BroadcastStats) -> [u8; MESSAGE_TOTAL_SIZE
     current_idx += 4; // Index: 32 (Last index written: 31)
 
     buf[current_idx..current_idx + 4].copy_from_slice(&stats.total_ask_volumn.to_be_bytes());
-    //current_idx += 4; // Index: 32 (Last index written: 31)
-    
+    current_idx += 4;
+
+    buf[current_idx..current_idx + 4].copy_from_slice(&stats.clock_skew_count.to_be_bytes());
+    current_idx += 4;
+
+    buf[current_idx..current_idx + 4].copy_from_slice(&stats.malformed_frames.to_be_bytes());
+    current_idx += 4;
+
+    buf[current_idx..current_idx + 4].copy_from_slice(&stats.checksum_failures.to_be_bytes());
+
     // Checksum calculation and placement
     // Last data byte is at index 31. Padding goes from index 32 up to MESSAGE_TOTAL_SIZE - 1.
     buf[0] = calculate_checksum(&buf);
@@ -203,40 +745,206 @@ pub fn serialize_stats_result(stats: &BroadcastStats) -> [u8; MESSAGE_TOTAL_SIZE
     buf
 }
 
-/// Unpacks a 50-byte network buffer into an Order or CancelOrder payload.
-/// Performs checksum validation and returns the message type and payload slice.
-pub fn unpack_message_payload(buf: &[u8; MESSAGE_TOTAL_SIZE]) -> Result<(u8, &[u8]), &'static str> {
-    if buf.len() != MESSAGE_TOTAL_SIZE {
-        return Err("Buffer size mismatch");
+/// Serializes a StatusExtension struct into a network buffer.
+pub fn serialize_status_extension(extension: &StatusExtension) -> [u8; MESSAGE_TOTAL_SIZE] {
+    let mut buf = [0u8; MESSAGE_TOTAL_SIZE];
+    let payload_start = 2;
+
+    buf[1] = MSG_STATUS_EXTENSION;
+
+    // Instance Tag ([u8; 16])
+    buf[payload_start..payload_start + 16].copy_from_slice(&extension.instance_tag);
+    // Uptime (u64)
+    buf[payload_start + 16..payload_start + 24].copy_from_slice(&extension.uptime_ns.to_be_bytes());
+    // Engine Version (3 x u8)
+    buf[payload_start + 24..payload_start + 27].copy_from_slice(&extension.engine_version);
+    // Queue Depth (u32)
+    buf[payload_start + 27..payload_start + 31].copy_from_slice(&extension.queue_depth.to_be_bytes());
+    // Max Queue Depth (u32)
+    buf[payload_start + 31..payload_start + 35].copy_from_slice(&extension.max_queue_depth.to_be_bytes());
+
+    buf[0] = calculate_checksum(&buf);
+
+    buf
+}
+
+/// Serializes a SequenceHeader struct into the leading frame of a datagram.
+pub fn serialize_sequence_header(header: &SequenceHeader) -> [u8; MESSAGE_TOTAL_SIZE] {
+    let mut buf = [0u8; MESSAGE_TOTAL_SIZE];
+    let payload_start = 2;
+
+    buf[1] = MSG_SEQUENCE;
+
+    // First Sequence Number (u64)
+    buf[payload_start..payload_start + 8].copy_from_slice(&header.first_seq.to_be_bytes());
+    // Frame Count (u16)
+    buf[payload_start + 8..payload_start + 10].copy_from_slice(&header.frame_count.to_be_bytes());
+
+    buf[0] = calculate_checksum(&buf);
+
+    buf
+}
+
+/// Serializes one side of a market data snapshot into a network buffer.
+///
+/// Layout after the header: product id (u16), side (u8), level count (u8), best
+/// price (u64), distance of each deeper level from the previous one (4 x u32)
+/// and the quantity of every level (5 x u32). Unused levels are zero. A level
+/// further from the previous one than a u32 can carry ends the frame, so the count
+/// sent may be lower than `count`; later levels would otherwise decode to wrong prices.
+fn serialize_market_data_side(
+    product_id: u16,
+    side: u8,
+    count: u8,
+    levels: &[DepthLevel; MARKET_DATA_DEPTH],
+) -> [u8; MESSAGE_TOTAL_SIZE] {
+    let mut buf = [0u8; MESSAGE_TOTAL_SIZE];
+    let payload_start = 2;
+
+    let mut count = (count as usize).min(MARKET_DATA_DEPTH);
+    let mut distances = [0u32; MARKET_DATA_DEPTH - 1];
+    for i in 1..count {
+        match u32::try_from(levels[i].price.abs_diff(levels[i - 1].price)) {
+            Ok(distance) => distances[i - 1] = distance,
+            Err(_) => {
+                count = i;
+                break;
+            }
+        }
+    }
+
+    buf[1] = MSG_MARKETDATA;
+
+    buf[payload_start..payload_start + 2].copy_from_slice(&product_id.to_be_bytes());
+    buf[payload_start + 2] = side;
+    buf[payload_start + 3] = count as u8;
+    buf[payload_start + 4..payload_start + 12].copy_from_slice(&levels[0].price.to_be_bytes());
+
+    let mut offset = payload_start + 12;
+    for distance in distances {
+        buf[offset..offset + 4].copy_from_slice(&distance.to_be_bytes());
+        offset += 4;
+    }
+    for level in &levels[..count] {
+        buf[offset..offset + 4].copy_from_slice(&level.quantity.to_be_bytes());
+        offset += 4;
     }
 
+    buf[0] = calculate_checksum(&buf);
+
+    buf
+}
+
+/// Serializes a market data snapshot as two frames, bids then asks.
+pub fn serialize_market_data(snapshot: &MarketDataSnapshot) -> Vec<u8> {
+    let mut buf = Vec::with_capacity(MESSAGE_TOTAL_SIZE * 2);
+    buf.extend_from_slice(&serialize_market_data_side(
+        snapshot.product_id,
+        ORDER_TYPE_BUY,
+        snapshot.bid_count,
+        &snapshot.bids,
+    ));
+    buf.extend_from_slice(&serialize_market_data_side(
+        snapshot.product_id,
+        ORDER_TYPE_SELL,
+        snapshot.ask_count,
+        &snapshot.asks,
+    ));
+    buf
+}
+
+/// Deserializes one MSG_MARKETDATA payload into (product id, side, levels).
+#[cfg(test)]
+pub fn deserialize_market_data_side(
+    payload: &[u8],
+) -> Result<(u16, u8, Vec<DepthLevel>), CodecError> {
+    require_len(payload, 52)?;
+
+    let product_id = u16::from_be_bytes(field(payload, 0)?);
+    let [side, count] = field(payload, 2)?;
+    let count = (count as usize).min(MARKET_DATA_DEPTH);
+    let mut price = i64::from_be_bytes(field(payload, 4)?);
+
+    let mut levels = Vec::with_capacity(count);
+    for i in 0..count {
+        if i > 0 {
+            let at = 12 + (i - 1) * 4;
+            let distance = u32::from_be_bytes(field(payload, at)?) as i64;
+            // bids step down from the best price, asks step up
+            price = if side == ORDER_TYPE_BUY {
+                price.saturating_sub(distance)
+            } else {
+                price.saturating_add(distance)
+            };
+        }
+        let at = 28 + i * 4;
+        let quantity = u32::from_be_bytes(field(payload, at)?);
+        levels.push(DepthLevel { price, quantity });
+    }
+
+    Ok((product_id, side, levels))
+}
+
+/// Unpacks a 50-byte network buffer into an Order or CancelOrder payload.
+/// Performs checksum validation and returns the message type, the layout version
+/// from the top bits of the type byte, and the payload slice.
+pub fn unpack_message_payload(buf: &[u8; MESSAGE_TOTAL_SIZE]) -> Result<(u8, u8, &[u8]), CodecError> {
     let received_checksum = buf[0];
     let calculated_checksum = calculate_checksum(buf);
 
     if received_checksum != calculated_checksum {
-        return Err("Checksum failed");
+        return Err(CodecError::ChecksumMismatch {
+            expected: calculated_checksum,
+            got: received_checksum,
+        });
     }
 
-    let message_type = buf[1];
+    let message_type = buf[1] & MSG_TYPE_MASK;
+    let version = buf[1] >> MSG_VERSION_SHIFT;
     let payload = &buf[2..];
 
-    Ok((message_type, payload))
+    Ok((message_type, version, payload))
 }
 
 /// Deserializes a payload slice into an Order struct.
-pub fn deserialize_order(payload: &[u8]) -> Result<Order, &'static str> {
-    if payload.len() < 40 {
-        return Err("Order payload too short");
-    }
-
-    let product_id = u16::from_be_bytes(payload[0..2].try_into().unwrap());
-    let order_id = u64::from_be_bytes(payload[2..10].try_into().unwrap());
-    let price = u64::from_be_bytes(payload[10..18].try_into().unwrap());
-    let quantity = u32::from_be_bytes(payload[18..22].try_into().unwrap());
-    let order_type = payload[22];
-    let price_type = payload[23];
-    let submit_time = u64::from_be_bytes(payload[24..32].try_into().unwrap());
-    let expire_time = u64::from_be_bytes(payload[32..40].try_into().unwrap());
+///
+/// A legacy layout has only the first 40 bytes; every later field takes its default
+/// whatever the rest of the frame holds. The extended layout reads them all, and so
+/// does an unversioned frame, whose older clients leave unknown fields zero.
+pub fn deserialize_order(payload: &[u8], version: u8) -> Result<Order, CodecError> {
+    require_len(payload, 40)?;
+    let payload = match version {
+        ORDER_LAYOUT_LEGACY => &payload[..40],
+        ORDER_LAYOUT_UNVERSIONED | ORDER_LAYOUT_EXTENDED => payload,
+        _ => return Err(CodecError::UnsupportedVersion(version)),
+    };
+
+    let product_id = u16::from_be_bytes(field(payload, 0)?);
+    let order_id = require_order_id(u64::from_be_bytes(field(payload, 2)?))?;
+    let price = i64::from_be_bytes(field(payload, 10)?);
+    let quantity = u32::from_be_bytes(field(payload, 18)?);
+    let [order_type, price_type] = field(payload, 22)?;
+    if !(ORDER_TYPE_BUY..=ORDER_TYPE_MOCK_SELL).contains(&order_type) {
+        return Err(CodecError::UnknownOrderType(order_type));
+    }
+    if price_type != ORDER_PRICE_TYPE_LIMIT && price_type != ORDER_PRICE_TYPE_MARKET {
+        return Err(CodecError::UnknownPriceType(price_type));
+    }
+    let submit_time = u64::from_be_bytes(field(payload, 24)?);
+    let expire_time = u64::from_be_bytes(field(payload, 32)?);
+    // frames from older clients leave this byte zero, which is TIF_GTC
+    let time_in_force = payload.get(40).copied().unwrap_or(TIF_GTC);
+    if time_in_force > TIF_GTD {
+        return Err(CodecError::UnknownTimeInForce(time_in_force));
+    }
+    // zero from older clients as well, meaning no minimum
+    let min_qty = field(payload, 49).map_or(0, u32::from_be_bytes);
+    let aon = payload.get(53).is_some_and(|&flag| flag != 0);
+    let trader_id = field(payload, 54).map_or(0, u32::from_be_bytes);
+    // zero from older clients too, which shows the whole quantity
+    let display_quantity = field(payload, 58).map_or(0, u32::from_be_bytes);
+    // opaque to the engine; zero from older clients, who left these bytes unused
+    let client_ref = field(payload, 41).unwrap_or([0; 8]);
     Ok(Order {
         product_id,
         order_id,
@@ -245,21 +953,567 @@ pub fn deserialize_order(payload: &[u8]) -> Result<Order, &'static str> {
         order_type,
         price_type,
         submit_time,
-        expire_time
+        expire_time,
+        time_in_force,
+        min_qty,
+        aon,
+        trader_id,
+        client_ref,
+        display_quantity,
     })
 }
 
-/// Deserializes a payload slice into a CancelOrder struct.
-pub fn deserialize_cancel_order(payload: &[u8]) -> Result<CancelOrder, &'static str> {
-    if payload.len() < 10 {
-        return Err("CancelOrder payload too short");
+/// Deserializes a payload slice into a ReplaceOrder struct.
+pub fn deserialize_replace_order(payload: &[u8], version: u8) -> Result<ReplaceOrder, CodecError> {
+    require_len(payload, 49)?;
+
+    let mut order = deserialize_order(payload, version)?;
+    let old_order_id = require_order_id(u64::from_be_bytes(field(payload, 41)?))?;
+    // those bytes were the old order ID; the replacement keeps the replaced order's reference
+    order.client_ref = [0; 8];
+
+    Ok(ReplaceOrder { old_order_id, order })
+}
+
+/// Deserializes a payload slice into an OrderQuery struct.
+pub fn deserialize_order_query(payload: &[u8]) -> Result<OrderQuery, CodecError> {
+    require_len(payload, 10)?;
+
+    let product_id = u16::from_be_bytes(field(payload, 0)?);
+    let order_id = require_order_id(u64::from_be_bytes(field(payload, 2)?))?;
+
+    Ok(OrderQuery {
+        product_id,
+        order_id,
+    })
+}
+
+/// Deserializes a payload slice into a BookAdmin struct.
+pub fn deserialize_book_admin(payload: &[u8]) -> Result<BookAdmin, CodecError> {
+    require_len(payload, 3)?;
+
+    let product_id = u16::from_be_bytes(field(payload, 0)?);
+    let [command] = field(payload, 2)?;
+    if command != BOOK_ADMIN_REPORT_MEMORY && command != BOOK_ADMIN_SHRINK && command != BOOK_ADMIN_REGISTER {
+        return Err(CodecError::UnknownAdminCommand(command));
     }
 
-    let product_id = u16::from_be_bytes(payload[0..2].try_into().unwrap());
-    let order_id = u64::from_be_bytes(payload[2..10].try_into().unwrap());
+    Ok(BookAdmin {
+        product_id,
+        command,
+    })
+}
+
+/// Deserializes a MSG_SET_TRADING_STATE payload slice into a TradingStateChange struct.
+pub fn deserialize_trading_state(payload: &[u8]) -> Result<TradingStateChange, CodecError> {
+    require_len(payload, 3)?;
+
+    let product_id = u16::from_be_bytes(field(payload, 0)?);
+    let [state] = field(payload, 2)?;
+    let state = TradingState::from_u8(state).ok_or(CodecError::UnknownTradingState(state))?;
+
+    Ok(TradingStateChange { product_id, state })
+}
+
+/// Deserializes a payload slice into a CancelAtPrice struct.
+pub fn deserialize_cancel_at_price(payload: &[u8]) -> Result<CancelAtPrice, CodecError> {
+    require_len(payload, 15)?;
+
+    let product_id = u16::from_be_bytes(field(payload, 0)?);
+    let [order_type] = field(payload, 2)?;
+    let price = i64::from_be_bytes(field(payload, 3)?);
+    let trader_id = u32::from_be_bytes(field(payload, 11)?);
+
+    Ok(CancelAtPrice {
+        product_id,
+        order_type,
+        price,
+        trader_id,
+    })
+}
+
+/// Deserializes a MSG_STATUS_EXTENSION payload slice into a StatusExtension struct.
+#[cfg(test)]
+pub fn deserialize_status_extension(payload: &[u8]) -> Result<StatusExtension, CodecError> {
+    require_len(payload, 35)?;
+
+    Ok(StatusExtension {
+        instance_tag: field(payload, 0)?,
+        uptime_ns: u64::from_be_bytes(field(payload, 16)?),
+        engine_version: field(payload, 24)?,
+        queue_depth: u32::from_be_bytes(field(payload, 27)?),
+        max_queue_depth: u32::from_be_bytes(field(payload, 31)?),
+    })
+}
+
+/// Deserializes a MSG_SEQUENCE payload slice into a SequenceHeader struct.
+#[cfg(test)]
+pub fn deserialize_sequence_header(payload: &[u8]) -> Result<SequenceHeader, CodecError> {
+    require_len(payload, 10)?;
+
+    Ok(SequenceHeader {
+        first_seq: u64::from_be_bytes(field(payload, 0)?),
+        frame_count: u16::from_be_bytes(field(payload, 8)?),
+    })
+}
+
+/// Deserializes a MSG_SNAPSHOT_HEADER payload slice into a SnapshotHeader struct.
+pub fn deserialize_snapshot_header(payload: &[u8]) -> Result<SnapshotHeader, CodecError> {
+    require_len(payload, 56)?;
+
+    Ok(SnapshotHeader {
+        sequence: u64::from_be_bytes(field(payload, 0)?),
+        matched_orders: u64::from_be_bytes(field(payload, 8)?),
+        total_received_orders: u64::from_be_bytes(field(payload, 16)?),
+        cancelled_orders: u64::from_be_bytes(field(payload, 24)?),
+        clock_skew_count: u64::from_be_bytes(field(payload, 32)?),
+        malformed_frames: u64::from_be_bytes(field(payload, 40)?),
+        checksum_failures: u64::from_be_bytes(field(payload, 48)?),
+    })
+}
+
+/// Deserializes a MSG_SNAPSHOT_PRODUCT payload slice into a SnapshotProduct struct.
+pub fn deserialize_snapshot_product(payload: &[u8]) -> Result<SnapshotProduct, CodecError> {
+    require_len(payload, 18)?;
+
+    Ok(SnapshotProduct {
+        product_id: u16::from_be_bytes(field(payload, 0)?),
+        received_orders: u64::from_be_bytes(field(payload, 2)?),
+        matched_orders: u64::from_be_bytes(field(payload, 10)?),
+    })
+}
+
+/// Deserializes a MSG_SESSION_SUMMARY payload slice into its product and SessionStats.
+pub fn deserialize_session_summary(payload: &[u8]) -> Result<(u16, SessionStats), CodecError> {
+    require_len(payload, 59)?;
+
+    let product_id = u16::from_be_bytes(field(payload, 0)?);
+    let volume = u64::from_be_bytes(field(payload, 2)?);
+    let turnover = i128::from_be_bytes(field(payload, 10)?);
+    let [has_ohlc] = field(payload, 26)?;
+    let ohlc = if has_ohlc != 0 {
+        Some(Ohlc {
+            open: i64::from_be_bytes(field(payload, 27)?),
+            high: i64::from_be_bytes(field(payload, 35)?),
+            low: i64::from_be_bytes(field(payload, 43)?),
+            close: i64::from_be_bytes(field(payload, 51)?),
+        })
+    } else {
+        None
+    };
+
+    Ok((product_id, SessionStats { volume, turnover, ohlc }))
+}
+
+/// Deserializes a payload slice into a CancelOrder struct.
+pub fn deserialize_cancel_order(payload: &[u8]) -> Result<CancelOrder, CodecError> {
+    require_len(payload, 10)?;
+
+    let product_id = u16::from_be_bytes(field(payload, 0)?);
+    let order_id = require_order_id(u64::from_be_bytes(field(payload, 2)?))?;
 
     Ok(CancelOrder {
         product_id,
         order_id,
     })
 }
+
+/// Deserializes one MSG_ORDER_CANCEL_BATCH payload into a CancelOrderBatch of its own
+/// order IDs; the engine cancels them without waiting for the rest of the batch.
+pub fn deserialize_cancel_order_chunk(payload: &[u8]) -> Result<CancelOrderBatch, CodecError> {
+    require_len(payload, 5)?;
+
+    let product_id = u16::from_be_bytes(field(payload, 0)?);
+    let [index, count, id_count] = field(payload, 2)?;
+    if index >= count {
+        return Err(CodecError::ChunkOutOfSequence { index, count });
+    }
+    if id_count as usize > MAX_IDS_PER_CHUNK {
+        return Err(CodecError::TooManyIds(id_count as usize));
+    }
+    let order_ids = (0..id_count as usize)
+        .map(|i| require_order_id(u64::from_be_bytes(field(payload, 5 + i * 8)?)))
+        .collect::<Result<Vec<u64>, CodecError>>()?;
+
+    Ok(CancelOrderBatch { product_id, order_ids })
+}
+
+/// Reassembles the frames of `serialize_cancel_order_batch` into the batch they
+/// carry. Every chunk must be there, in order, and of the same product; there must be
+/// at least one.
+#[cfg(test)]
+pub fn deserialize_cancel_order_batch(frames: &[[u8; MESSAGE_TOTAL_SIZE]]) -> Result<CancelOrderBatch, CodecError> {
+    let mut batch: Option<CancelOrderBatch> = None;
+    for (position, frame) in frames.iter().enumerate() {
+        let (message_type, _, payload) = unpack_message_payload(frame)?;
+        if message_type != MSG_ORDER_CANCEL_BATCH {
+            return Err(CodecError::UnknownMessageType(message_type));
+        }
+        let chunk = deserialize_cancel_order_chunk(payload)?;
+        let (index, count) = (payload[2], payload[3]);
+        let batch = batch.get_or_insert_with(|| CancelOrderBatch { product_id: chunk.product_id, order_ids: Vec::new() });
+        if index as usize != position || count as usize != frames.len() || chunk.product_id != batch.product_id {
+            return Err(CodecError::ChunkOutOfSequence { index, count });
+        }
+        batch.order_ids.extend(chunk.order_ids);
+    }
+    batch.ok_or(CodecError::BufferTooShort { need: MESSAGE_TOTAL_SIZE, got: 0 })
+}
+
+/// Decodes the payload of an inbound message type into an engine message. `version`
+/// selects the order layout of order and replace frames.
+pub fn deserialize_message(message_type: u8, version: u8, payload: &[u8]) -> Result<IncomingMessage, CodecError> {
+    match message_type {
+        MSG_ORDER_SUBMIT => deserialize_order(payload, version).map(IncomingMessage::Order),
+        MSG_ORDER_CANCEL => deserialize_cancel_order(payload).map(IncomingMessage::Cancel),
+        MSG_ORDER_CANCEL_BATCH => deserialize_cancel_order_chunk(payload).map(IncomingMessage::CancelBatch),
+        MSG_CANCEL_AT_PRICE => deserialize_cancel_at_price(payload).map(IncomingMessage::CancelAtPrice),
+        MSG_ORDER_QUERY => deserialize_order_query(payload).map(IncomingMessage::Query),
+        MSG_ORDER_REPLACE => deserialize_replace_order(payload, version).map(IncomingMessage::Replace),
+        MSG_WHATIF => deserialize_order(payload, version).map(IncomingMessage::WhatIf),
+        MSG_BOOK_ADMIN => deserialize_book_admin(payload).map(IncomingMessage::BookAdmin),
+        MSG_SET_TRADING_STATE => deserialize_trading_state(payload).map(IncomingMessage::SetTradingState),
+        _ => Err(CodecError::UnknownMessageType(message_type)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data_types::ORDER_TYPE_MOCK_BUY;
+    use crate::test_order_book_builder::XorShift64;
+
+    fn sample_order() -> Order {
+        Order {
+            product_id: u16::MAX,
+            order_type: ORDER_TYPE_SELL,
+            price_type: ORDER_PRICE_TYPE_LIMIT,
+            quantity: u32::MAX,
+            order_id: u64::MAX,
+            price: i64::MIN,
+            submit_time: 1_700_000_000_123_456_789,
+            expire_time: u64::MAX,
+            time_in_force: TIF_GTD,
+            min_qty: 7,
+            aon: true,
+            trader_id: 0xDEAD_BEEF,
+            client_ref: *b"ref-0001",
+            display_quantity: 0x0102_0304,
+        }
+    }
+
+    fn assert_same_order(decoded: &Order, sent: &Order) {
+        assert_eq!(decoded.product_id, sent.product_id);
+        assert_eq!(decoded.order_type, sent.order_type);
+        assert_eq!(decoded.price_type, sent.price_type);
+        assert_eq!(decoded.quantity, sent.quantity);
+        assert_eq!(decoded.order_id, sent.order_id);
+        assert_eq!(decoded.price, sent.price);
+        assert_eq!(decoded.submit_time, sent.submit_time);
+        assert_eq!(decoded.expire_time, sent.expire_time);
+        assert_eq!(decoded.time_in_force, sent.time_in_force);
+        assert_eq!(decoded.min_qty, sent.min_qty);
+        assert_eq!(decoded.aon, sent.aon);
+        assert_eq!(decoded.trader_id, sent.trader_id);
+        assert_eq!(decoded.client_ref, sent.client_ref);
+        assert_eq!(decoded.display_quantity, sent.display_quantity);
+    }
+
+    fn decode(frame: &[u8; MESSAGE_TOTAL_SIZE]) -> Result<IncomingMessage, CodecError> {
+        let (message_type, version, payload) = unpack_message_payload(frame)?;
+        deserialize_message(message_type, version, payload)
+    }
+
+    /// Rewrites one payload byte and fixes the checksum, as a client sending it would.
+    fn with_payload_byte(mut frame: [u8; MESSAGE_TOTAL_SIZE], at: usize, value: u8) -> [u8; MESSAGE_TOTAL_SIZE] {
+        frame[2 + at] = value;
+        frame[0] = calculate_checksum(&frame);
+        frame
+    }
+
+    #[test]
+    fn order_round_trips_at_field_limits() {
+        let sent = sample_order();
+        match decode(&serialize_order(&sent)) {
+            Ok(IncomingMessage::Order(decoded)) => assert_same_order(&decoded, &sent),
+            other => panic!("expected an order, got {:?}", other),
+        }
+
+        let sent = Order {
+            order_type: ORDER_TYPE_MOCK_BUY,
+            price_type: ORDER_PRICE_TYPE_MARKET,
+            order_id: 1,
+            price: i64::MAX,
+            time_in_force: TIF_GTC,
+            aon: false,
+            ..sample_order()
+        };
+        match decode(&serialize_order(&sent)) {
+            Ok(IncomingMessage::Order(decoded)) => assert_same_order(&decoded, &sent),
+            other => panic!("expected an order, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn legacy_order_ignores_fields_past_forty_bytes() {
+        let mut frame = serialize_order(&sample_order());
+        frame[1] = versioned_type(MSG_ORDER_SUBMIT, ORDER_LAYOUT_LEGACY);
+        frame[0] = calculate_checksum(&frame);
+
+        let Ok(IncomingMessage::Order(decoded)) = decode(&frame) else {
+            panic!("expected an order");
+        };
+        assert_eq!(decoded.time_in_force, TIF_GTC);
+        assert_eq!(decoded.min_qty, 0);
+        assert!(!decoded.aon);
+        assert_eq!(decoded.trader_id, 0);
+        assert_eq!(decoded.client_ref, [0; 8]);
+    }
+
+    #[test]
+    fn order_with_unknown_type_is_rejected() {
+        let frame = serialize_order(&sample_order());
+        for order_type in [0, ORDER_TYPE_MOCK_SELL + 1, u8::MAX] {
+            assert_eq!(
+                decode(&with_payload_byte(frame, 22, order_type)).err(),
+                Some(CodecError::UnknownOrderType(order_type))
+            );
+        }
+        for price_type in [0, ORDER_PRICE_TYPE_MARKET + 1] {
+            assert_eq!(
+                decode(&with_payload_byte(frame, 23, price_type)).err(),
+                Some(CodecError::UnknownPriceType(price_type))
+            );
+        }
+        assert_eq!(
+            decode(&with_payload_byte(frame, 40, TIF_GTD + 1)).err(),
+            Some(CodecError::UnknownTimeInForce(TIF_GTD + 1))
+        );
+    }
+
+    #[test]
+    fn order_id_zero_is_rejected() {
+        let sent = Order { order_id: 0, ..sample_order() };
+        assert_eq!(decode(&serialize_order(&sent)).err(), Some(CodecError::ReservedOrderIdZero));
+    }
+
+    #[test]
+    fn corrupted_frame_fails_the_checksum() {
+        let mut frame = serialize_order(&sample_order());
+        frame[20] ^= 0x01;
+        assert!(matches!(decode(&frame), Err(CodecError::ChecksumMismatch { .. })));
+    }
+
+    #[test]
+    fn cancel_round_trips() {
+        let frame = serialize_cancel_order(&CancelOrder { product_id: 7, order_id: u64::MAX });
+        match decode(&frame) {
+            Ok(IncomingMessage::Cancel(decoded)) => {
+                assert_eq!(decoded.product_id, 7);
+                assert_eq!(decoded.order_id, u64::MAX);
+            }
+            other => panic!("expected a cancel, got {:?}", other),
+        }
+
+        let frame = serialize_cancel_order(&CancelOrder { product_id: 7, order_id: 0 });
+        assert_eq!(decode(&frame).err(), Some(CodecError::ReservedOrderIdZero));
+    }
+
+    #[test]
+    fn replace_round_trips_without_a_client_ref() {
+        let sent = ReplaceOrder { old_order_id: 42, order: sample_order() };
+        match decode(&serialize_replace_order(&sent)) {
+            Ok(IncomingMessage::Replace(decoded)) => {
+                assert_eq!(decoded.old_order_id, 42);
+                // the client reference bytes carry the old order ID in a replace
+                let expected = Order { client_ref: [0; 8], ..sample_order() };
+                assert_same_order(&decoded.order, &expected);
+            }
+            other => panic!("expected a replace, got {:?}", other),
+        }
+    }
+
+    /// A value from `rng`, one draw in four a boundary of the type instead.
+    fn draw(rng: &mut XorShift64) -> u64 {
+        match rng.next_range(0, 7) {
+            0 => 0,
+            1 => u64::MAX,
+            _ => rng.next_u64(),
+        }
+    }
+
+    /// An order the decoder accepts: known types, a time in force up to GTD and a
+    /// non-zero id, every other field drawn freely.
+    fn random_order(rng: &mut XorShift64) -> Order {
+        Order {
+            product_id: draw(rng) as u16,
+            order_type: rng.next_range(ORDER_TYPE_BUY as u64, ORDER_TYPE_MOCK_SELL as u64) as u8,
+            price_type: rng.next_range(ORDER_PRICE_TYPE_LIMIT as u64, ORDER_PRICE_TYPE_MARKET as u64) as u8,
+            quantity: draw(rng) as u32,
+            order_id: draw(rng).max(1),
+            price: draw(rng) as i64,
+            submit_time: draw(rng),
+            expire_time: draw(rng),
+            time_in_force: rng.next_range(TIF_GTC as u64, TIF_GTD as u64) as u8,
+            min_qty: draw(rng) as u32,
+            aon: rng.next_u64() & 1 == 1,
+            trader_id: draw(rng) as u32,
+            client_ref: draw(rng).to_be_bytes(),
+            display_quantity: draw(rng) as u32,
+        }
+    }
+
+    /// `sent` as a frame of layout `version` decodes it.
+    fn as_decoded(sent: &Order, version: u8) -> Order {
+        if version != ORDER_LAYOUT_LEGACY {
+            return sent.clone();
+        }
+        Order {
+            time_in_force: TIF_GTC,
+            min_qty: 0,
+            aon: false,
+            trader_id: 0,
+            client_ref: [0; 8],
+            display_quantity: 0,
+            ..sent.clone()
+        }
+    }
+
+    fn with_version(mut frame: [u8; MESSAGE_TOTAL_SIZE], version: u8) -> [u8; MESSAGE_TOTAL_SIZE] {
+        frame[1] = versioned_type(frame[1] & MSG_TYPE_MASK, version);
+        frame[0] = calculate_checksum(&frame);
+        frame
+    }
+
+    #[test]
+    fn cancel_batch_of_twelve_ids_round_trips_in_three_chunks() {
+        let batch = CancelOrderBatch { product_id: 7, order_ids: (1..=12).map(|id| id * 1_000 + u64::MAX / 2).collect() };
+        let frames = serialize_cancel_order_batch(&batch).unwrap();
+        assert_eq!(frames.len(), 3);
+        assert_eq!(deserialize_cancel_order_batch(&frames), Ok(batch.clone()));
+
+        // each chunk cancels its own ids as it arrives
+        let sizes: Vec<usize> = frames
+            .iter()
+            .map(|frame| match decode(frame) {
+                Ok(IncomingMessage::CancelBatch(chunk)) => chunk.order_ids.len(),
+                other => panic!("unexpected {:?}", other),
+            })
+            .collect();
+        assert_eq!(sizes, [5, 5, 2]);
+
+        let mut reordered = frames.clone();
+        reordered.swap(0, 1);
+        assert_eq!(deserialize_cancel_order_batch(&reordered), Err(CodecError::ChunkOutOfSequence { index: 1, count: 3 }));
+        assert_eq!(deserialize_cancel_order_batch(&frames[..2]), Err(CodecError::ChunkOutOfSequence { index: 0, count: 3 }));
+    }
+
+    #[test]
+    fn cancel_batch_refuses_zero_ids_and_oversized_batches_without_panicking() {
+        let zero = CancelOrderBatch { product_id: 7, order_ids: vec![1, 2, 0, 4] };
+        assert_eq!(serialize_cancel_order_batch(&zero), Err(CodecError::ReservedOrderIdZero));
+
+        let oversized = CancelOrderBatch { product_id: 7, order_ids: (1..=255 * MAX_IDS_PER_CHUNK as u64 + 1).collect() };
+        assert_eq!(serialize_cancel_order_batch(&oversized), Err(CodecError::TooManyIds(1_276)));
+        let largest = CancelOrderBatch { product_id: 7, order_ids: (1..=255 * MAX_IDS_PER_CHUNK as u64).collect() };
+        assert_eq!(serialize_cancel_order_batch(&largest).map(|frames| frames.len()), Ok(255));
+        assert_eq!(serialize_cancel_order_batch(&CancelOrderBatch { product_id: 7, order_ids: vec![] }), Ok(vec![]));
+
+        // frames from elsewhere claiming too many ids, a chunk past the count or a zero id
+        let frame = serialize_cancel_order_batch(&CancelOrderBatch { product_id: 7, order_ids: vec![9] }).unwrap()[0];
+        assert_eq!(decode(&with_payload_byte(frame, 4, 6)).err(), Some(CodecError::TooManyIds(6)));
+        assert_eq!(decode(&with_payload_byte(frame, 2, 1)).err(), Some(CodecError::ChunkOutOfSequence { index: 1, count: 1 }));
+        assert_eq!(decode(&with_payload_byte(frame, 4, 2)).err(), Some(CodecError::ReservedOrderIdZero));
+    }
+
+    #[test]
+    fn random_frames_round_trip_at_every_layout_version() {
+        let mut rng = XorShift64::new(0x5EED);
+        for _ in 0..2_000 {
+            let sent = random_order(&mut rng);
+            let replace = ReplaceOrder { old_order_id: draw(&mut rng).max(1), order: sent.clone() };
+            let cancel = CancelOrder { product_id: draw(&mut rng) as u16, order_id: draw(&mut rng).max(1) };
+
+            for version in [ORDER_LAYOUT_UNVERSIONED, ORDER_LAYOUT_LEGACY, ORDER_LAYOUT_EXTENDED] {
+                match decode(&with_version(serialize_order(&sent), version)) {
+                    Ok(IncomingMessage::Order(decoded)) => assert_same_order(&decoded, &as_decoded(&sent, version)),
+                    other => panic!("expected an order at version {}, got {:?}", version, other),
+                }
+                match decode(&with_version(serialize_replace_order(&replace), version)) {
+                    Ok(IncomingMessage::Replace(decoded)) => {
+                        assert_eq!(decoded.old_order_id, replace.old_order_id);
+                        let expected = Order { client_ref: [0; 8], ..as_decoded(&sent, version) };
+                        assert_same_order(&decoded.order, &expected);
+                    }
+                    other => panic!("expected a replace at version {}, got {:?}", version, other),
+                }
+                match decode(&with_version(serialize_cancel_order(&cancel), version)) {
+                    Ok(IncomingMessage::Cancel(decoded)) => {
+                        assert_eq!((decoded.product_id, decoded.order_id), (cancel.product_id, cancel.order_id));
+                    }
+                    other => panic!("expected a cancel at version {}, got {:?}", version, other),
+                }
+            }
+        }
+    }
+
+    fn market_data(bids: &[DepthLevel], asks: &[DepthLevel]) -> MarketDataSnapshot {
+        let mut snapshot = MarketDataSnapshot {
+            product_id: 9,
+            bid_count: bids.len() as u8,
+            ask_count: asks.len() as u8,
+            bids: [DepthLevel::default(); MARKET_DATA_DEPTH],
+            asks: [DepthLevel::default(); MARKET_DATA_DEPTH],
+        };
+        snapshot.bids[..bids.len()].copy_from_slice(bids);
+        snapshot.asks[..asks.len()].copy_from_slice(asks);
+        snapshot
+    }
+
+    fn decode_market_data(frames: &[u8]) -> Vec<(u16, u8, Vec<DepthLevel>)> {
+        frames
+            .chunks_exact(MESSAGE_TOTAL_SIZE)
+            .map(|frame| {
+                let (message_type, _, payload) = unpack_message_payload(frame.try_into().unwrap()).unwrap();
+                assert_eq!(message_type, MSG_MARKETDATA);
+                deserialize_market_data_side(payload).unwrap()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn market_data_round_trips() {
+        let bids = [
+            DepthLevel { price: 1_000, quantity: 5 },
+            DepthLevel { price: 990, quantity: u32::MAX },
+            DepthLevel { price: -50, quantity: 1 },
+        ];
+        let asks = [DepthLevel { price: 1_010, quantity: 3 }];
+
+        let sides = decode_market_data(&serialize_market_data(&market_data(&bids, &asks)));
+        assert_eq!(sides, vec![(9, ORDER_TYPE_BUY, bids.to_vec()), (9, ORDER_TYPE_SELL, asks.to_vec())]);
+    }
+
+    #[test]
+    fn market_data_of_an_empty_book_has_no_levels() {
+        let sides = decode_market_data(&serialize_market_data(&market_data(&[], &[])));
+        assert_eq!(sides, vec![(9, ORDER_TYPE_BUY, vec![]), (9, ORDER_TYPE_SELL, vec![])]);
+    }
+
+    #[test]
+    fn market_data_ends_before_a_gap_too_wide_for_u32() {
+        let gap = u32::MAX as i64 + 1;
+        let bids = [
+            DepthLevel { price: 0, quantity: 1 },
+            DepthLevel { price: -(u32::MAX as i64), quantity: 2 },
+            DepthLevel { price: -(u32::MAX as i64) - gap, quantity: 3 },
+        ];
+        let asks = [
+            DepthLevel { price: 10, quantity: 4 },
+            DepthLevel { price: 10 + gap, quantity: 5 },
+        ];
+
+        let sides = decode_market_data(&serialize_market_data(&market_data(&bids, &asks)));
+        assert_eq!(sides, vec![(9, ORDER_TYPE_BUY, bids[..2].to_vec()), (9, ORDER_TYPE_SELL, asks[..1].to_vec())]);
+    }
+}
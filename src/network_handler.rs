@@ -0,0 +1,659 @@
+use std::io::ErrorKind;
+use std::net::{Ipv4Addr, SocketAddr, UdpSocket};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::Receiver;
+use std::sync::{Arc, Mutex, PoisonError};
+use std::time::Duration;
+
+use ahash::AHashMap;
+
+use crate::data_types::{
+    CancelAck, CancelReject, ChecksumPolicy, EngineState, IncomingMessage, MESSAGE_TOTAL_SIZE, MSG_CANCEL_ACK,
+    MSG_CANCEL_REJECT, MSG_MARKETDATA, MSG_ORDER_ACK, MSG_ORDER_FILLED, MSG_ORDER_QUERY_RESPONSE, MSG_SESSION_SUMMARY,
+    MSG_STATUS_BROADCAST, MSG_TRADE_BROADCAST, MSG_UNFILLED_MARKET, MatchResult, TapeAggregation, OrderAck, OrderQueryResponse, OrderSummary, ResultSender, UnfilledMarket,
+    MSG_WHATIF_RESPONSE, WhatIfResponse, BookMemoryReport, MSG_BOOK_MEMORY, MSG_TRADING_STATE, TradingStateChange,
+    BookDigest, BookState, BOOK_DIGEST_DEPTH, MSG_BOOK_DIGEST, MARKET_DATA_DEPTH, MSG_STATUS_EXTENSION, MSG_SEQUENCE, SequenceHeader,
+};
+use crate::date_time_tool::{current_timestamp, saturating_duration};
+use crate::engine_state::StatusBroadcaster;
+use crate::matcher_actor::{DroppedFrame, Housekeeping, MatcherActor, MatcherCommand};
+use crate::high_resolution_timer::HighResolutionTimer;
+use crate::log_tool::LogThrottle;
+use crate::{log_error, log_info, log_warn};
+use crate::message_codec::{self, CodecError};
+use crate::order_matcher::OrderMatcher;
+
+/// Wakes the receive loop up often enough for the status broadcaster to stay on time.
+const RECV_TIMEOUT: Duration = Duration::from_millis(50);
+
+/// Largest UDP payload over IPv4. A batch of frames beyond an Ethernet MTU (jumbo
+/// frames, loopback) is read whole instead of truncated and dropped as malformed.
+const MAX_DATAGRAM_SIZE: usize = 65_507;
+
+/// At most one malformed-frame log line per interval, so junk traffic cannot flood the log.
+const MALFORMED_LOG_INTERVAL_NS: u64 = 1_000_000_000;
+
+/// Window over which checksum failures are counted against the alert threshold.
+const CHECKSUM_WINDOW_NS: u64 = 1_000_000_000;
+
+/// Gap between two top-of-book market data snapshots, session summaries and book digests.
+const MARKET_DATA_INTERVAL_NS: u64 = 1_000_000_000;
+
+/// How long a shutdown waits for the matcher to apply, and answer, the frames it was handed.
+const SHUTDOWN_DRAIN_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Set by SIGINT or SIGTERM once `stop_on_signals` installed the handler.
+static SHUTDOWN_REQUESTED: AtomicBool = AtomicBool::new(false);
+
+/// Makes SIGINT and SIGTERM end `NetworkHandler::run` cleanly instead of killing the
+/// process with executions still queued for broadcast.
+#[cfg(unix)]
+pub fn stop_on_signals() {
+    extern "C" fn request_shutdown(_signal: libc::c_int) {
+        SHUTDOWN_REQUESTED.store(true, Ordering::Relaxed);
+    }
+    let handler = request_shutdown as extern "C" fn(libc::c_int) as libc::sighandler_t;
+    unsafe {
+        libc::signal(libc::SIGINT, handler);
+        libc::signal(libc::SIGTERM, handler);
+    }
+}
+
+#[cfg(not(unix))]
+pub fn stop_on_signals() {
+    log_warn!("Shutdown signals not handled on this platform; stopping drops queued executions");
+}
+
+fn shutdown_requested() -> bool {
+    SHUTDOWN_REQUESTED.load(Ordering::Relaxed)
+}
+
+/// Owns the engine's sockets: one joined to the order group for receiving, one for
+/// broadcasting results and status to the broadcast group.
+pub struct NetworkHandler {
+    socket: UdpSocket,
+    multicast_addr: SocketAddr,
+    broadcast_socket: UdpSocket,
+    broadcast_addr: SocketAddr,
+    sequencer: Arc<FrameSequencer>,
+    checksum_monitor: ChecksumMonitor,
+    tape_aggregation: TapeAggregation,
+    mock_trade_addr: Option<SocketAddr>,
+}
+
+/// Counts frames dropped for a bad checksum and reports them per `ChecksumPolicy`.
+///
+/// Random bit flips on a lossy link show up as a trickle; a systematic framing bug
+/// (wrong layout, wrong checksum range) fails every frame. The per-window threshold
+/// tells the two apart and warns once per window when it is reached.
+struct ChecksumMonitor {
+    policy: ChecksumPolicy,
+    alert_threshold: u32,
+    window_start_ns: u64,
+    window_failures: u32,
+    log: LogThrottle,
+}
+
+impl ChecksumMonitor {
+    fn new(policy: ChecksumPolicy, alert_threshold: u32) -> Self {
+        Self {
+            policy,
+            alert_threshold,
+            window_start_ns: 0,
+            window_failures: 0,
+            log: LogThrottle::new(MALFORMED_LOG_INTERVAL_NS),
+        }
+    }
+
+    fn record_failure(&mut self, state: &mut EngineState, error: CodecError, now_ns: u64) {
+        state.checksum_failures += 1;
+        state.stats_dirty = true;
+
+        match self.policy {
+            ChecksumPolicy::Count => {
+                if let Some(suppressed) = self.log.allow(now_ns) {
+                    log_warn!(
+                        "Dropped frame: {} ({} more since last report)",
+                        error, suppressed
+                    );
+                }
+            }
+            ChecksumPolicy::Alert => {
+                log_error!("Dropped frame: {} ({} bad checksums in total)", error, state.checksum_failures);
+            }
+        }
+
+        if saturating_duration(self.window_start_ns, now_ns) >= CHECKSUM_WINDOW_NS {
+            self.window_start_ns = now_ns;
+            self.window_failures = 0;
+        }
+        self.window_failures += 1;
+        if self.window_failures == self.alert_threshold {
+            log_warn!(
+                "Checksum failure spike: {} bad frames within {} ms, likely a framing bug rather than bit flips",
+                self.window_failures,
+                CHECKSUM_WINDOW_NS / 1_000_000
+            );
+        }
+    }
+}
+
+/// Numbers every frame the engine sends, per destination, and sends each datagram
+/// behind a MSG_SEQUENCE frame giving the number of its first frame, so a consumer
+/// can tell a lost datagram from a quiet feed. Shared by the result sender and the
+/// periodic broadcasts, whose sockets are clones of one.
+#[derive(Default)]
+pub struct FrameSequencer {
+    state: Mutex<SequencerState>,
+}
+
+#[derive(Default)]
+struct SequencerState {
+    next_seq: AHashMap<SocketAddr, u64>,
+    datagram: Vec<u8>, // reused for every send
+}
+
+impl FrameSequencer {
+    /// Sends `frames`, whole frames back to back, to `addr` behind their sequence
+    /// header. The numbers are used up even when the send fails, so the loss shows
+    /// as a gap.
+    pub fn send_to(&self, socket: &UdpSocket, frames: &[u8], addr: SocketAddr) -> std::io::Result<usize> {
+        let mut state = self.state.lock().unwrap_or_else(PoisonError::into_inner);
+        let SequencerState { next_seq, datagram } = &mut *state;
+        let frame_count = (frames.len() / MESSAGE_TOTAL_SIZE) as u16;
+        let next = next_seq.entry(addr).or_insert(0);
+        let header = SequenceHeader {
+            first_seq: *next,
+            frame_count,
+        };
+        *next += frame_count as u64;
+
+        datagram.clear();
+        datagram.extend_from_slice(&message_codec::serialize_sequence_header(&header));
+        datagram.extend_from_slice(frames);
+        socket.send_to(datagram, addr)
+    }
+}
+
+/// Consumer side of `FrameSequencer`: follows the sequence headers of one sender and
+/// destination and reports the frames lost between them.
+#[cfg(test)]
+#[derive(Default)]
+pub struct SequenceGapDetector {
+    expected: Option<u64>,
+}
+
+#[cfg(test)]
+impl SequenceGapDetector {
+    /// Returns how many frames went missing before the datagram `header` leads. A
+    /// number below the expected one means the sender restarted, which is no loss.
+    pub fn observe(&mut self, header: &SequenceHeader) -> u64 {
+        let missed = self.expected.map_or(0, |expected| header.first_seq.saturating_sub(expected));
+        self.expected = Some(header.first_seq + header.frame_count as u64);
+        missed
+    }
+}
+
+/// Broadcasts match results to the multicast group.
+pub struct UdpResultSender {
+    socket: UdpSocket,
+    sequencer: Arc<FrameSequencer>,
+    multicast_addr: SocketAddr,
+    tape_aggregation: TapeAggregation,
+    mock_trade_addr: Option<SocketAddr>, // Mock executions go here, never to the trade feed
+}
+
+impl UdpResultSender {
+    fn broadcast_result(&self, result: &MatchResult, addr: SocketAddr) {
+        for batch in message_codec::serialize_match_result(result, self.tape_aggregation) {
+            if let Err(e) = self.sequencer.send_to(&self.socket, &batch, addr) {
+                log_error!("Failed to broadcast match result to {}: {}", addr, e);
+            }
+        }
+    }
+}
+
+impl ResultSender for UdpResultSender {
+    /// Real executions go to the trade feed. Mock ones would read as genuine trades
+    /// there, so they are sent to the mock trade group if one is set and dropped
+    /// otherwise.
+    fn send_result(&self, result: &MatchResult) {
+        if !result.order_execution_list.iter().any(|e| e.is_mocked_result) {
+            self.broadcast_result(result, self.multicast_addr);
+            return;
+        }
+        let (real, mocked) = result.split_mocked();
+        if !real.order_execution_list.is_empty() {
+            self.broadcast_result(&real, self.multicast_addr);
+        }
+        if let Some(addr) = self.mock_trade_addr {
+            self.broadcast_result(&mocked, addr);
+        }
+    }
+
+    fn send_ack(&self, ack: &OrderAck) {
+        let frame = message_codec::serialize_order_ack(ack);
+        if let Err(e) = self.sequencer.send_to(&self.socket, &frame, self.multicast_addr) {
+            log_error!("Failed to send order ack: {}", e);
+        }
+    }
+
+    fn send_unfilled_market(&self, unfilled: &UnfilledMarket) {
+        let frame = message_codec::serialize_unfilled_market(unfilled);
+        if let Err(e) = self.sequencer.send_to(&self.socket, &frame, self.multicast_addr) {
+            log_error!("Failed to send unfilled market notice: {}", e);
+        }
+    }
+
+    fn send_query_response(&self, response: &OrderQueryResponse) {
+        let frame = message_codec::serialize_order_query_response(response);
+        if let Err(e) = self.sequencer.send_to(&self.socket, &frame, self.multicast_addr) {
+            log_error!("Failed to send order query response: {}", e);
+        }
+    }
+
+    fn send_order_summary(&self, summary: &OrderSummary) {
+        let frame = message_codec::serialize_order_summary(summary);
+        if let Err(e) = self.sequencer.send_to(&self.socket, &frame, self.multicast_addr) {
+            log_error!("Failed to send order summary: {}", e);
+        }
+    }
+
+    fn send_cancel_ack(&self, ack: &CancelAck) {
+        let frame = message_codec::serialize_cancel_ack(ack);
+        if let Err(e) = self.sequencer.send_to(&self.socket, &frame, self.multicast_addr) {
+            log_error!("Failed to send cancel ack: {}", e);
+        }
+    }
+
+    fn send_cancel_reject(&self, reject: &CancelReject) {
+        let frame = message_codec::serialize_cancel_reject(reject);
+        if let Err(e) = self.sequencer.send_to(&self.socket, &frame, self.multicast_addr) {
+            log_error!("Failed to send cancel reject: {}", e);
+        }
+    }
+
+    fn send_whatif_response(&self, response: &WhatIfResponse) {
+        let frame = message_codec::serialize_whatif_response(response);
+        if let Err(e) = self.sequencer.send_to(&self.socket, &frame, self.multicast_addr) {
+            log_error!("Failed to send what-if response: {}", e);
+        }
+    }
+
+    fn send_book_memory(&self, report: &BookMemoryReport) {
+        let frame = message_codec::serialize_book_memory(report);
+        if let Err(e) = self.sequencer.send_to(&self.socket, &frame, self.multicast_addr) {
+            log_error!("Failed to send book memory report: {}", e);
+        }
+    }
+
+    fn send_trading_state(&self, change: &TradingStateChange) {
+        let frame = message_codec::serialize_trading_state(change);
+        if let Err(e) = self.sequencer.send_to(&self.socket, &frame, self.multicast_addr) {
+            log_error!("Failed to send trading state: {}", e);
+        }
+    }
+}
+
+/// Everything the matcher thread sends besides answers to orders: status frames,
+/// market data, session summaries and book digests. Also counts the frames the
+/// receive loop dropped, as those counters live in the matcher's state.
+struct Publisher {
+    book_states: Receiver<(u16, BookState)>,
+    socket: UdpSocket,
+    addr: SocketAddr,
+    sequencer: Arc<FrameSequencer>,
+    status_broadcaster: StatusBroadcaster,
+    checksum_monitor: ChecksumMonitor,
+    malformed_log: LogThrottle,
+    timer: HighResolutionTimer,
+    last_market_data_ns: u64,
+}
+
+impl Housekeeping for Publisher {
+    fn tick(&mut self, order_matcher: &mut OrderMatcher) {
+        for (product_id, book_state) in self.book_states.try_iter() {
+            log_info!("Book state for product {}: {:?}", product_id, book_state);
+        }
+
+        order_matcher.expire_orders(current_timestamp());
+        order_matcher.flush_audit_log_if_due();
+        order_matcher.snapshot_if_due();
+        order_matcher.compact_if_due();
+
+        let now_ns = self.timer.ns() as u64;
+        let status = self.status_broadcaster.poll(&mut order_matcher.state, now_ns);
+        for stats in &status {
+            let frame = message_codec::serialize_stats_result(stats);
+            if let Err(e) = self.sequencer.send_to(&self.socket, &frame, self.addr) {
+                log_warn!("Failed to send status frame of product {}: {}", stats.product_id, e);
+            }
+        }
+        if !status.is_empty() {
+            let frame = message_codec::serialize_status_extension(&order_matcher.state.build_status_extension());
+            if let Err(e) = self.sequencer.send_to(&self.socket, &frame, self.addr) {
+                log_warn!("Failed to send status extension frame: {}", e);
+            }
+        }
+
+        if saturating_duration(self.last_market_data_ns, now_ns) >= MARKET_DATA_INTERVAL_NS {
+            self.last_market_data_ns = now_ns;
+            for (product_id, book) in order_matcher.state.books() {
+                // one view per book, so its depth and session totals agree
+                let view = book.snapshot_view(MARKET_DATA_DEPTH);
+                let frames = message_codec::serialize_market_data(&order_matcher.state.market_data_of(product_id, &view));
+                if let Err(e) = self.sequencer.send_to(&self.socket, &frames, self.addr) {
+                    log_warn!("Failed to send market data of product {}: {}", product_id, e);
+                }
+                let frame = message_codec::serialize_session_summary(
+                    product_id,
+                    view.session.volume,
+                    view.session.turnover,
+                    view.session.ohlc,
+                );
+                if let Err(e) = self.sequencer.send_to(&self.socket, &frame, self.addr) {
+                    log_warn!("Failed to send session summary of product {}: {}", product_id, e);
+                }
+                // taken at the same instant as the market data above
+                let frame = message_codec::serialize_book_digest(&BookDigest {
+                    instance_tag: order_matcher.state.instance_tag,
+                    product_id,
+                    depth: BOOK_DIGEST_DEPTH as u8,
+                    digest: book.top_of_book_digest(BOOK_DIGEST_DEPTH),
+                });
+                if let Err(e) = self.sequencer.send_to(&self.socket, &frame, self.addr) {
+                    log_warn!("Failed to send book digest of product {}: {}", product_id, e);
+                }
+            }
+        }
+    }
+
+    fn frame_dropped(&mut self, order_matcher: &mut OrderMatcher, dropped: DroppedFrame) {
+        let now_ns = self.timer.ns() as u64;
+        match dropped {
+            DroppedFrame::BadChecksum(e) => self.checksum_monitor.record_failure(&mut order_matcher.state, e, now_ns),
+            DroppedFrame::Malformed { size, src } => {
+                order_matcher.state.malformed_frames += 1;
+                order_matcher.state.stats_dirty = true;
+
+                if let Some(suppressed) = self.malformed_log.allow(now_ns) {
+                    log_warn!(
+                        "Dropped malformed frame of {} bytes from {} ({} more since last report)",
+                        size, src, suppressed
+                    );
+                }
+            }
+        }
+    }
+}
+
+impl NetworkHandler {
+    /// Binds to the order group's port and joins it on each of `interfaces`, or on the
+    /// default interface when none are given. Broadcasts to `broadcast_addr` go out on a
+    /// socket of their own.
+    ///
+    /// A failed join is logged and the remaining interfaces are still joined; binding
+    /// only fails when no join succeeds. Outbound broadcasts leave through the first
+    /// interface that joined.
+    pub fn bind(
+        multicast_addr: SocketAddr,
+        broadcast_addr: SocketAddr,
+        interfaces: &[Ipv4Addr],
+    ) -> std::io::Result<Self> {
+        let socket = UdpSocket::bind((Ipv4Addr::UNSPECIFIED, multicast_addr.port()))?;
+        let broadcast_socket = UdpSocket::bind((Ipv4Addr::UNSPECIFIED, 0))?;
+
+        if let SocketAddr::V4(addr) = multicast_addr {
+            let interfaces = if interfaces.is_empty() { &[Ipv4Addr::UNSPECIFIED][..] } else { interfaces };
+            let mut joined = Vec::with_capacity(interfaces.len());
+            let mut last_error = None;
+            for interface in interfaces {
+                match socket.join_multicast_v4(addr.ip(), interface) {
+                    Ok(()) => joined.push(*interface),
+                    Err(e) => {
+                        log_error!("Failed to join {} on interface {}: {}", addr.ip(), interface, e);
+                        last_error = Some(e);
+                    }
+                }
+            }
+            if let Some(e) = last_error.filter(|_| joined.is_empty()) {
+                return Err(e);
+            }
+            if let Some(&outbound) = joined.first().filter(|ip| !ip.is_unspecified()) {
+                set_multicast_interface(&broadcast_socket, outbound)?;
+            }
+            log_info!("Joined {} on {:?}", addr.ip(), joined);
+        }
+        socket.set_read_timeout(Some(RECV_TIMEOUT))?;
+
+        if broadcast_addr == multicast_addr {
+            log_info!("Broadcasting on the order group {}; own frames will be heard", broadcast_addr);
+        }
+
+        Ok(Self {
+            socket,
+            multicast_addr,
+            broadcast_socket,
+            broadcast_addr,
+            sequencer: Arc::new(FrameSequencer::default()),
+            checksum_monitor: ChecksumMonitor::new(ChecksumPolicy::default(), 0),
+            tape_aggregation: TapeAggregation::default(),
+            mock_trade_addr: None,
+        })
+    }
+
+    /// Sets the hop limit and host loopback of outbound broadcasts explicitly rather
+    /// than relying on socket defaults.
+    pub fn with_multicast_options(self, ttl: u32, loopback: bool) -> std::io::Result<Self> {
+        self.broadcast_socket.set_multicast_ttl_v4(ttl)?;
+        self.broadcast_socket.set_multicast_loop_v4(loopback)?;
+        log_info!(
+            "Multicast TTL {}, loopback {}",
+            self.broadcast_socket.multicast_ttl_v4()?,
+            if self.broadcast_socket.multicast_loop_v4()? { "on" } else { "off" }
+        );
+        Ok(self)
+    }
+
+    /// Reports bad checksums per `policy`, warning when `alert_threshold` of them
+    /// arrive within a second. A threshold of 0 never warns.
+    pub fn with_checksum_policy(mut self, policy: ChecksumPolicy, alert_threshold: u32) -> Self {
+        self.checksum_monitor = ChecksumMonitor::new(policy, alert_threshold);
+        self
+    }
+
+    /// Prints the executions of each match on the trade feed per `aggregation`.
+    pub fn with_tape_aggregation(mut self, aggregation: TapeAggregation) -> Self {
+        self.tape_aggregation = aggregation;
+        self
+    }
+
+    /// Sends mock executions to `addr` instead of dropping them.
+    pub fn with_mock_trade_addr(mut self, addr: Option<SocketAddr>) -> Self {
+        self.mock_trade_addr = addr;
+        self
+    }
+
+    /// Creates a sender that broadcasts match results on this handler's broadcast group.
+    pub fn result_sender(&self) -> std::io::Result<UdpResultSender> {
+        Ok(UdpResultSender {
+            socket: self.broadcast_socket.try_clone()?,
+            sequencer: Arc::clone(&self.sequencer),
+            multicast_addr: self.broadcast_addr,
+            tape_aggregation: self.tape_aggregation,
+            mock_trade_addr: self.mock_trade_addr,
+        })
+    }
+
+    /// Runs the receive loop until receiving fails or a shutdown signal arrives,
+    /// handing each datagram's frames to the matcher, which runs on a thread of its
+    /// own pinned to `matcher_core` if given. Either way the matcher first applies
+    /// what it was handed. A failed periodic broadcast is logged and sent again next
+    /// interval; it never stops matching.
+    pub fn run(
+        self,
+        mut order_matcher: OrderMatcher,
+        status_broadcaster: StatusBroadcaster,
+        matcher_core: Option<usize>,
+    ) -> std::io::Result<()> {
+        log_info!("Listening for orders on {}", self.multicast_addr);
+
+        let publisher = Publisher {
+            book_states: order_matcher.state.subscribe_book_states(),
+            socket: self.broadcast_socket.try_clone()?,
+            addr: self.broadcast_addr,
+            sequencer: Arc::clone(&self.sequencer),
+            status_broadcaster,
+            checksum_monitor: self.checksum_monitor,
+            malformed_log: LogThrottle::new(MALFORMED_LOG_INTERVAL_NS),
+            timer: HighResolutionTimer::start(),
+            last_market_data_ns: 0,
+        };
+        let actor = MatcherActor::spawn(order_matcher, publisher, matcher_core)?;
+        let mut buf = [0u8; MAX_DATAGRAM_SIZE];
+
+        loop {
+            if shutdown_requested() {
+                log_info!("Shutting down: draining the matcher queue");
+                // whatever was received is still matched and its results sent
+                actor.shutdown(SHUTDOWN_DRAIN_TIMEOUT);
+                return Ok(());
+            }
+            let command = match self.socket.recv_from(&mut buf) {
+                // a datagram may carry several back-to-back frames, e.g. market data
+                Ok((size, _src)) if size > 0 && size % MESSAGE_TOTAL_SIZE == 0 => {
+                    let received_time = current_timestamp();
+                    let mut frames = Vec::with_capacity(size / MESSAGE_TOTAL_SIZE);
+                    for frame in buf[..size].as_chunks::<MESSAGE_TOTAL_SIZE>().0 {
+                        // a whole frame was read, so unpacking can only fail on the checksum
+                        let (message_type, version, payload) = match message_codec::unpack_message_payload(frame) {
+                            Ok(unpacked) => unpacked,
+                            Err(e) => {
+                                actor.send(MatcherCommand::Dropped(DroppedFrame::BadChecksum(e)));
+                                continue;
+                            }
+                        };
+                        if let Some(msg) = Self::process_single_message(message_type, version, payload) {
+                            frames.push((*frame, msg));
+                        }
+                    }
+                    if frames.is_empty() {
+                        continue;
+                    }
+                    MatcherCommand::Frames { frames, received_time }
+                }
+                Ok((size, src)) => MatcherCommand::Dropped(DroppedFrame::Malformed { size, src }),
+                // a shutdown signal interrupts the wait, even under SA_RESTART
+                Err(e) if matches!(e.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut | ErrorKind::Interrupted) => continue,
+                Err(e) => {
+                    actor.shutdown(SHUTDOWN_DRAIN_TIMEOUT);
+                    return Err(e);
+                }
+            };
+            if !actor.send(command) {
+                return Err(std::io::Error::other("the matcher thread stopped"));
+            }
+        }
+    }
+
+    /// Decodes one checksum-verified payload into an engine message, logging and
+    /// dropping bad payloads.
+    fn process_single_message(message_type: u8, version: u8, payload: &[u8]) -> Option<IncomingMessage> {
+        match message_type {
+            // engine output, ours or another instance's, heard on a shared group: routine
+            MSG_TRADE_BROADCAST | MSG_STATUS_BROADCAST | MSG_MARKETDATA | MSG_ORDER_ACK | MSG_UNFILLED_MARKET
+            | MSG_ORDER_QUERY_RESPONSE | MSG_ORDER_FILLED | MSG_SESSION_SUMMARY
+            | MSG_CANCEL_ACK | MSG_CANCEL_REJECT | MSG_WHATIF_RESPONSE
+            | MSG_BOOK_MEMORY | MSG_TRADING_STATE | MSG_BOOK_DIGEST | MSG_STATUS_EXTENSION | MSG_SEQUENCE => None,
+            _ => match message_codec::deserialize_message(message_type, version, payload) {
+                Ok(msg) => Some(msg),
+                Err(e) => {
+                    log_warn!("Dropping frame of message type {}: {}", message_type, e);
+                    None
+                }
+            },
+        }
+    }
+}
+
+/// Sends this socket's multicast traffic out of `interface` (`IP_MULTICAST_IF`)
+/// instead of the one the routing table picks.
+#[cfg(unix)]
+fn set_multicast_interface(socket: &UdpSocket, interface: Ipv4Addr) -> std::io::Result<()> {
+    use std::os::fd::AsRawFd;
+
+    let addr = libc::in_addr {
+        s_addr: u32::from(interface).to_be(),
+    };
+    let rc = unsafe {
+        libc::setsockopt(
+            socket.as_raw_fd(),
+            libc::IPPROTO_IP,
+            libc::IP_MULTICAST_IF,
+            &addr as *const libc::in_addr as *const libc::c_void,
+            std::mem::size_of::<libc::in_addr>() as libc::socklen_t,
+        )
+    };
+    if rc == 0 { Ok(()) } else { Err(std::io::Error::last_os_error()) }
+}
+
+#[cfg(not(unix))]
+fn set_multicast_interface(_socket: &UdpSocket, interface: Ipv4Addr) -> std::io::Result<()> {
+    log_warn!("Outbound interface {} ignored: not supported on this platform", interface);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data_types::{MSG_SEQUENCE, TradingState};
+
+    fn sequence_header(datagram: &[u8]) -> SequenceHeader {
+        let frame = datagram.first_chunk::<MESSAGE_TOTAL_SIZE>().unwrap();
+        let (message_type, _, payload) = message_codec::unpack_message_payload(frame).unwrap();
+        assert_eq!(message_type, MSG_SEQUENCE);
+        message_codec::deserialize_sequence_header(payload).unwrap()
+    }
+
+    #[test]
+    fn sequence_numbers_run_on_across_frame_types() {
+        let receiver = UdpSocket::bind((Ipv4Addr::LOCALHOST, 0)).unwrap();
+        receiver.set_read_timeout(Some(Duration::from_secs(1))).unwrap();
+        let addr = receiver.local_addr().unwrap();
+        let socket = UdpSocket::bind((Ipv4Addr::LOCALHOST, 0)).unwrap();
+        let sequencer = FrameSequencer::default();
+
+        let state = message_codec::serialize_trading_state(&TradingStateChange {
+            product_id: 7,
+            state: TradingState::Halted,
+        });
+        let sent: [&[u8]; 3] = [&state, &[state, state].concat(), &state];
+        for frames in sent {
+            sequencer.send_to(&socket, frames, addr).unwrap();
+        }
+
+        let mut detector = SequenceGapDetector::default();
+        let mut buf = [0u8; MAX_DATAGRAM_SIZE];
+        let mut first_seqs = Vec::new();
+        for frames in sent {
+            let size = receiver.recv(&mut buf).unwrap();
+            assert_eq!(size, MESSAGE_TOTAL_SIZE + frames.len());
+            assert_eq!(&buf[MESSAGE_TOTAL_SIZE..size], frames);
+            let header = sequence_header(&buf[..size]);
+            assert_eq!(detector.observe(&header), 0);
+            first_seqs.push(header.first_seq);
+        }
+        assert_eq!(first_seqs, [0, 1, 3]);
+    }
+
+    #[test]
+    fn gap_detector_counts_lost_frames_and_forgives_restarts() {
+        let mut detector = SequenceGapDetector::default();
+        let header = |first_seq, frame_count| SequenceHeader { first_seq, frame_count };
+        assert_eq!(detector.observe(&header(10, 2)), 0);
+        assert_eq!(detector.observe(&header(12, 1)), 0);
+        assert_eq!(detector.observe(&header(16, 3)), 3);
+        assert_eq!(detector.observe(&header(0, 1)), 0);
+        assert_eq!(detector.observe(&header(1, 1)), 0);
+    }
+}
@@ -1,3 +1,5 @@
+use crate::data_types::PriceRounding;
+
 /// Parses a human-readable string containing an optional size unit (k, M, G)
 /// into a u32 integer.
 ///
@@ -63,3 +65,25 @@ pub fn parse_human_readable_u32(s: &str) -> Result<u32, &'static str> {
         Ok(final_value as u32)
     }
 }
+
+/// Rounds `price` to the nearest point of the grid `origin + k * tick`. A price
+/// exactly halfway between two points goes up with `HalfUp` and to the even `k`
+/// with `HalfEven`.
+pub fn round_to_tick(price: i64, origin: i64, tick: u64, rounding: PriceRounding) -> i64 {
+    let tick = tick as i128;
+    let offset = price as i128 - origin as i128;
+    let mut k = offset.div_euclid(tick);
+    let twice_rem = 2 * offset.rem_euclid(tick);
+    let round_up = match twice_rem.cmp(&tick) {
+        std::cmp::Ordering::Greater => true,
+        std::cmp::Ordering::Less => false,
+        std::cmp::Ordering::Equal => match rounding {
+            PriceRounding::HalfUp => true,
+            PriceRounding::HalfEven => k % 2 != 0,
+        },
+    };
+    if round_up {
+        k += 1;
+    }
+    (origin as i128 + k * tick).clamp(i64::MIN as i128, i64::MAX as i128) as i64
+}
@@ -0,0 +1,951 @@
+use crate::data_types::{
+    CancelAck, CancelAtPrice, CancelReject, CancelRejectReason, EngineState, IncomingMessage, ORDER_ACK_CANCELLED, ORDER_ACK_FILLED, ORDER_ACK_PARTIALLY_FILLED, ORDER_ACK_REJECTED,
+    ORDER_ACK_RESTING,
+    ORDER_PRICE_TYPE_LIMIT, ORDER_TYPE_BUY, Order, OrderAck, OrderQuery, OrderQueryResponse, RejectReason,
+    ResultSender, UnfilledMarket, WashTradePolicy, WhatIfResponse, BOOK_ADMIN_REGISTER, BOOK_ADMIN_SHRINK, BookAdmin, BookMemoryReport,
+    TradingState, TradingStateChange, TIF_GTD, MESSAGE_TOTAL_SIZE, MatchResult,
+};
+use std::path::{Path, PathBuf};
+
+use crate::audit_log::AuditLog;
+use crate::message_codec;
+use crate::write_ahead_log::WriteAheadLog;
+use crate::date_time_tool::{current_timestamp, saturating_duration, saturating_duration_u32};
+use crate::{log_debug, log_error, log_info, log_warn};
+
+/// Handler responsible for the core order matching logic.
+///
+/// Owns the engine state so every mutation of the `ContinuousOrderBook` happens on
+/// the thread that drives the matcher.
+pub struct OrderMatcher {
+    pub state: EngineState,
+    sender: Box<dyn ResultSender>, // Sender for matched trades
+    audit_log: Option<AuditLog>,   // Compliance record of rejected orders
+    write_ahead_log: Option<WriteAheadLog>, // Inbound frames since the last snapshot
+    snapshot: Option<SnapshotSchedule>,
+    compaction: Option<CompactionSchedule>,
+}
+
+/// Stands in for the real sender while journaled frames are replayed.
+struct SilentSender;
+
+impl ResultSender for SilentSender {
+    fn send_result(&self, _result: &MatchResult) {}
+}
+
+/// How often books are checked for spare capacity left behind by a burst.
+struct CompactionSchedule {
+    interval_ns: u64,
+    last_ns: u64,
+}
+
+/// Where and how often the books are checkpointed to disk.
+struct SnapshotSchedule {
+    path: PathBuf,
+    interval_ns: u64,
+    last_ns: u64,
+}
+
+impl OrderMatcher {
+    /// Creates a new OrderMatcher.
+    pub fn new(state: EngineState, sender: Box<dyn ResultSender>) -> Self {
+        OrderMatcher {
+            state,
+            sender,
+            audit_log: None,
+            write_ahead_log: None,
+            snapshot: None,
+            compaction: None,
+        }
+    }
+
+    /// Compacts overgrown books once per `interval_ns` from now on.
+    pub fn with_compaction(mut self, interval_ns: u64) -> Self {
+        self.compaction = Some(CompactionSchedule {
+            interval_ns,
+            last_ns: current_timestamp(),
+        });
+        self
+    }
+
+    /// Compacts every book whose id map has grown well past its contents, if the
+    /// interval has passed since the last check. Called from the receive loop.
+    pub fn compact_if_due(&mut self) {
+        let Some(schedule) = self.compaction.as_mut() else {
+            return;
+        };
+        let now = current_timestamp();
+        if saturating_duration(schedule.last_ns, now) < schedule.interval_ns {
+            return;
+        }
+        schedule.last_ns = now;
+        for (product_id, book) in self.state.books_mut() {
+            if book.is_overgrown() {
+                let before = book.memory_footprint();
+                book.compact();
+                log_info!(
+                    "Book of product {} compacted from {} to {} bytes",
+                    product_id,
+                    before,
+                    book.memory_footprint()
+                );
+            }
+        }
+    }
+
+    /// Checkpoints every book to `path` once per `interval_ns` from now on.
+    pub fn with_snapshot(mut self, path: PathBuf, interval_ns: u64) -> Self {
+        self.snapshot = Some(SnapshotSchedule {
+            path,
+            interval_ns,
+            last_ns: current_timestamp(),
+        });
+        self
+    }
+
+    /// Writes a snapshot if the interval has passed since the last one. Called from
+    /// the receive loop; a failed write is logged and retried next interval.
+    pub fn snapshot_if_due(&mut self) {
+        let Some(schedule) = self.snapshot.as_mut() else {
+            return;
+        };
+        let now = current_timestamp();
+        if saturating_duration(schedule.last_ns, now) < schedule.interval_ns {
+            return;
+        }
+        schedule.last_ns = now;
+        match self.state.snapshot_to(&schedule.path) {
+            Ok(count) => log_info!("Snapshot of {} resting orders written to {}", count, schedule.path.display()),
+            Err(e) => {
+                log_error!("Failed to write snapshot to {}: {}", schedule.path.display(), e);
+                return;
+            }
+        }
+        // the snapshot covers every journaled frame, so recovery no longer needs them
+        if let Some(write_ahead_log) = self.write_ahead_log.as_mut()
+            && let Err(e) = write_ahead_log.truncate()
+        {
+            log_error!("Failed to truncate the write-ahead log: {}", e);
+        }
+    }
+
+    /// Applies the frames journaled at `path` after the state's sequence, as recovery
+    /// after a snapshot, and returns how many were applied. Results are not sent and
+    /// rejections not audited again: both already happened before the restart.
+    pub fn replay_write_ahead_log(&mut self, path: &Path) -> std::io::Result<usize> {
+        let sender = std::mem::replace(&mut self.sender, Box::new(SilentSender));
+        let audit_log = self.audit_log.take();
+        let after_sequence = self.state.sequence;
+        let replayed = WriteAheadLog::replay(path, after_sequence, |sequence, received_time, frame| {
+            self.state.sequence = sequence;
+            let msg = message_codec::unpack_message_payload(frame).and_then(|(message_type, version, payload)| {
+                message_codec::deserialize_message(message_type, version, payload)
+            });
+            match msg {
+                Ok(msg) => self.handle_message(msg, received_time),
+                Err(e) => log_warn!("Skipping journaled frame {}: {}", sequence, e),
+            }
+        });
+        self.sender = sender;
+        self.audit_log = audit_log;
+        replayed
+    }
+
+    /// Journals every applied inbound frame to `write_ahead_log` from now on.
+    pub fn with_write_ahead_log(mut self, write_ahead_log: WriteAheadLog) -> Self {
+        self.write_ahead_log = Some(write_ahead_log);
+        self
+    }
+
+    /// Journals one inbound frame under the next sequence number, ahead of applying it.
+    /// A failed write is logged; matching goes on without the frame being recoverable.
+    pub fn journal(&mut self, frame: &[u8; MESSAGE_TOTAL_SIZE], received_time: u64) {
+        let Some(write_ahead_log) = self.write_ahead_log.as_mut() else {
+            return;
+        };
+        self.state.sequence += 1;
+        if let Err(e) = write_ahead_log.append(self.state.sequence, received_time, frame) {
+            log_error!("Failed to journal frame {}: {}", self.state.sequence, e);
+        }
+    }
+
+    /// Hands the frames journaled so far to the OS. Called once per datagram, before
+    /// any of its frames is applied.
+    pub fn flush_journal(&mut self) {
+        if let Some(write_ahead_log) = self.write_ahead_log.as_mut()
+            && let Err(e) = write_ahead_log.flush()
+        {
+            log_error!("Failed to flush the write-ahead log: {}", e);
+        }
+    }
+
+    /// Records every rejected order to `audit_log` from now on.
+    pub fn with_audit_log(mut self, audit_log: AuditLog) -> Self {
+        self.audit_log = Some(audit_log);
+        self
+    }
+
+    /// Takes every GTD order due by `now` off its book. The owner gets a cancel ack, as
+    /// for DAY orders at the close. Called before each message and from the receive
+    /// loop, so expiries are announced while no orders arrive.
+    pub fn expire_orders(&mut self, now: u64) {
+        let sender = &self.sender;
+        let mut expired = 0;
+        for (product_id, book) in self.state.books_mut() {
+            let count = book.expire_orders(now, &mut |order| {
+                sender.send_cancel_ack(&CancelAck {
+                    product_id: order.product_id,
+                    order_id: order.order_id,
+                    cancelled_quantity: order.quantity,
+                });
+            });
+            if count > 0 {
+                log_debug!("{} GTD orders of product {} expired", count, product_id);
+            }
+            expired += count;
+        }
+        if expired > 0 {
+            self.state.cancelled_orders += expired as u64;
+            self.state.stats_dirty = true;
+        }
+    }
+
+    /// Gives the audit log a chance to flush while no rejections are arriving.
+    pub fn flush_audit_log_if_due(&mut self) {
+        if let Some(audit_log) = self.audit_log.as_mut() {
+            audit_log.flush_if_due();
+        }
+    }
+
+    /// Audits a rejected order and tells the client why with a rejected ack.
+    fn reject(&mut self, order: &Order, reason: RejectReason, received_time: u64) {
+        if let Some(audit_log) = self.audit_log.as_mut() {
+            audit_log.record_rejection(received_time, order.product_id, order.order_id, reason);
+        }
+        self.sender.send_ack(&OrderAck {
+            product_id: order.product_id,
+            order_id: order.order_id,
+            filled_quantity: 0,
+            resting_quantity: 0,
+            status: ORDER_ACK_REJECTED,
+            reject_reason: reason as u8,
+            client_ref: order.client_ref,
+        });
+    }
+
+    /// Dispatches one decoded message to the matching logic.
+    ///
+    /// `received_time` is the engine wall-clock time (Nanoseconds) the frame arrived.
+    pub fn handle_message(&mut self, msg: IncomingMessage, received_time: u64) {
+        // nothing may match, or be reported as resting, past its expire_time
+        self.expire_orders(received_time);
+        match msg {
+            IncomingMessage::Order(order) => self.handle_order_submission(order, received_time),
+            IncomingMessage::Cancel(cancel) => {
+                self.handle_order_cancellation(cancel.product_id, cancel.order_id)
+            }
+            IncomingMessage::CancelBatch(batch) => {
+                for order_id in batch.order_ids {
+                    self.handle_order_cancellation(batch.product_id, order_id);
+                }
+            }
+            IncomingMessage::Replace(replace) => {
+                self.handle_order_replace(replace.old_order_id, replace.order, received_time)
+            }
+            IncomingMessage::Query(query) => self.handle_order_query(query),
+            IncomingMessage::CancelAtPrice(cancel) => self.handle_cancel_at_price(cancel),
+            IncomingMessage::WhatIf(order) => self.handle_whatif(order),
+            IncomingMessage::BookAdmin(admin) => self.handle_book_admin(admin),
+            IncomingMessage::SetTradingState(change) => self.handle_trading_state(change),
+        }
+    }
+
+    /// Handles an incoming order (Limit or Market).
+    fn handle_order_submission(&mut self, new_order: Order, received_time: u64) {
+        // orders are sprayed to every shard; the others silently ignore them
+        if !self.state.owns_order(new_order.order_id) {
+            return;
+        }
+        self.process_order(new_order, None, received_time);
+    }
+
+    /// Handles a cancel/replace. Routed by the replaced order, which must live here.
+    fn handle_order_replace(&mut self, old_order_id: u64, mut new_order: Order, received_time: u64) {
+        if !self.state.owns_order(old_order_id) {
+            return;
+        }
+        if !self.state.owns_order(new_order.order_id) {
+            log_warn!(
+                "Replace rejected: Order {} would move to another shard as {}",
+                old_order_id, new_order.order_id
+            );
+            self.reject(&new_order, RejectReason::ShardMismatch, received_time);
+            return;
+        }
+        // a replace frame has no room for a reference, so the replaced order's carries over
+        if let Some(resting) = self.state.book(new_order.product_id).and_then(|book| book.get_order(old_order_id)) {
+            new_order.client_ref = resting.client_ref;
+        }
+        self.process_order(new_order, Some(old_order_id), received_time);
+    }
+
+    /// Validates, matches and reports one order, cancelling `replaces` first if given.
+    fn process_order(&mut self, mut new_order: Order, replaces: Option<u64>, received_time: u64) {
+        let engine_stamped = self.state.stamp_submit_time(&mut new_order, received_time);
+
+        // Only process orders for the configured product_id and further products
+        if !self.state.accepts_product(new_order.product_id) {
+            log_warn!(
+                "Order rejected: Product {} not configured or registered on this instance (Engine: {}, further: {:?})",
+                new_order.product_id, self.state.product_id, self.state.products
+            );
+            self.reject(&new_order, RejectReason::UnknownProduct, received_time);
+            return;
+        }
+
+        if let Some(reason) = self.state.trading_state(new_order.product_id).order_reject_reason() {
+            log_warn!(
+                "Order rejected: Product {} is {:?} (order {})",
+                new_order.product_id,
+                self.state.trading_state(new_order.product_id),
+                new_order.order_id
+            );
+            self.reject(&new_order, reason, received_time);
+            return;
+        }
+
+        if new_order.price < 0 && !self.state.allow_negative_prices {
+            log_warn!(
+                "Order rejected: Negative price {} for product {} (order {})",
+                new_order.price, new_order.product_id, new_order.order_id
+            );
+            self.reject(&new_order, RejectReason::NegativePrice, received_time);
+            return;
+        }
+
+        if new_order.time_in_force == TIF_GTD && new_order.expire_time <= received_time {
+            log_warn!(
+                "Order rejected: GTD order {} expired at {}, received at {}",
+                new_order.order_id, new_order.expire_time, received_time
+            );
+            self.reject(&new_order, RejectReason::Expired, received_time);
+            return;
+        }
+
+        let (in_range, rests_residual) = self
+            .state
+            .open_book(new_order.product_id)
+            .map_or((false, false), |book| (book.price_in_range(new_order.price), book.rests_residual(&new_order)));
+        if rests_residual && !in_range {
+            log_warn!(
+                "Order rejected: Price {} outside the book's range (order {})",
+                new_order.price, new_order.order_id
+            );
+            self.reject(&new_order, RejectReason::PriceOutOfRange, received_time);
+            return;
+        }
+
+        if self
+            .state
+            .book(new_order.product_id)
+            .is_some_and(|book| book.meets_resting_market(&new_order) && book.market_reference_price().is_none())
+        {
+            log_warn!(
+                "Order rejected: market order {} meets a resting market order with no price to trade at",
+                new_order.order_id
+            );
+            self.reject(&new_order, RejectReason::NoReferencePrice, received_time);
+            return;
+        }
+
+        if self
+            .state
+            .book(new_order.product_id)
+            .is_some_and(|book| book.breaks_price_band(&new_order))
+        {
+            log_warn!(
+                "Order rejected: order {} would trade outside the price band {:?} of product {}, halting it",
+                new_order.order_id,
+                self.state.book(new_order.product_id).and_then(|book| book.price_band()),
+                new_order.product_id
+            );
+            self.reject(&new_order, RejectReason::PriceBand, received_time);
+            self.handle_trading_state(TradingStateChange {
+                product_id: new_order.product_id,
+                state: TradingState::Halted,
+            });
+            return;
+        }
+
+        if self.state.wash_trade_policy != WashTradePolicy::Off
+            && self
+                .state
+                .book(new_order.product_id)
+                .is_some_and(|book| book.crosses_own_quote(&new_order, replaces))
+        {
+            log_warn!(
+                "Order {} of trader {} would cross the trader's own resting quote",
+                new_order.order_id, new_order.trader_id
+            );
+            if self.state.wash_trade_policy == WashTradePolicy::Reject {
+                self.reject(&new_order, RejectReason::WashTrade, received_time);
+                return;
+            }
+        }
+
+        // submit_time is stamped by the client unless the engine stamped it; a client
+        // clock ahead of ours would underflow. An engine stamp may run a few nanoseconds
+        // past the receive time to keep arrival order, which is no skew.
+        let network_time = if engine_stamped {
+            Some(0)
+        } else {
+            received_time.checked_sub(new_order.submit_time)
+        };
+        if network_time.is_none() {
+            self.state.clock_skew_count += 1;
+            self.state.stats_dirty = true;
+            if let Some(tolerance) = self.state.max_clock_skew_ns {
+                let ahead = new_order.submit_time - received_time;
+                if ahead > tolerance {
+                    log_warn!(
+                        "Order rejected: submit_time {} ns ahead of the engine clock (order {})",
+                        ahead, new_order.order_id
+                    );
+                    self.reject(&new_order, RejectReason::ClockSkew, received_time);
+                    return;
+                }
+                // a small skew keeps the order, queued as if it had just arrived
+                new_order.submit_time = received_time;
+            }
+        }
+
+        let product_id = new_order.product_id;
+        let order_id = new_order.order_id;
+        let quantity = new_order.quantity;
+        let is_limit = new_order.price_type == ORDER_PRICE_TYPE_LIMIT;
+        let client_ref = new_order.client_ref;
+
+        match replaces {
+            None => self.state.match_order(new_order),
+            Some(old_order_id) => {
+                let audit_copy = new_order.clone();
+                if let Err(reason) = self.state.replace_order(old_order_id, new_order) {
+                    log_warn!("Replace of order {} rejected: {}", old_order_id, reason.as_str());
+                    self.reject(&audit_copy, reason, received_time);
+                    return;
+                }
+            }
+        }
+
+        let lot_size = self.state.lot_size;
+        let Some(book) = self.state.book_mut(product_id) else {
+            return;
+        };
+        let result = &mut book.match_result;
+        result.receive_to_match_time = saturating_duration_u32(received_time, current_timestamp());
+        for execution in result.order_execution_list.iter_mut() {
+            execution.trade_time_network = network_time.unwrap_or(0).min(u32::MAX as u64) as u32;
+            execution.clock_skew_detected = network_time.is_none();
+        }
+
+        // self-check of debug builds: a match never leaves the book crossed
+        if cfg!(debug_assertions) && book.is_crossed() {
+            let (bid, ask) = book.best_prices();
+            log_warn!(
+                "Book of product {} {} after order {}: bid {:?} ask {:?}",
+                product_id,
+                if book.is_locked() { "locked" } else { "crossed" },
+                order_id,
+                bid,
+                ask
+            );
+        }
+
+        let result = &book.match_result;
+        if let Some(summary) = result.order_summary(lot_size) {
+            self.sender.send_result(result);
+            self.sender.send_order_summary(&summary);
+        }
+
+        let filled_quantity: u32 = result.order_execution_list.iter().map(|e| e.quantity).sum();
+        // market, IOC and FOK orders never rest their residual, nor does one the full book refused
+        let book_full = result.residual_rejected;
+        let resting_quantity = if rests_residual && !book_full { quantity - filled_quantity } else { 0 };
+        let status = if filled_quantity == quantity {
+            ORDER_ACK_FILLED
+        } else if resting_quantity == quantity {
+            ORDER_ACK_RESTING
+        } else if filled_quantity == 0 && book_full {
+            ORDER_ACK_REJECTED
+        } else if filled_quantity == 0 {
+            ORDER_ACK_CANCELLED
+        } else {
+            ORDER_ACK_PARTIALLY_FILLED
+        };
+        let evicted_order = result.evicted_order.clone();
+        self.sender.send_ack(&OrderAck {
+            product_id,
+            order_id,
+            filled_quantity,
+            resting_quantity,
+            status,
+            reject_reason: if book_full { RejectReason::BookFull as u8 } else { 0 },
+            client_ref,
+        });
+
+        if book_full {
+            log_warn!("Order {} residual rejected: book side is full", order_id);
+            if let Some(audit_log) = self.audit_log.as_mut() {
+                audit_log.record_rejection(received_time, product_id, order_id, RejectReason::BookFull);
+            }
+        }
+
+        // the evicted order's owner gets a cancelled ack carrying the reason
+        if let Some(evicted) = evicted_order {
+            self.sender.send_ack(&OrderAck {
+                product_id: evicted.product_id,
+                order_id: evicted.order_id,
+                filled_quantity: 0,
+                resting_quantity: 0,
+                status: ORDER_ACK_CANCELLED,
+                reject_reason: RejectReason::BookFull as u8,
+                client_ref: evicted.client_ref,
+            });
+        }
+
+        // a market residual is dropped rather than rested; say so instead of losing it silently
+        if !is_limit && filled_quantity < quantity {
+            self.sender.send_unfilled_market(&UnfilledMarket {
+                product_id,
+                order_id,
+                unfilled_quantity: quantity - filled_quantity,
+            });
+        }
+    }
+
+    /// Answers a query for one order's state. Read-only; the book is not touched.
+    fn handle_order_query(&mut self, query: OrderQuery) {
+        if !self.state.owns_order(query.order_id) || !self.state.accepts_product(query.product_id) {
+            return;
+        }
+        self.sender.send_query_response(&OrderQueryResponse {
+            product_id: query.product_id,
+            order_id: query.order_id,
+            view: self.state.book(query.product_id).and_then(|book| book.get_order(query.order_id)),
+        });
+    }
+
+    /// Previews the fills of an order against its product's book. Read-only; nothing
+    /// rests, trades or counts. A product without a book yet previews as unfilled.
+    fn handle_whatif(&mut self, order: Order) {
+        if !self.state.owns_order(order.order_id) || !self.state.accepts_product(order.product_id) {
+            return;
+        }
+        let (residual_quantity, executions) = match self.state.book(order.product_id) {
+            Some(book) => book.mock_match(&order),
+            None => (order.quantity, Vec::new()),
+        };
+
+        // price * quantity overflows i64 for large prices, so accumulate in i128
+        let mut notional: i128 = 0;
+        let mut filled_quantity: u32 = 0;
+        for execution in &executions {
+            notional += execution.price as i128 * execution.quantity as i128;
+            filled_quantity += execution.quantity;
+        }
+        self.sender.send_whatif_response(&WhatIfResponse {
+            product_id: order.product_id,
+            order_id: order.order_id,
+            filled_quantity,
+            residual_quantity,
+            average_price: if filled_quantity > 0 { (notional / filled_quantity as i128) as i64 } else { 0 },
+            worst_price: if order.is_buy() {
+                executions.iter().map(|e| e.price).max()
+            } else {
+                executions.iter().map(|e| e.price).min()
+            }
+            .unwrap_or(0),
+            fills: executions.len() as u32,
+        });
+    }
+
+    /// Moves a product to another trading state and announces it. Every shard applies
+    /// and announces the change.
+    ///
+    /// Reopening from pre-open or a halt first uncrosses the book in a call auction,
+    /// whose price anchors the price band when it crosses. An auction refused for its
+    /// imbalance is extended: the product keeps its state and the change is neither
+    /// applied nor announced.
+    fn handle_trading_state(&mut self, change: TradingStateChange) {
+        if !self.state.accepts_product(change.product_id) {
+            return;
+        }
+        let previous = self.state.trading_state(change.product_id);
+        if change.state == TradingState::Continuous
+            && matches!(previous, TradingState::PreOpen | TradingState::Halted)
+        {
+            match self.state.run_auction(change.product_id) {
+                Some(Err(extended)) => {
+                    log_warn!(
+                        "Product {} auction extended: {} unmatched on side {} at price {} for volume {}",
+                        change.product_id,
+                        extended.imbalance,
+                        extended.surplus_side,
+                        extended.price,
+                        extended.matched_volume
+                    );
+                    return;
+                }
+                Some(Ok((result, summary))) if summary.matched_volume > 0 => {
+                    self.sender.send_result(&result);
+                    log_info!(
+                        "Product {} auction crossed {} at price {}, {} unmatched on side {}",
+                        change.product_id,
+                        summary.matched_volume,
+                        summary.price,
+                        summary.imbalance,
+                        summary.surplus_side
+                    );
+                }
+                _ => {}
+            }
+        }
+        self.state.trading_states.insert(change.product_id, change.state);
+        log_info!("Product {} trading state {:?} -> {:?}", change.product_id, previous, change.state);
+        self.sender.send_trading_state(&change);
+
+        // the session is over: DAY orders go, each with a cancel ack to its owner
+        if change.state == TradingState::Closed
+            && previous != TradingState::Closed
+            && let Some(book) = self.state.book_mut(change.product_id)
+        {
+            let sender = &self.sender;
+            let cancelled = book.cancel_day_orders(&mut |order| {
+                sender.send_cancel_ack(&CancelAck {
+                    product_id: order.product_id,
+                    order_id: order.order_id,
+                    cancelled_quantity: order.quantity,
+                });
+            });
+            self.state.cancelled_orders += cancelled as u64;
+            log_info!("Product {} closed: {} DAY orders cancelled", change.product_id, cancelled);
+        }
+
+        // a new session starts: the closed one's OHLC stays visible until then
+        if previous == TradingState::Closed
+            && change.state != TradingState::Closed
+            && let Some(book) = self.state.book_mut(change.product_id)
+        {
+            book.reset_ohlc();
+        }
+    }
+
+    /// Reports a book's memory footprint, compacting it first when asked to shrink or
+    /// opening it when asked to register. Other products without a book yet are not answered.
+    fn handle_book_admin(&mut self, admin: BookAdmin) {
+        let instance_tag = self.state.instance_tag;
+        let book = if admin.command == BOOK_ADMIN_REGISTER {
+            log_info!("Product {} registered", admin.product_id);
+            self.state.register_product(admin.product_id)
+        } else {
+            let Some(book) = self.state.book_mut(admin.product_id) else {
+                return;
+            };
+            book
+        };
+        if admin.command == BOOK_ADMIN_SHRINK {
+            let before = book.memory_footprint();
+            book.compact();
+            log_info!(
+                "Book of product {} compacted from {} to {} bytes",
+                admin.product_id,
+                before,
+                book.memory_footprint()
+            );
+        }
+        self.sender.send_book_memory(&BookMemoryReport {
+            instance_tag,
+            product_id: admin.product_id,
+            footprint_bytes: book.memory_footprint() as u64,
+            resting_orders: book.bid_order_count + book.ask_order_count,
+        });
+    }
+
+    /// Clears one trader's orders from one price level of one side. Every shard applies
+    /// it to its own orders; each removed order gets a cancel ack. Trader 0 is anonymous
+    /// and cancels nothing, so anonymous orders can only be cancelled by id.
+    fn handle_cancel_at_price(&mut self, cancel: CancelAtPrice) {
+        if !self.state.accepts_product(cancel.product_id) {
+            log_warn!(
+                "Cancel at price {} ignored: Product {} not configured or registered on this instance",
+                cancel.price, cancel.product_id
+            );
+            return;
+        }
+        if !self.state.trading_state(cancel.product_id).accepts_cancels() {
+            log_warn!("Cancel at price {} ignored: Product {} is closed", cancel.price, cancel.product_id);
+            return;
+        }
+        if cancel.trader_id == 0 {
+            log_warn!("Cancel at price {} ignored: no trader given", cancel.price);
+            return;
+        }
+        let Some(book) = self.state.book_mut(cancel.product_id) else {
+            return;
+        };
+        let is_buy = cancel.order_type == ORDER_TYPE_BUY;
+        let sender = &self.sender;
+        let cancelled = book.cancel_at_price(is_buy, cancel.price, cancel.trader_id, &mut |order| {
+            sender.send_cancel_ack(&CancelAck {
+                product_id: order.product_id,
+                order_id: order.order_id,
+                cancelled_quantity: order.quantity,
+            });
+        });
+        self.state.cancelled_orders += cancelled as u64;
+        log_debug!(
+            "Cancelled {} {} orders of trader {} at price {}",
+            cancelled,
+            if is_buy { "bid" } else { "ask" },
+            cancel.trader_id,
+            cancel.price
+        );
+    }
+
+    /// Handles order cancellation by removing the matching order from its product's book.
+    /// The client gets a cancel ack with the quantity taken off, or a reject saying why not.
+    fn handle_order_cancellation(&mut self, product_id: u16, order_id_to_cancel: u64) {
+        if !self.state.owns_order(order_id_to_cancel) {
+            return;
+        }
+        if !self.state.trading_state(product_id).accepts_cancels() {
+            self.sender.send_cancel_reject(&CancelReject {
+                product_id,
+                order_id: order_id_to_cancel,
+                reason: CancelRejectReason::ProductClosed,
+            });
+            return;
+        }
+        if let Some(cancelled_quantity) = self
+            .state
+            .book_mut(product_id)
+            .and_then(|book| book.cancel_order(order_id_to_cancel))
+        {
+            self.state.cancelled_orders += 1;
+            log_debug!(
+                "Cancelled order {} ({} lots), {} cancels so far",
+                order_id_to_cancel, cancelled_quantity, self.state.cancelled_orders
+            );
+            self.sender.send_cancel_ack(&CancelAck {
+                product_id,
+                order_id: order_id_to_cancel,
+                cancelled_quantity,
+            });
+            return;
+        }
+
+        let reason = if !self.state.accepts_product(product_id)
+            || self.state.books().any(|(_, book)| book.order_map.contains_key(&order_id_to_cancel))
+        {
+            CancelRejectReason::WrongProduct
+        } else if self.state.book(product_id).is_some_and(|book| book.was_filled(order_id_to_cancel)) {
+            CancelRejectReason::AlreadyFilled
+        } else {
+            CancelRejectReason::NotFound
+        };
+        log_debug!("Cancel of order {} rejected: {}", order_id_to_cancel, reason.as_str());
+        self.sender.send_cancel_reject(&CancelReject {
+            product_id,
+            order_id: order_id_to_cancel,
+            reason,
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::parse_config;
+    use crate::data_types::{ORDER_PRICE_TYPE_MARKET, ORDER_TYPE_SELL, OrderExecution, TIF_GTC};
+    use std::sync::{Arc, Mutex};
+
+    const PRODUCT: u16 = 7;
+
+    /// Keeps what the matcher sends, for the tests to look at.
+    #[derive(Default)]
+    struct Recorder {
+        acks: Mutex<Vec<OrderAck>>,
+        executions: Mutex<Vec<OrderExecution>>,
+    }
+
+    impl ResultSender for Arc<Recorder> {
+        fn send_result(&self, result: &MatchResult) {
+            self.executions.lock().unwrap().extend_from_slice(&result.order_execution_list);
+        }
+
+        fn send_ack(&self, ack: &OrderAck) {
+            self.acks.lock().unwrap().push(ack.clone());
+        }
+    }
+
+    impl Recorder {
+        fn last_ack(&self) -> OrderAck {
+            self.acks.lock().unwrap().last().cloned().unwrap()
+        }
+
+        fn fills(&self) -> Vec<(u64, u64, i64, u32)> {
+            self.executions
+                .lock()
+                .unwrap()
+                .iter()
+                .map(|execution| (execution.buy_order_id, execution.sell_order_id, execution.price, execution.quantity))
+                .collect()
+        }
+    }
+
+    fn matcher(flags: &[&str]) -> OrderMatcher {
+        recording_matcher(flags).0
+    }
+
+    fn recording_matcher(flags: &[&str]) -> (OrderMatcher, Arc<Recorder>) {
+        let mut args = vec!["engine".to_string(), "--prodid".to_string(), PRODUCT.to_string()];
+        args.extend(flags.iter().map(|flag| flag.to_string()));
+        let config = parse_config(&args).unwrap();
+        let recorder = Arc::new(Recorder::default());
+        (OrderMatcher::new(EngineState::new(&config), Box::new(Arc::clone(&recorder))), recorder)
+    }
+
+    fn market(order_id: u64, order_type: u8, quantity: u32) -> Order {
+        Order {
+            price_type: ORDER_PRICE_TYPE_MARKET,
+            ..limit(order_id, order_type, 0, quantity, 0)
+        }
+    }
+
+    /// Rests a market order the way no match ever would, for market-vs-market cases. It
+    /// still needs a level to sit on.
+    fn rest_market(matcher: &mut OrderMatcher, order_id: u64, order_type: u8, quantity: u32) {
+        let order = Order {
+            price: 400_001,
+            ..market(order_id, order_type, quantity)
+        };
+        matcher.state.book_mut(PRODUCT).unwrap().fuel_order(order);
+    }
+
+    fn limit(order_id: u64, order_type: u8, price: i64, quantity: u32, submit_time: u64) -> Order {
+        Order {
+            product_id: PRODUCT,
+            order_type,
+            price_type: ORDER_PRICE_TYPE_LIMIT,
+            quantity,
+            order_id,
+            price,
+            submit_time,
+            expire_time: 0,
+            time_in_force: TIF_GTC,
+            min_qty: 0,
+            aon: false,
+            trader_id: 0,
+            client_ref: [0; 8],
+            display_quantity: 0,
+        }
+    }
+
+    fn submit_quantity(matcher: &OrderMatcher, order_id: u64) -> u32 {
+        matcher.state.book(PRODUCT).and_then(|book| book.get_order(order_id)).unwrap().remaining_quantity
+    }
+
+    fn submit_time_of(matcher: &OrderMatcher, order_id: u64) -> u64 {
+        matcher.state.book(PRODUCT).and_then(|book| book.get_order(order_id)).unwrap().submit_time
+    }
+
+    #[test]
+    fn engine_stamps_frames_of_one_datagram_in_arrival_order_without_skew() {
+        let mut matcher = matcher(&["--timestamp-source", "engine", "--max-clock-skew-ms", "5"]);
+        let received_time = 1_000_000_000;
+        // both frames of the datagram share a receive time and carry no client time
+        matcher.handle_message(IncomingMessage::Order(limit(1, ORDER_TYPE_BUY, 100_001, 10, 0)), received_time);
+        matcher.handle_message(IncomingMessage::Order(limit(2, ORDER_TYPE_BUY, 100_001, 10, 0)), received_time);
+        // a client time ahead of the engine is replaced too
+        matcher.handle_message(IncomingMessage::Order(limit(3, ORDER_TYPE_SELL, 200_001, 10, u64::MAX)), received_time);
+
+        let first = submit_time_of(&matcher, 1);
+        let second = submit_time_of(&matcher, 2);
+        let third = submit_time_of(&matcher, 3);
+        assert_eq!(first, received_time);
+        assert!(first < second && second < third);
+        assert_eq!(matcher.state.clock_skew_count, 0);
+    }
+
+    #[test]
+    fn client_time_ahead_of_the_engine_counts_as_skew() {
+        let mut matcher = matcher(&["--max-clock-skew-ms", "5"]);
+        let received_time = 1_000_000_000;
+        matcher.handle_message(IncomingMessage::Order(limit(1, ORDER_TYPE_BUY, 100_001, 10, received_time + 1)), received_time);
+
+        assert_eq!(matcher.state.clock_skew_count, 1);
+        // a skew within tolerance queues the order as if it had just arrived
+        assert_eq!(submit_time_of(&matcher, 1), received_time);
+    }
+
+    #[test]
+    fn market_against_resting_market_without_a_reference_price_is_rejected() {
+        let (mut matcher, recorder) = recording_matcher(&[]);
+        rest_market(&mut matcher, 1, ORDER_TYPE_SELL, 10);
+        matcher.handle_message(IncomingMessage::Order(market(2, ORDER_TYPE_BUY, 10)), 1);
+
+        let ack = recorder.last_ack();
+        assert_eq!((ack.order_id, ack.status), (2, ORDER_ACK_REJECTED));
+        assert_eq!(ack.reject_reason, RejectReason::NoReferencePrice as u8);
+        assert!(recorder.fills().is_empty());
+        assert_eq!(submit_quantity(&matcher, 1), 10);
+    }
+
+    #[test]
+    fn market_against_resting_market_trades_at_the_reference_price() {
+        let (mut matcher, recorder) = recording_matcher(&["--reference-price", "300001"]);
+        rest_market(&mut matcher, 1, ORDER_TYPE_SELL, 10);
+        matcher.handle_message(IncomingMessage::Order(market(2, ORDER_TYPE_BUY, 4)), 1);
+
+        assert_eq!(recorder.fills(), [(2, 1, 300_001, 4)]);
+        assert_eq!(submit_quantity(&matcher, 1), 6);
+    }
+
+    #[test]
+    fn market_against_resting_market_trades_at_the_last_trade() {
+        let (mut matcher, recorder) = recording_matcher(&["--reference-price", "300001"]);
+        matcher.handle_message(IncomingMessage::Order(limit(1, ORDER_TYPE_SELL, 200_001, 5, 0)), 1);
+        matcher.handle_message(IncomingMessage::Order(limit(2, ORDER_TYPE_BUY, 200_001, 5, 0)), 1);
+        rest_market(&mut matcher, 3, ORDER_TYPE_BUY, 10);
+        matcher.handle_message(IncomingMessage::Order(market(4, ORDER_TYPE_SELL, 10)), 1);
+
+        assert_eq!(recorder.fills(), [(2, 1, 200_001, 5), (3, 4, 200_001, 10)]);
+    }
+
+    #[test]
+    fn opening_auction_anchors_the_price_band_and_an_order_through_it_halts() {
+        let (mut matcher, recorder) = recording_matcher(&["--price-band-ticks", "2"]);
+        let set_state = |state| IncomingMessage::SetTradingState(TradingStateChange { product_id: PRODUCT, state });
+        matcher.handle_message(set_state(TradingState::PreOpen), 1);
+        matcher.state.call_auction_pool.add_order(limit(1, ORDER_TYPE_BUY, 500_001, 5, 0));
+        matcher.state.call_auction_pool.add_order(limit(2, ORDER_TYPE_SELL, 500_001, 5, 0));
+        matcher.handle_message(set_state(TradingState::Continuous), 1);
+        assert_eq!(recorder.fills(), [(1, 2, 500_001, 5)]);
+        assert_eq!(matcher.state.book(PRODUCT).and_then(|book| book.price_band()), Some((300_001, 700_001)));
+
+        // inside the band trading goes on
+        matcher.handle_message(IncomingMessage::Order(limit(3, ORDER_TYPE_SELL, 600_001, 10, 0)), 1);
+        matcher.handle_message(IncomingMessage::Order(limit(4, ORDER_TYPE_BUY, 600_001, 2, 0)), 1);
+        assert_eq!(recorder.fills().last(), Some(&(4, 3, 600_001, 2)));
+
+        // sweeping on past the band trades nothing and halts the product
+        matcher.handle_message(IncomingMessage::Order(limit(5, ORDER_TYPE_SELL, 900_001, 10, 0)), 1);
+        matcher.handle_message(IncomingMessage::Order(limit(6, ORDER_TYPE_BUY, 900_001, 10, 0)), 1);
+        let ack = recorder.last_ack();
+        assert_eq!((ack.order_id, ack.status), (6, ORDER_ACK_REJECTED));
+        assert_eq!(ack.reject_reason, RejectReason::PriceBand as u8);
+        assert_eq!(matcher.state.trading_state(PRODUCT), TradingState::Halted);
+        assert_eq!(recorder.fills().len(), 2);
+        assert_eq!(submit_quantity(&matcher, 3), 8);
+    }
+}
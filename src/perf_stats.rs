@@ -25,6 +25,12 @@ pub fn calculate_perf(mut data: Vec<u32>) -> Option<Stats> {
     })
 }
 
+/// 丢弃前 `warmup` 个样本（分配和缓存预热），只对稳态样本计算统计信息
+pub fn calculate_perf_after_warmup(mut data: Vec<u32>, warmup: usize) -> Option<Stats> {
+    data.drain(..warmup.min(data.len()));
+    calculate_perf(data)
+}
+
 /// 打印纯数据百分位表格
 pub fn print_stats_table(s: &Stats) {
     let headers = [
@@ -0,0 +1,213 @@
+use crate::data_types::{
+    ORDER_PRICE_TYPE_LIMIT, ORDER_PRICE_TYPE_MARKET, ORDER_TYPE_BUY, ORDER_TYPE_SELL, Order,
+    OrderGenParams, TIF_GTC, TestBookScenario,
+};
+use crate::date_time_tool::current_timestamp;
+
+/// Small xorshift64 generator so generated books are reproducible from a seed
+/// without pulling in an rng crate.
+#[derive(Debug, Clone)]
+pub struct XorShift64 {
+    state: u64,
+}
+
+impl XorShift64 {
+    pub fn new(seed: u64) -> Self {
+        // xorshift never leaves the all-zero state
+        Self { state: if seed == 0 { 0x9E37_79B9_7F4A_7C15 } else { seed } }
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        let mut x = self.state;
+        x ^= x << 13;
+        x ^= x >> 7;
+        x ^= x << 17;
+        self.state = x;
+        x
+    }
+
+    /// Uniform value in `low..=high`.
+    pub fn next_range(&mut self, low: u64, high: u64) -> u64 {
+        low + self.next_u64() % (high - low + 1)
+    }
+}
+
+/// Generates synthetic order books for exercising the matcher.
+pub struct TestOrderBookBuilder {
+    product_id: u16,
+}
+
+impl TestOrderBookBuilder {
+    pub fn new(product_id: u16) -> Self {
+        Self { product_id }
+    }
+
+    /// Builds the orders for `scenario`, in the order they should be submitted.
+    pub fn build(&self, scenario: TestBookScenario, size: u32) -> Vec<Order> {
+        match scenario {
+            TestBookScenario::NonCrossing => self.non_crossing(size),
+            TestBookScenario::Crossing => self.crossing(size),
+            TestBookScenario::OneSided => self.one_sided(size, true),
+            TestBookScenario::Random(seed) => self.random(size, seed),
+        }
+    }
+
+    /// Bids at `1..=size` and asks at `size+1..=2*size`; nothing matches.
+    pub fn non_crossing(&self, size: u32) -> Vec<Order> {
+        let mut orders = Vec::with_capacity(size as usize * 2);
+        for i in 0..size {
+            orders.push(self.create_order(ORDER_TYPE_BUY, (i + 1) as u64, (i + 1) as i64, 2));
+        }
+        for i in 0..size {
+            let id = (size + i + 1) as u64;
+            orders.push(self.create_order(ORDER_TYPE_SELL, id, id as i64, 2));
+        }
+        orders
+    }
+
+    /// Asks at `1..=size` followed by bids at `size+1..=2*size` of the same quantity.
+    ///
+    /// Each bid takes out exactly one ask, so feeding the orders through the matcher
+    /// yields `size` executions and leaves the book empty.
+    pub fn crossing(&self, size: u32) -> Vec<Order> {
+        let mut orders = Vec::with_capacity(size as usize * 2);
+        for i in 0..size {
+            orders.push(self.create_order(ORDER_TYPE_SELL, (i + 1) as u64, (i + 1) as i64, 2));
+        }
+        for i in 0..size {
+            let id = (size + i + 1) as u64;
+            orders.push(self.create_order(ORDER_TYPE_BUY, id, id as i64, 2));
+        }
+        orders
+    }
+
+    /// `size` resting orders on one side only, one per price level.
+    pub fn one_sided(&self, size: u32, is_buy: bool) -> Vec<Order> {
+        let order_type = if is_buy { ORDER_TYPE_BUY } else { ORDER_TYPE_SELL };
+        (0..size)
+            .map(|i| self.create_order(order_type, (i + 1) as u64, (i + 1) as i64, 2))
+            .collect()
+    }
+
+    /// `2*size` orders with random side, price in `1..=2*size` and quantity in `1..=10`.
+    pub fn random(&self, size: u32, seed: u64) -> Vec<Order> {
+        let mut rng = XorShift64::new(seed);
+        let max_price = (size as u64 * 2).max(1);
+        (0..size as u64 * 2)
+            .map(|i| {
+                let order_type = if rng.next_u64() & 1 == 0 { ORDER_TYPE_BUY } else { ORDER_TYPE_SELL };
+                let price = rng.next_range(1, max_price) as i64;
+                let quantity = rng.next_range(1, 10) as u32;
+                self.create_order(order_type, i + 1, price, quantity)
+            })
+            .collect()
+    }
+
+    fn create_order(&self, order_type: u8, order_id: u64, price: i64, quantity: u32) -> Order {
+        let time_now = current_timestamp();
+        Order {
+            product_id: self.product_id,
+            order_id,
+            order_type,
+            price_type: ORDER_PRICE_TYPE_LIMIT,
+            price,
+            quantity,
+            submit_time: time_now,
+            expire_time: time_now + 1000 * 1000 * 1000 * 1000 * 10,
+            time_in_force: TIF_GTC,
+            min_qty: 0,
+            aon: false,
+            trader_id: 0,
+            client_ref: [0; 8],
+            display_quantity: 0,
+        }
+    }
+}
+
+/// Generates `count` orders around `params.mid_price` from a seeded PRNG.
+///
+/// The same seed and params always produce the same orders, including timestamps,
+/// so benchmark and replay runs are reproducible.
+pub fn generate_orders(count: usize, seed: u64, params: &OrderGenParams) -> Vec<Order> {
+    let mut rng = XorShift64::new(seed);
+    let low_price = params.mid_price.saturating_sub(params.price_spread as i64);
+    let high_price = params.mid_price.saturating_add(params.price_spread as i64);
+    let min_quantity = params.min_quantity.max(1) as u64;
+    let max_quantity = (params.max_quantity as u64).max(min_quantity);
+
+    (0..count as u64)
+        .map(|i| {
+            let order_type = if rng.next_u64() & 1 == 0 { ORDER_TYPE_BUY } else { ORDER_TYPE_SELL };
+            let is_market = rng.next_range(1, 100) <= params.market_order_percent as u64;
+            let (price_type, price) = if is_market {
+                (ORDER_PRICE_TYPE_MARKET, 0)
+            } else {
+                let offset = rng.next_range(0, high_price.abs_diff(low_price));
+                (ORDER_PRICE_TYPE_LIMIT, low_price + offset as i64)
+            };
+            // the smaller of two draws skews sizes towards small orders
+            let quantity = rng
+                .next_range(min_quantity, max_quantity)
+                .min(rng.next_range(min_quantity, max_quantity)) as u32;
+
+            Order {
+                product_id: params.product_id,
+                order_id: params.first_order_id + i,
+                order_type,
+                price_type,
+                price,
+                quantity,
+                submit_time: i + 1,
+                expire_time: 0,
+                time_in_force: TIF_GTC,
+                min_qty: 0,
+                aon: false,
+                trader_id: 0,
+                client_ref: [0; 8],
+                display_quantity: 0,
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data_types::ContinuousOrderBook;
+
+    /// The fields a generator draws, as something comparable.
+    fn drawn(orders: &[Order]) -> Vec<(u64, u8, u8, i64, u32)> {
+        orders
+            .iter()
+            .map(|order| (order.order_id, order.order_type, order.price_type, order.price, order.quantity))
+            .collect()
+    }
+
+    #[test]
+    fn same_seed_generates_the_same_orders() {
+        let params = OrderGenParams {
+            market_order_percent: 10,
+            ..OrderGenParams::default()
+        };
+        let orders = drawn(&generate_orders(1_000, 42, &params));
+        assert_eq!(drawn(&generate_orders(1_000, 42, &params)), orders);
+        assert_ne!(drawn(&generate_orders(1_000, 43, &params)), orders);
+
+        let builder = TestOrderBookBuilder::new(1);
+        assert_eq!(drawn(&builder.random(500, 42)), drawn(&builder.random(500, 42)));
+        assert_ne!(drawn(&builder.random(500, 42)), drawn(&builder.random(500, 43)));
+    }
+
+    #[test]
+    fn crossing_scenario_fills_every_ask_once() {
+        let size = 50;
+        let mut book = ContinuousOrderBook::new(1, 0, 2 * size as usize + 1, 64);
+        let mut executions = 0;
+        for order in TestOrderBookBuilder::new(1).build(TestBookScenario::Crossing, size) {
+            book.match_order(order);
+            executions += book.match_result.order_execution_list.len();
+        }
+        assert_eq!(executions, size as usize);
+        assert_eq!(book.best_prices(), (None, None));
+    }
+}
@@ -1,19 +1,48 @@
 
-use crate::data_types::{MatchResult};
+use crate::data_types::{BroadcastStats, MatchResult};
 
 pub fn print_separator(eq_len: usize) {
     println!("\n{}\n", "=".repeat(eq_len));
 }
+
+/// Renders an instance tag for display.
+///
+/// Trailing zero padding is trimmed and the remaining bytes are shown as ASCII
+/// when they are all printable; otherwise the whole tag is rendered as hex.
+pub fn tag_to_string(tag: &[u8]) -> String {
+    let end = tag.iter().rposition(|&b| b != 0).map_or(0, |pos| pos + 1);
+    let name = &tag[..end];
+
+    if !name.is_empty() && name.iter().all(|b| b.is_ascii_graphic() || *b == b' ') {
+        return String::from_utf8_lossy(name).into_owned();
+    }
+
+    tag.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+pub fn print_status(stats: &BroadcastStats) {
+    println!(
+        "📊 STATUS [{}] product {} bids {} ({} lots) asks {} ({} lots) matched {} received {} malformed {} checksum {}",
+        tag_to_string(&stats.instance_tag),
+        stats.product_id,
+        stats.bids_order_count,
+        stats.total_bid_volumn,
+        stats.ask_order_count,
+        stats.total_ask_volumn,
+        stats.matched_orders,
+        stats.total_received_orders,
+        stats.malformed_frames,
+        stats.checksum_failures,
+    );
+}
 pub fn show_result(result: MatchResult) {
     if result.order_execution_list.is_empty() {
         return;
     }
 
-    let time_per_order_execution =
-        result.total_time() as usize / result.order_execution_list.len();
-
     // column widths
     const W_TYPE: usize = 24;
+    const W_ENGINE: usize = 16;
     const W_PRODUCT: usize = 8;
     const W_PRICE: usize = 8;
     const W_QTY: usize = 6;
@@ -22,8 +51,9 @@ pub fn show_result(result: MatchResult) {
     const W_LAT: usize = 10;
 
     let header = format!(
-        "{:<W_TYPE$} {:<W_PRODUCT$} {:<W_PRICE$} {:<W_QTY$} {:<W_BUY$} {:<W_SELL$} {:<W_LAT$}",
+        "{:<W_TYPE$} {:<W_ENGINE$} {:<W_PRODUCT$} {:<W_PRICE$} {:<W_QTY$} {:<W_BUY$} {:<W_SELL$} {:<W_LAT$}",
         "MSG Type",
+        "Engine",
         "Product",
         "Price",
         "Qty",
@@ -31,6 +61,7 @@ pub fn show_result(result: MatchResult) {
         "SellOrderID",
         "Lat(ns)",
         W_TYPE = W_TYPE,
+        W_ENGINE = W_ENGINE,
         W_PRODUCT = W_PRODUCT,
         W_PRICE = W_PRICE,
         W_QTY = W_QTY,
@@ -50,15 +81,17 @@ pub fn show_result(result: MatchResult) {
         }
 
         println!(
-            "{:<W_TYPE$} {:<W_PRODUCT$} {:<W_PRICE$} {:<W_QTY$} {:<W_BUY$} {:<W_SELL$} {:<W_LAT$}",
+            "{:<W_TYPE$} {:<W_ENGINE$} {:<W_PRODUCT$} {:<W_PRICE$} {:<W_QTY$} {:<W_BUY$} {:<W_SELL$} {:<W_LAT$}",
             "🔥 ORDER EXECUTION",
+            tag_to_string(&o.instance_tag),
             o.product_id,
             o.price,
             o.quantity,
             o.buy_order_id,
             o.sell_order_id,
-            time_per_order_execution,
+            o.internal_match_time,
             W_TYPE = W_TYPE,
+            W_ENGINE = W_ENGINE,
             W_PRODUCT = W_PRODUCT,
             W_PRICE = W_PRICE,
             W_QTY = W_QTY,
@@ -0,0 +1,83 @@
+use std::fs::{File, OpenOptions};
+use std::io::{self, BufWriter, Read, Write};
+use std::path::Path;
+
+use crate::data_types::MESSAGE_TOTAL_SIZE;
+use crate::log_warn;
+
+/// Bytes per record: sequence, receive time, then the frame as received.
+const RECORD_SIZE: usize = 8 + 8 + MESSAGE_TOTAL_SIZE;
+
+/// Journal of every inbound frame the matcher applies, written before it is applied.
+///
+/// One fixed-size record per frame: `sequence (u64) | received_time (u64) | frame`,
+/// big-endian. A snapshot records the sequence it covers and the log is truncated
+/// after it is written, so recovery loads the snapshot and replays only the tail.
+pub struct WriteAheadLog {
+    writer: BufWriter<File>,
+}
+
+impl WriteAheadLog {
+    /// Opens `path` for appending, creating it if needed. Existing records are kept.
+    pub fn open(path: &Path) -> io::Result<Self> {
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        Ok(Self {
+            writer: BufWriter::new(file),
+        })
+    }
+
+    /// Buffers one record; `flush` hands the records to the OS.
+    pub fn append(&mut self, sequence: u64, received_time: u64, frame: &[u8; MESSAGE_TOTAL_SIZE]) -> io::Result<()> {
+        self.writer.write_all(&sequence.to_be_bytes())?;
+        self.writer.write_all(&received_time.to_be_bytes())?;
+        self.writer.write_all(frame)
+    }
+
+    pub fn flush(&mut self) -> io::Result<()> {
+        self.writer.flush()
+    }
+
+    /// Drops every record, once a snapshot covering them is safely on disk.
+    pub fn truncate(&mut self) -> io::Result<()> {
+        self.writer.flush()?;
+        self.writer.get_ref().set_len(0)
+    }
+
+    /// Calls `apply` with `(sequence, received_time, frame)` for each record of `path`
+    /// after `after_sequence`, in file order, and returns how many were applied.
+    ///
+    /// A record cut short by a crash mid-write is cut off the file, so appending
+    /// resumes on a record boundary. A missing file has nothing to replay.
+    pub fn replay(
+        path: &Path,
+        after_sequence: u64,
+        mut apply: impl FnMut(u64, u64, &[u8; MESSAGE_TOTAL_SIZE]),
+    ) -> io::Result<usize> {
+        let mut file = match OpenOptions::new().read(true).write(true).open(path) {
+            Ok(file) => file,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(0),
+            Err(e) => return Err(e),
+        };
+        let mut bytes = Vec::new();
+        file.read_to_end(&mut bytes)?;
+
+        let (records, partial) = bytes.as_chunks::<RECORD_SIZE>();
+        if !partial.is_empty() {
+            log_warn!("Dropping {} bytes of a partial record at the end of the write-ahead log", partial.len());
+            file.set_len((records.len() * RECORD_SIZE) as u64)?;
+        }
+
+        let mut applied = 0;
+        for record in records {
+            let (sequence, rest) = record.split_first_chunk::<8>().unwrap();
+            let (received_time, frame) = rest.split_first_chunk::<8>().unwrap();
+            let sequence = u64::from_be_bytes(*sequence);
+            if sequence <= after_sequence {
+                continue;
+            }
+            apply(sequence, u64::from_be_bytes(*received_time), frame.try_into().unwrap());
+            applied += 1;
+        }
+        Ok(applied)
+    }
+}